@@ -1,6 +1,6 @@
-use crate::token::Token;
+use crate::token::{Span, Token};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
@@ -19,18 +19,28 @@ impl Program {
     pub fn string(&self) -> String {
         self.statements.iter().map(|s| s.string()).collect()
     }
+
+    pub fn span(&self) -> Span {
+        match (self.statements.first(), self.statements.last()) {
+            (Some(first), Some(last)) => first.span().to(last.span()),
+            _ => Span::default(),
+        }
+    }
 }
 
 pub trait Node {
     fn token_literal(&self) -> &str;
     fn string(&self) -> String;
+    fn span(&self) -> Span;
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum Statement {
     LetStatement(LetStatement),
     ReturnStatement(ReturnStatement),
     ExpressionStatement(ExpressionStatement),
+    Error(ErrorNode),
 }
 
 impl Node for Statement {
@@ -39,6 +49,7 @@ impl Node for Statement {
             Self::LetStatement(let_stmt) => let_stmt.token_literal(),
             Self::ReturnStatement(return_stmt) => return_stmt.token_literal(),
             Self::ExpressionStatement(expression_stmt) => expression_stmt.token_literal(),
+            Self::Error(error) => error.token_literal(),
         }
     }
 
@@ -47,13 +58,23 @@ impl Node for Statement {
             Self::LetStatement(let_stmt) => let_stmt.string(),
             Self::ReturnStatement(return_stmt) => return_stmt.string(),
             Self::ExpressionStatement(expression_stmt) => expression_stmt.string(),
+            Self::Error(error) => error.string(),
+        }
+    }
+
+    fn span(&self) -> Span {
+        match self {
+            Self::LetStatement(let_stmt) => let_stmt.span(),
+            Self::ReturnStatement(return_stmt) => return_stmt.span(),
+            Self::ExpressionStatement(expression_stmt) => expression_stmt.span(),
+            Self::Error(error) => error.span(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct LetStatement {
-    pub token: Token,
+    pub token: Token<'static>,
     pub name: Identifier,
     pub value: Option<Expression>,
 }
@@ -81,16 +102,21 @@ impl Node for LetStatement {
             self.value.clone().map_or("".to_string(), |v| v.string())
         )
     }
+
+    fn span(&self) -> Span {
+        let end = self.value.as_ref().map_or(self.name.span(), |v| v.span());
+        self.token.span.to(end)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ReturnStatement {
-    pub token: Token,
+    pub token: Token<'static>,
     pub return_value: Option<Expression>,
 }
 
 impl ReturnStatement {
-    pub fn new(token: Token) -> Self {
+    pub fn new(token: Token<'static>) -> Self {
         Self {
             token,
             return_value: None,
@@ -112,11 +138,17 @@ impl Node for ReturnStatement {
                 .map_or("".to_string(), |v| v.string())
         )
     }
+
+    fn span(&self) -> Span {
+        self.return_value
+            .as_ref()
+            .map_or(self.token.span, |v| self.token.span.to(v.span()))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ExpressionStatement {
-    pub token: Token,
+    pub token: Token<'static>,
     pub expression: Option<Expression>,
 }
 
@@ -130,12 +162,47 @@ impl Node for ExpressionStatement {
             .clone()
             .map_or("".to_string(), |v| v.string())
     }
+
+    fn span(&self) -> Span {
+        self.expression
+            .as_ref()
+            .map_or(self.token.span, |v| v.span())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockStatement {
+    pub token: Token<'static>,
+    pub statements: Vec<Statement>,
+    pub rbrace: Token<'static>,
+}
+
+impl Node for BlockStatement {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        self.statements.iter().map(|s| s.string()).collect()
+    }
+
+    fn span(&self) -> Span {
+        self.token.span.to(self.rbrace.span)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Expression {
     Identifier(Identifier),
     IntegerLiteral(IntegerLiteral),
+    StringLiteral(StringLiteral),
+    Boolean(Boolean),
+    Prefix(PrefixExpression),
+    Infix(InfixExpression),
+    If(IfExpression),
+    FunctionLiteral(FunctionLiteral),
+    Call(CallExpression),
+    Error(ErrorNode),
 }
 
 impl Node for Expression {
@@ -143,6 +210,14 @@ impl Node for Expression {
         match self {
             Self::Identifier(identifier) => &identifier.token.literal,
             Self::IntegerLiteral(integer_literal) => &integer_literal.token.literal,
+            Self::StringLiteral(string_literal) => string_literal.token_literal(),
+            Self::Boolean(boolean) => boolean.token_literal(),
+            Self::Prefix(prefix) => prefix.token_literal(),
+            Self::Infix(infix) => infix.token_literal(),
+            Self::If(if_expression) => if_expression.token_literal(),
+            Self::FunctionLiteral(function_literal) => function_literal.token_literal(),
+            Self::Call(call) => call.token_literal(),
+            Self::Error(error) => error.token_literal(),
         }
     }
 
@@ -150,13 +225,36 @@ impl Node for Expression {
         match self {
             Self::Identifier(identifier) => identifier.string(),
             Self::IntegerLiteral(integer_literal) => integer_literal.string(),
+            Self::StringLiteral(string_literal) => string_literal.string(),
+            Self::Boolean(boolean) => boolean.string(),
+            Self::Prefix(prefix) => prefix.string(),
+            Self::Infix(infix) => infix.string(),
+            Self::If(if_expression) => if_expression.string(),
+            Self::FunctionLiteral(function_literal) => function_literal.string(),
+            Self::Call(call) => call.string(),
+            Self::Error(error) => error.string(),
+        }
+    }
+
+    fn span(&self) -> Span {
+        match self {
+            Self::Identifier(identifier) => identifier.span(),
+            Self::IntegerLiteral(integer_literal) => integer_literal.span(),
+            Self::StringLiteral(string_literal) => string_literal.span(),
+            Self::Boolean(boolean) => boolean.span(),
+            Self::Prefix(prefix) => prefix.span(),
+            Self::Infix(infix) => infix.span(),
+            Self::If(if_expression) => if_expression.span(),
+            Self::FunctionLiteral(function_literal) => function_literal.span(),
+            Self::Call(call) => call.span(),
+            Self::Error(error) => error.span(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Identifier {
-    pub token: Token,
+    pub token: Token<'static>,
     pub value: String,
 }
 
@@ -177,11 +275,15 @@ impl Node for Identifier {
     fn string(&self) -> String {
         self.value.clone()
     }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct IntegerLiteral {
-    pub token: Token,
+    pub token: Token<'static>,
     pub value: i64,
 }
 
@@ -191,18 +293,684 @@ impl Node for IntegerLiteral {
     }
 
     fn string(&self) -> String {
-        self.token.literal.clone()
+        self.token.literal.to_string()
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StringLiteral {
+    pub token: Token<'static>,
+    pub value: String,
+}
+
+impl Node for StringLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        quote(&self.value)
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+/// Renders `value` as a string literal the lexer reads back unchanged.
+pub fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            ch if ch.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", ch as u32)),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[derive(Debug, Clone)]
+pub struct PrefixExpression {
+    pub token: Token<'static>,
+    pub operator: String,
+    pub right: Box<Expression>,
+}
+
+impl Node for PrefixExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        format!("({}{})", self.operator, self.right.string())
+    }
+
+    fn span(&self) -> Span {
+        self.token.span.to(self.right.span())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InfixExpression {
+    pub token: Token<'static>,
+    pub left: Box<Expression>,
+    pub operator: String,
+    pub right: Box<Expression>,
+}
+
+impl Node for InfixExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        format!(
+            "({} {} {})",
+            self.left.string(),
+            self.operator,
+            self.right.string()
+        )
+    }
+
+    fn span(&self) -> Span {
+        self.left.span().to(self.right.span())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Boolean {
+    pub token: Token<'static>,
+    pub value: bool,
+}
+
+impl Node for Boolean {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        self.token.literal.to_string()
+    }
+
+    fn span(&self) -> Span {
+        self.token.span
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IfExpression {
+    pub token: Token<'static>,
+    pub condition: Box<Expression>,
+    pub consequence: BlockStatement,
+    pub alternative: Option<BlockStatement>,
+}
+
+impl Node for IfExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        let mut out = format!(
+            "if{} {}",
+            self.condition.string(),
+            self.consequence.string()
+        );
+        if let Some(alternative) = &self.alternative {
+            out.push_str(&format!("else {}", alternative.string()));
+        }
+        out
+    }
+
+    fn span(&self) -> Span {
+        let end = self
+            .alternative
+            .as_ref()
+            .map_or(self.consequence.span(), |a| a.span());
+        self.token.span.to(end)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionLiteral {
+    pub token: Token<'static>,
+    pub parameters: Vec<Identifier>,
+    pub body: BlockStatement,
+}
+
+impl Node for FunctionLiteral {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        let params: Vec<String> = self.parameters.iter().map(|p| p.string()).collect();
+        format!(
+            "{}({}) {}",
+            self.token_literal(),
+            params.join(", "),
+            self.body.string()
+        )
+    }
+
+    fn span(&self) -> Span {
+        self.token.span.to(self.body.span())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CallExpression {
+    pub token: Token<'static>,
+    pub function: Box<Expression>,
+    pub arguments: Vec<Expression>,
+    pub rparen: Token<'static>,
+}
+
+impl Node for CallExpression {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        let args: Vec<String> = self.arguments.iter().map(|a| a.string()).collect();
+        format!("{}({})", self.function.string(), args.join(", "))
+    }
+
+    fn span(&self) -> Span {
+        self.function.span().to(self.rparen.span)
+    }
+}
+
+/// Placeholder for source the parser skipped while recovering from an error.
+/// `token` is the token where the failed statement or expression began and
+/// `span` covers everything that was skipped.
+#[derive(Debug, Clone)]
+pub struct ErrorNode {
+    pub token: Token<'static>,
+    pub span: Span,
+}
+
+impl Node for ErrorNode {
+    fn token_literal(&self) -> &str {
+        &self.token.literal
+    }
+
+    fn string(&self) -> String {
+        "<error>".to_string()
+    }
+
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Walks a syntax tree by reference. Every method defaults to visiting the
+/// node's children through the matching `walk_` function, so an
+/// implementation only overrides the node kinds it cares about and calls the
+/// `walk_` function itself to keep descending.
+///
+/// ```
+/// use monkey_rust::ast::{Identifier, Visitor};
+///
+/// struct Names(Vec<String>);
+///
+/// impl Visitor for Names {
+///     fn visit_identifier(&mut self, identifier: &Identifier) {
+///         self.0.push(identifier.value.clone());
+///     }
+/// }
+///
+/// let program = monkey_rust::parse("let y = fn(x) { x + z };").unwrap();
+/// let mut names = Names(Vec::new());
+/// names.visit_program(&program);
+/// assert_eq!(names.0, ["y", "x", "x", "z"]);
+/// ```
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_let_statement(&mut self, let_stmt: &LetStatement) {
+        walk_let_statement(self, let_stmt);
+    }
+
+    fn visit_return_statement(&mut self, return_stmt: &ReturnStatement) {
+        walk_return_statement(self, return_stmt);
+    }
+
+    fn visit_expression_statement(&mut self, expression_stmt: &ExpressionStatement) {
+        walk_expression_statement(self, expression_stmt);
+    }
+
+    fn visit_block_statement(&mut self, block: &BlockStatement) {
+        walk_block_statement(self, block);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+
+    fn visit_identifier(&mut self, _identifier: &Identifier) {}
+
+    fn visit_integer_literal(&mut self, _integer_literal: &IntegerLiteral) {}
+
+    fn visit_string_literal(&mut self, _string_literal: &StringLiteral) {}
+
+    fn visit_boolean(&mut self, _boolean: &Boolean) {}
+
+    fn visit_prefix_expression(&mut self, prefix: &PrefixExpression) {
+        walk_prefix_expression(self, prefix);
+    }
+
+    fn visit_infix_expression(&mut self, infix: &InfixExpression) {
+        walk_infix_expression(self, infix);
+    }
+
+    fn visit_if_expression(&mut self, if_expression: &IfExpression) {
+        walk_if_expression(self, if_expression);
+    }
+
+    fn visit_function_literal(&mut self, function_literal: &FunctionLiteral) {
+        walk_function_literal(self, function_literal);
+    }
+
+    fn visit_call_expression(&mut self, call: &CallExpression) {
+        walk_call_expression(self, call);
+    }
+
+    /// Called for both statement and expression placeholders.
+    fn visit_error_node(&mut self, _error: &ErrorNode) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for statement in &program.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::LetStatement(let_stmt) => visitor.visit_let_statement(let_stmt),
+        Statement::ReturnStatement(return_stmt) => visitor.visit_return_statement(return_stmt),
+        Statement::ExpressionStatement(expression_stmt) => {
+            visitor.visit_expression_statement(expression_stmt)
+        }
+        Statement::Error(error) => visitor.visit_error_node(error),
+    }
+}
+
+pub fn walk_let_statement<V: Visitor + ?Sized>(visitor: &mut V, let_stmt: &LetStatement) {
+    visitor.visit_identifier(&let_stmt.name);
+    if let Some(value) = &let_stmt.value {
+        visitor.visit_expression(value);
+    }
+}
+
+pub fn walk_return_statement<V: Visitor + ?Sized>(visitor: &mut V, return_stmt: &ReturnStatement) {
+    if let Some(return_value) = &return_stmt.return_value {
+        visitor.visit_expression(return_value);
+    }
+}
+
+pub fn walk_expression_statement<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    expression_stmt: &ExpressionStatement,
+) {
+    if let Some(expression) = &expression_stmt.expression {
+        visitor.visit_expression(expression);
+    }
+}
+
+pub fn walk_block_statement<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    for statement in &block.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Identifier(identifier) => visitor.visit_identifier(identifier),
+        Expression::IntegerLiteral(integer_literal) => {
+            visitor.visit_integer_literal(integer_literal)
+        }
+        Expression::StringLiteral(string_literal) => visitor.visit_string_literal(string_literal),
+        Expression::Boolean(boolean) => visitor.visit_boolean(boolean),
+        Expression::Prefix(prefix) => visitor.visit_prefix_expression(prefix),
+        Expression::Infix(infix) => visitor.visit_infix_expression(infix),
+        Expression::If(if_expression) => visitor.visit_if_expression(if_expression),
+        Expression::FunctionLiteral(function_literal) => {
+            visitor.visit_function_literal(function_literal)
+        }
+        Expression::Call(call) => visitor.visit_call_expression(call),
+        Expression::Error(error) => visitor.visit_error_node(error),
+    }
+}
+
+pub fn walk_prefix_expression<V: Visitor + ?Sized>(visitor: &mut V, prefix: &PrefixExpression) {
+    visitor.visit_expression(&prefix.right);
+}
+
+pub fn walk_infix_expression<V: Visitor + ?Sized>(visitor: &mut V, infix: &InfixExpression) {
+    visitor.visit_expression(&infix.left);
+    visitor.visit_expression(&infix.right);
+}
+
+pub fn walk_if_expression<V: Visitor + ?Sized>(visitor: &mut V, if_expression: &IfExpression) {
+    visitor.visit_expression(&if_expression.condition);
+    visitor.visit_block_statement(&if_expression.consequence);
+    if let Some(alternative) = &if_expression.alternative {
+        visitor.visit_block_statement(alternative);
+    }
+}
+
+pub fn walk_function_literal<V: Visitor + ?Sized>(
+    visitor: &mut V,
+    function_literal: &FunctionLiteral,
+) {
+    for parameter in &function_literal.parameters {
+        visitor.visit_identifier(parameter);
+    }
+    visitor.visit_block_statement(&function_literal.body);
+}
+
+pub fn walk_call_expression<V: Visitor + ?Sized>(visitor: &mut V, call: &CallExpression) {
+    visitor.visit_expression(&call.function);
+    for argument in &call.arguments {
+        visitor.visit_expression(argument);
+    }
+}
+
+/// Rebuilds a syntax tree by value. Every method defaults to folding the
+/// node's children through the matching `fold_` function and reassembling
+/// the node, so an implementation only overrides the node kinds it rewrites.
+/// `fold_statement` and `fold_expression` may replace a node with one of a
+/// different kind.
+pub trait Folder {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_program(self, program)
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        fold_statement(self, statement)
+    }
+
+    fn fold_let_statement(&mut self, let_stmt: LetStatement) -> LetStatement {
+        fold_let_statement(self, let_stmt)
+    }
+
+    fn fold_return_statement(&mut self, return_stmt: ReturnStatement) -> ReturnStatement {
+        fold_return_statement(self, return_stmt)
+    }
+
+    fn fold_expression_statement(
+        &mut self,
+        expression_stmt: ExpressionStatement,
+    ) -> ExpressionStatement {
+        fold_expression_statement(self, expression_stmt)
+    }
+
+    fn fold_block_statement(&mut self, block: BlockStatement) -> BlockStatement {
+        fold_block_statement(self, block)
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        fold_expression(self, expression)
+    }
+
+    fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+        Identifier {
+            token: self.fold_token(identifier.token),
+            ..identifier
+        }
+    }
+
+    fn fold_integer_literal(&mut self, integer_literal: IntegerLiteral) -> IntegerLiteral {
+        IntegerLiteral {
+            token: self.fold_token(integer_literal.token),
+            ..integer_literal
+        }
+    }
+
+    fn fold_string_literal(&mut self, string_literal: StringLiteral) -> StringLiteral {
+        StringLiteral {
+            token: self.fold_token(string_literal.token),
+            ..string_literal
+        }
+    }
+
+    fn fold_boolean(&mut self, boolean: Boolean) -> Boolean {
+        Boolean {
+            token: self.fold_token(boolean.token),
+            ..boolean
+        }
+    }
+
+    fn fold_prefix_expression(&mut self, prefix: PrefixExpression) -> PrefixExpression {
+        fold_prefix_expression(self, prefix)
+    }
+
+    fn fold_infix_expression(&mut self, infix: InfixExpression) -> InfixExpression {
+        fold_infix_expression(self, infix)
+    }
+
+    fn fold_if_expression(&mut self, if_expression: IfExpression) -> IfExpression {
+        fold_if_expression(self, if_expression)
+    }
+
+    fn fold_function_literal(&mut self, function_literal: FunctionLiteral) -> FunctionLiteral {
+        fold_function_literal(self, function_literal)
+    }
+
+    fn fold_call_expression(&mut self, call: CallExpression) -> CallExpression {
+        fold_call_expression(self, call)
+    }
+
+    /// Called for both statement and expression placeholders.
+    fn fold_error_node(&mut self, error: ErrorNode) -> ErrorNode {
+        ErrorNode {
+            token: self.fold_token(error.token),
+            ..error
+        }
+    }
+
+    /// Called for every token stored in the tree, including the closing
+    /// tokens of blocks and calls.
+    fn fold_token(&mut self, token: Token<'static>) -> Token<'static> {
+        token
+    }
+}
+
+pub fn fold_program<F: Folder + ?Sized>(folder: &mut F, program: Program) -> Program {
+    Program {
+        statements: program
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+    }
+}
+
+pub fn fold_statement<F: Folder + ?Sized>(folder: &mut F, statement: Statement) -> Statement {
+    match statement {
+        Statement::LetStatement(let_stmt) => {
+            Statement::LetStatement(folder.fold_let_statement(let_stmt))
+        }
+        Statement::ReturnStatement(return_stmt) => {
+            Statement::ReturnStatement(folder.fold_return_statement(return_stmt))
+        }
+        Statement::ExpressionStatement(expression_stmt) => {
+            Statement::ExpressionStatement(folder.fold_expression_statement(expression_stmt))
+        }
+        Statement::Error(error) => Statement::Error(folder.fold_error_node(error)),
+    }
+}
+
+pub fn fold_let_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    let_stmt: LetStatement,
+) -> LetStatement {
+    LetStatement {
+        token: folder.fold_token(let_stmt.token),
+        name: folder.fold_identifier(let_stmt.name),
+        value: let_stmt.value.map(|value| folder.fold_expression(value)),
+    }
+}
+
+pub fn fold_return_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    return_stmt: ReturnStatement,
+) -> ReturnStatement {
+    ReturnStatement {
+        token: folder.fold_token(return_stmt.token),
+        return_value: return_stmt
+            .return_value
+            .map(|return_value| folder.fold_expression(return_value)),
+    }
+}
+
+pub fn fold_expression_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    expression_stmt: ExpressionStatement,
+) -> ExpressionStatement {
+    ExpressionStatement {
+        token: folder.fold_token(expression_stmt.token),
+        expression: expression_stmt
+            .expression
+            .map(|expression| folder.fold_expression(expression)),
+    }
+}
+
+pub fn fold_block_statement<F: Folder + ?Sized>(
+    folder: &mut F,
+    block: BlockStatement,
+) -> BlockStatement {
+    BlockStatement {
+        token: folder.fold_token(block.token),
+        statements: block
+            .statements
+            .into_iter()
+            .map(|statement| folder.fold_statement(statement))
+            .collect(),
+        rbrace: folder.fold_token(block.rbrace),
+    }
+}
+
+pub fn fold_expression<F: Folder + ?Sized>(folder: &mut F, expression: Expression) -> Expression {
+    match expression {
+        Expression::Identifier(identifier) => {
+            Expression::Identifier(folder.fold_identifier(identifier))
+        }
+        Expression::IntegerLiteral(integer_literal) => {
+            Expression::IntegerLiteral(folder.fold_integer_literal(integer_literal))
+        }
+        Expression::StringLiteral(string_literal) => {
+            Expression::StringLiteral(folder.fold_string_literal(string_literal))
+        }
+        Expression::Boolean(boolean) => Expression::Boolean(folder.fold_boolean(boolean)),
+        Expression::Prefix(prefix) => Expression::Prefix(folder.fold_prefix_expression(prefix)),
+        Expression::Infix(infix) => Expression::Infix(folder.fold_infix_expression(infix)),
+        Expression::If(if_expression) => Expression::If(folder.fold_if_expression(if_expression)),
+        Expression::FunctionLiteral(function_literal) => {
+            Expression::FunctionLiteral(folder.fold_function_literal(function_literal))
+        }
+        Expression::Call(call) => Expression::Call(folder.fold_call_expression(call)),
+        Expression::Error(error) => Expression::Error(folder.fold_error_node(error)),
+    }
+}
+
+pub fn fold_prefix_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    prefix: PrefixExpression,
+) -> PrefixExpression {
+    PrefixExpression {
+        token: folder.fold_token(prefix.token),
+        operator: prefix.operator,
+        right: Box::new(folder.fold_expression(*prefix.right)),
+    }
+}
+
+pub fn fold_infix_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    infix: InfixExpression,
+) -> InfixExpression {
+    InfixExpression {
+        token: folder.fold_token(infix.token),
+        left: Box::new(folder.fold_expression(*infix.left)),
+        operator: infix.operator,
+        right: Box::new(folder.fold_expression(*infix.right)),
+    }
+}
+
+pub fn fold_if_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    if_expression: IfExpression,
+) -> IfExpression {
+    IfExpression {
+        token: folder.fold_token(if_expression.token),
+        condition: Box::new(folder.fold_expression(*if_expression.condition)),
+        consequence: folder.fold_block_statement(if_expression.consequence),
+        alternative: if_expression
+            .alternative
+            .map(|alternative| folder.fold_block_statement(alternative)),
+    }
+}
+
+pub fn fold_function_literal<F: Folder + ?Sized>(
+    folder: &mut F,
+    function_literal: FunctionLiteral,
+) -> FunctionLiteral {
+    FunctionLiteral {
+        token: folder.fold_token(function_literal.token),
+        parameters: function_literal
+            .parameters
+            .into_iter()
+            .map(|parameter| folder.fold_identifier(parameter))
+            .collect(),
+        body: folder.fold_block_statement(function_literal.body),
+    }
+}
+
+pub fn fold_call_expression<F: Folder + ?Sized>(
+    folder: &mut F,
+    call: CallExpression,
+) -> CallExpression {
+    CallExpression {
+        token: folder.fold_token(call.token),
+        function: Box::new(folder.fold_expression(*call.function)),
+        arguments: call
+            .arguments
+            .into_iter()
+            .map(|argument| folder.fold_expression(argument))
+            .collect(),
+        rparen: folder.fold_token(call.rparen),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::token::{
-        Token,
+        Span, Token,
         TokenType::{IDENT, LET},
     };
 
-    use super::{Expression, Identifier, LetStatement, Program, Statement};
+    use super::{
+        fold_expression, Boolean, ErrorNode, Expression, Folder, Identifier, InfixExpression,
+        IntegerLiteral, LetStatement, Node, Program, Statement, Visitor,
+    };
 
     #[test]
     fn test_string() {
@@ -210,19 +978,22 @@ mod tests {
             statements: [Statement::LetStatement(LetStatement {
                 token: Token {
                     token_type: LET,
-                    literal: "let".to_string(),
+                    literal: "let".into(),
+                    span: Span::default(),
                 },
                 name: Identifier {
                     token: Token {
                         token_type: IDENT,
-                        literal: "myVar".to_string(),
+                        literal: "myVar".into(),
+                        span: Span::default(),
                     },
                     value: "myVar".to_string(),
                 },
                 value: Some(Expression::Identifier(Identifier {
                     token: Token {
                         token_type: IDENT,
-                        literal: "anotherVar".to_string(),
+                        literal: "anotherVar".into(),
+                        span: Span::default(),
                     },
                     value: "anotherVar".to_string(),
                 })),
@@ -230,8 +1001,146 @@ mod tests {
             .to_vec(),
         };
 
-        if program.string() != "let myVar = anotherVar;".to_string() {
+        if program.string() != "let myVar = anotherVar;" {
             panic!("program.String() wrong. got={}", program.string())
         }
     }
+
+    /// Records the kind of every node in visiting order.
+    #[derive(Default)]
+    struct Kinds(Vec<String>);
+
+    impl Visitor for Kinds {
+        fn visit_statement(&mut self, statement: &Statement) {
+            self.0.push(statement.token_literal().to_string());
+            super::walk_statement(self, statement);
+        }
+
+        fn visit_identifier(&mut self, identifier: &Identifier) {
+            self.0.push(format!("ident {}", identifier.value));
+        }
+
+        fn visit_integer_literal(&mut self, integer_literal: &IntegerLiteral) {
+            self.0.push(format!("int {}", integer_literal.value));
+        }
+
+        fn visit_boolean(&mut self, boolean: &Boolean) {
+            self.0.push(format!("bool {}", boolean.value));
+        }
+
+        fn visit_infix_expression(&mut self, infix: &InfixExpression) {
+            self.0.push(format!("infix {}", infix.operator));
+            super::walk_infix_expression(self, infix);
+        }
+
+        fn visit_error_node(&mut self, _error: &ErrorNode) {
+            self.0.push("error".to_string());
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let tests = [
+            (
+                "let f = fn(x) { if (x < 1) { true } else { f(x - 1) } };",
+                vec![
+                    "let",
+                    "ident f",
+                    "ident x",
+                    "if",
+                    "infix <",
+                    "ident x",
+                    "int 1",
+                    "true",
+                    "bool true",
+                    "f",
+                    "ident f",
+                    "infix -",
+                    "ident x",
+                    "int 1",
+                ],
+            ),
+            (
+                "return -a * 2;",
+                vec!["return", "infix *", "ident a", "int 2"],
+            ),
+            ("let = 1; 2 + ;", vec!["let", "error", "2", "error"]),
+        ];
+
+        for (input, expected) in tests {
+            let (program, _) = crate::parse_with_recovery(input);
+            let mut kinds = Kinds::default();
+            kinds.visit_program(&program);
+            if kinds.0 != expected {
+                panic!(
+                    "visit order wrong for {:?}.\nwant={:?}\ngot= {:?}",
+                    input, expected, kinds.0
+                );
+            }
+        }
+    }
+
+    /// Folds additions of two integer literals and renames `x` to `y`.
+    struct Simplify;
+
+    impl Folder for Simplify {
+        fn fold_expression(&mut self, expression: Expression) -> Expression {
+            match fold_expression(self, expression) {
+                Expression::Infix(infix) if infix.operator == "+" => {
+                    match (infix.left.as_ref(), infix.right.as_ref()) {
+                        (Expression::IntegerLiteral(left), Expression::IntegerLiteral(right)) => {
+                            let value = left.value + right.value;
+                            Expression::IntegerLiteral(IntegerLiteral {
+                                token: Token {
+                                    literal: value.to_string().into(),
+                                    span: infix.span(),
+                                    ..left.token.clone()
+                                },
+                                value,
+                            })
+                        }
+                        _ => Expression::Infix(infix),
+                    }
+                }
+                expression => expression,
+            }
+        }
+
+        fn fold_identifier(&mut self, identifier: Identifier) -> Identifier {
+            match identifier.value.as_str() {
+                "x" => Identifier {
+                    value: "y".to_string(),
+                    ..identifier
+                },
+                _ => identifier,
+            }
+        }
+    }
+
+    #[test]
+    fn test_folder() {
+        let tests = [
+            ("1 + 2 + 3", "6"),
+            ("let x = fn(x) { x + (1 + 1) };", "let y = fn(y) (y + 2);"),
+            (
+                "if (true) { 1 + 2 } else { f(x, 2 + 2) }",
+                "iftrue 3else f(y, 4)",
+            ),
+            ("return -(1 + 1) * x;", "return ((-2) * y);"),
+            ("let = 1 + 1; 1 + 1", "<error>2"),
+        ];
+
+        for (input, expected) in tests {
+            let (program, _) = crate::parse_with_recovery(input);
+            let folded = Simplify.fold_program(program);
+            if folded.string() != expected {
+                panic!(
+                    "fold wrong for {:?}. want={:?}, got={:?}",
+                    input,
+                    expected,
+                    folded.string()
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,12 @@
+use std::{io, process::ExitCode};
+
+fn main() -> ExitCode {
+    match monkey_rust::lsp::run(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("monkey-lsp: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
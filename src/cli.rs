@@ -0,0 +1,345 @@
+use std::{fs, io, process::ExitCode};
+
+use monkey_rust::{
+    diagnostic::{ColorMode, Diagnostic},
+    evaluator::eval,
+    fmt,
+    lexer::Lexer,
+    object::Environment,
+    parse, serialize, ParseError,
+};
+
+const USAGE: &str = "usage:
+    monkey                      start the REPL
+    monkey run <file>           evaluate a script
+    monkey -e <code>            evaluate a one-liner
+    monkey tokens <file>        print the token stream
+    monkey ast [--format <string|debug|json|sexp>] <file>
+                                print the parsed program
+    monkey check <file>         parse only, exit non-zero on errors
+    monkey fmt [--check] <file>...
+                                format files in place, or with --check
+                                exit non-zero if any is not formatted
+    monkey help                 print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstFormat {
+    String,
+    Debug,
+    Json,
+    Sexp,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Repl,
+    Run(String),
+    Eval(String),
+    Tokens(String),
+    Ast { path: String, format: AstFormat },
+    Check(String),
+    Fmt { paths: Vec<String>, check: bool },
+    Help,
+}
+
+/// Parses the command line, not including the program name.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut args = args.iter().map(String::as_str);
+    let command = match args.next() {
+        None => Command::Repl,
+        Some("run") => Command::Run(expect_arg(&mut args, "run", "<file>")?),
+        Some("-e") => Command::Eval(expect_arg(&mut args, "-e", "<code>")?),
+        Some("tokens") => Command::Tokens(expect_arg(&mut args, "tokens", "<file>")?),
+        Some("ast") => {
+            let mut format = AstFormat::String;
+            let mut path = None;
+            while let Some(arg) = args.next() {
+                match arg {
+                    "--format" => {
+                        format = match expect_arg(&mut args, "--format", "<format>")?.as_str() {
+                            "string" => AstFormat::String,
+                            "debug" => AstFormat::Debug,
+                            "json" => AstFormat::Json,
+                            "sexp" => AstFormat::Sexp,
+                            other => return Err(format!("unknown ast format: {}", other)),
+                        }
+                    }
+                    _ if path.is_none() => path = Some(arg.to_string()),
+                    _ => return Err(format!("unexpected argument: {}", arg)),
+                }
+            }
+            match path {
+                Some(path) => Command::Ast { path, format },
+                None => return Err("ast expects <file>".to_string()),
+            }
+        }
+        Some("check") => Command::Check(expect_arg(&mut args, "check", "<file>")?),
+        Some("fmt") => {
+            let mut check = false;
+            let mut paths = Vec::new();
+            for arg in args.by_ref() {
+                match arg {
+                    "--check" => check = true,
+                    _ => paths.push(arg.to_string()),
+                }
+            }
+            if paths.is_empty() {
+                return Err("fmt expects <file>".to_string());
+            }
+            Command::Fmt { paths, check }
+        }
+        Some("help" | "-h" | "--help") => Command::Help,
+        Some(other) => return Err(format!("unknown command: {}", other)),
+    };
+
+    match args.next() {
+        Some(arg) => Err(format!("unexpected argument: {}", arg)),
+        None => Ok(command),
+    }
+}
+
+fn expect_arg<'a>(
+    args: &mut impl Iterator<Item = &'a str>,
+    command: &str,
+    name: &str,
+) -> Result<String, String> {
+    args.next()
+        .map(str::to_string)
+        .ok_or_else(|| format!("{} expects {}", command, name))
+}
+
+/// Runs `command`, reporting problems on stderr.
+pub fn execute(command: Command) -> ExitCode {
+    match command {
+        Command::Repl => {
+            crate::repl::start();
+            ExitCode::SUCCESS
+        }
+        Command::Run(path) => read_source(&path).map_or_else(|code| code, |s| run(&path, &s)),
+        Command::Eval(code) => run("-e", &code),
+        Command::Tokens(path) => read_source(&path).map_or_else(|code| code, |s| tokens(&path, &s)),
+        Command::Ast { path, format } => {
+            read_source(&path).map_or_else(|code| code, |s| ast(&path, &s, format))
+        }
+        Command::Check(path) => read_source(&path).map_or_else(|code| code, |s| check(&path, &s)),
+        Command::Fmt { paths, check } => format_files(&paths, check),
+        Command::Help => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+/// Prints the usage message after a command line error.
+pub fn usage_error(message: &str) -> ExitCode {
+    eprintln!("monkey: {}", message);
+    eprintln!("{}", USAGE);
+    ExitCode::from(2)
+}
+
+fn read_source(path: &str) -> Result<String, ExitCode> {
+    fs::read_to_string(path).map_err(|err| {
+        eprintln!("monkey: cannot read {}: {}", path, err);
+        ExitCode::from(2)
+    })
+}
+
+fn run(path: &str, source: &str) -> ExitCode {
+    let program = match parse(source) {
+        Ok(program) => program,
+        Err(errors) => return report_parse_errors(path, source, &errors),
+    };
+
+    match eval(&program, &Environment::new()) {
+        Some(evaluated) if evaluated.is_error() => {
+            eprintln!("{}", evaluated.inspect());
+            ExitCode::FAILURE
+        }
+        Some(evaluated) => {
+            println!("{}", evaluated.inspect());
+            ExitCode::SUCCESS
+        }
+        None => ExitCode::SUCCESS,
+    }
+}
+
+fn tokens(path: &str, source: &str) -> ExitCode {
+    let mut l = Lexer::new(source);
+    for tok in l.by_ref() {
+        println!("token_type:{:?} literal:{}", tok.token_type, tok.literal);
+    }
+
+    let errors = l.take_errors();
+    if errors.is_empty() {
+        return ExitCode::SUCCESS;
+    }
+    let color = ColorMode::detect(&io::stderr());
+    for err in &errors {
+        eprint!("{}", Diagnostic::from(err).render(path, source, color));
+    }
+    ExitCode::FAILURE
+}
+
+fn ast(path: &str, source: &str, format: AstFormat) -> ExitCode {
+    match parse(source) {
+        Ok(program) => {
+            match format {
+                AstFormat::String => println!("{}", program.string()),
+                AstFormat::Debug => println!("{:#?}", program),
+                AstFormat::Json => println!("{}", serialize::to_json(&program)),
+                AstFormat::Sexp => println!("{}", serialize::to_sexp(&program)),
+            }
+            ExitCode::SUCCESS
+        }
+        Err(errors) => report_parse_errors(path, source, &errors),
+    }
+}
+
+fn check(path: &str, source: &str) -> ExitCode {
+    match parse(source) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(errors) => report_parse_errors(path, source, &errors),
+    }
+}
+
+/// Formats each of `paths` in place, or with `check` only reports the files
+/// that are not formatted. Keeps going past failing files; a file that cannot
+/// be read or written takes precedence over one that is invalid or unformatted
+/// in the exit status.
+fn format_files(paths: &[String], check: bool) -> ExitCode {
+    let mut io_failed = false;
+    let mut failed = false;
+    for path in paths {
+        let source = match read_source(path) {
+            Ok(source) => source,
+            Err(_) => {
+                io_failed = true;
+                continue;
+            }
+        };
+        let formatted = match fmt::format(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                report_parse_errors(path, &source, &errors);
+                failed = true;
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+        if check {
+            eprintln!("monkey: {} is not formatted", path);
+            failed = true;
+        } else if let Err(err) = fs::write(path, formatted) {
+            eprintln!("monkey: cannot write {}: {}", path, err);
+            io_failed = true;
+        }
+    }
+
+    if io_failed {
+        ExitCode::from(2)
+    } else if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn report_parse_errors(path: &str, source: &str, errors: &[ParseError]) -> ExitCode {
+    let color = ColorMode::detect(&io::stderr());
+    for err in errors {
+        eprint!("{}", Diagnostic::from(err).render(path, source, color));
+    }
+    ExitCode::FAILURE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, AstFormat, Command};
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let tests = [
+            (vec![], Command::Repl),
+            (vec!["run", "a.mk"], Command::Run("a.mk".to_string())),
+            (vec!["-e", "1 + 2"], Command::Eval("1 + 2".to_string())),
+            (vec!["tokens", "a.mk"], Command::Tokens("a.mk".to_string())),
+            (
+                vec!["ast", "a.mk"],
+                Command::Ast {
+                    path: "a.mk".to_string(),
+                    format: AstFormat::String,
+                },
+            ),
+            (
+                vec!["ast", "--format", "debug", "a.mk"],
+                Command::Ast {
+                    path: "a.mk".to_string(),
+                    format: AstFormat::Debug,
+                },
+            ),
+            (
+                vec!["ast", "a.mk", "--format", "sexp"],
+                Command::Ast {
+                    path: "a.mk".to_string(),
+                    format: AstFormat::Sexp,
+                },
+            ),
+            (vec!["check", "a.mk"], Command::Check("a.mk".to_string())),
+            (
+                vec!["fmt", "a.mk", "b.mk"],
+                Command::Fmt {
+                    paths: args(&["a.mk", "b.mk"]),
+                    check: false,
+                },
+            ),
+            (
+                vec!["fmt", "--check", "a.mk"],
+                Command::Fmt {
+                    paths: args(&["a.mk"]),
+                    check: true,
+                },
+            ),
+            (vec!["--help"], Command::Help),
+        ];
+
+        for (input, expected) in tests {
+            match parse_args(&args(&input)) {
+                Ok(command) if command == expected => {}
+                result => panic!("parse_args({:?}) wrong. got={:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        let tests = [
+            (vec!["run"], "run expects <file>"),
+            (vec!["-e"], "-e expects <code>"),
+            (
+                vec!["ast", "--format", "xml", "a.mk"],
+                "unknown ast format: xml",
+            ),
+            (vec!["ast"], "ast expects <file>"),
+            (vec!["check", "a.mk", "b.mk"], "unexpected argument: b.mk"),
+            (vec!["fmt", "--check"], "fmt expects <file>"),
+            (vec!["compile"], "unknown command: compile"),
+        ];
+
+        for (input, expected) in tests {
+            match parse_args(&args(&input)) {
+                Err(message) if message == expected => {}
+                result => panic!(
+                    "parse_args({:?}) wrong. want={:?}, got={:?}",
+                    input, expected, result
+                ),
+            }
+        }
+    }
+}
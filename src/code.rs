@@ -0,0 +1,299 @@
+use std::fmt::Write;
+
+pub type Instructions = Vec<u8>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Opcode {
+    Constant,
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    True,
+    False,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    JumpNotTruthy,
+    Jump,
+    Null,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    Call,
+    ReturnValue,
+    Return,
+    Closure,
+    GetFree,
+    CurrentClosure,
+}
+
+const OPCODES: [Opcode; 27] = [
+    Opcode::Constant,
+    Opcode::Pop,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::True,
+    Opcode::False,
+    Opcode::Equal,
+    Opcode::NotEqual,
+    Opcode::GreaterThan,
+    Opcode::LessThan,
+    Opcode::Minus,
+    Opcode::Bang,
+    Opcode::JumpNotTruthy,
+    Opcode::Jump,
+    Opcode::Null,
+    Opcode::GetGlobal,
+    Opcode::SetGlobal,
+    Opcode::GetLocal,
+    Opcode::SetLocal,
+    Opcode::Call,
+    Opcode::ReturnValue,
+    Opcode::Return,
+    Opcode::Closure,
+    Opcode::GetFree,
+    Opcode::CurrentClosure,
+];
+
+/// The name of an opcode and the width in bytes of each of its operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Definition {
+    pub name: &'static str,
+    pub operand_widths: &'static [usize],
+}
+
+impl Opcode {
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    pub fn definition(self) -> Definition {
+        let (name, operand_widths): (&str, &[usize]) = match self {
+            Self::Constant => ("OpConstant", &[2]),
+            Self::Pop => ("OpPop", &[]),
+            Self::Add => ("OpAdd", &[]),
+            Self::Sub => ("OpSub", &[]),
+            Self::Mul => ("OpMul", &[]),
+            Self::Div => ("OpDiv", &[]),
+            Self::True => ("OpTrue", &[]),
+            Self::False => ("OpFalse", &[]),
+            Self::Equal => ("OpEqual", &[]),
+            Self::NotEqual => ("OpNotEqual", &[]),
+            Self::GreaterThan => ("OpGreaterThan", &[]),
+            Self::LessThan => ("OpLessThan", &[]),
+            Self::Minus => ("OpMinus", &[]),
+            Self::Bang => ("OpBang", &[]),
+            Self::JumpNotTruthy => ("OpJumpNotTruthy", &[2]),
+            Self::Jump => ("OpJump", &[2]),
+            Self::Null => ("OpNull", &[]),
+            Self::GetGlobal => ("OpGetGlobal", &[2]),
+            Self::SetGlobal => ("OpSetGlobal", &[2]),
+            Self::GetLocal => ("OpGetLocal", &[1]),
+            Self::SetLocal => ("OpSetLocal", &[1]),
+            Self::Call => ("OpCall", &[1]),
+            Self::ReturnValue => ("OpReturnValue", &[]),
+            Self::Return => ("OpReturn", &[]),
+            Self::Closure => ("OpClosure", &[2, 1]),
+            Self::GetFree => ("OpGetFree", &[1]),
+            Self::CurrentClosure => ("OpCurrentClosure", &[]),
+        };
+        Definition {
+            name,
+            operand_widths,
+        }
+    }
+}
+
+/// Encodes a single instruction. Operands are stored big-endian.
+///
+/// # Panics
+///
+/// Panics if an operand does not fit in its width; the compiler checks its
+/// operands before emitting them.
+pub fn make(op: Opcode, operands: &[usize]) -> Instructions {
+    let def = op.definition();
+
+    let instruction_len = 1 + def.operand_widths.iter().sum::<usize>();
+    let mut instruction = Vec::with_capacity(instruction_len);
+    instruction.push(op as u8);
+
+    for (operand, width) in operands.iter().zip(def.operand_widths) {
+        match width {
+            2 => {
+                let operand = u16::try_from(*operand).expect("operand does not fit in two bytes");
+                instruction.extend_from_slice(&operand.to_be_bytes());
+            }
+            1 => instruction.push(u8::try_from(*operand).expect("operand does not fit in a byte")),
+            _ => unreachable!("unsupported operand width {}", width),
+        }
+    }
+
+    instruction
+}
+
+/// Decodes the operands following an opcode, returning them together with
+/// the number of bytes read.
+pub fn read_operands(def: &Definition, ins: &[u8]) -> (Vec<usize>, usize) {
+    let mut operands = Vec::with_capacity(def.operand_widths.len());
+    let mut offset = 0;
+
+    for width in def.operand_widths {
+        match width {
+            2 => operands.push(read_u16(&ins[offset..]) as usize),
+            1 => operands.push(read_u8(&ins[offset..]) as usize),
+            _ => unreachable!("unsupported operand width {}", width),
+        }
+        offset += width;
+    }
+
+    (operands, offset)
+}
+
+pub fn read_u16(ins: &[u8]) -> u16 {
+    u16::from_be_bytes([ins[0], ins[1]])
+}
+
+pub fn read_u8(ins: &[u8]) -> u8 {
+    ins[0]
+}
+
+/// Disassembles `ins` into one line per instruction, prefixed with its
+/// offset.
+pub fn instructions_string(ins: &[u8]) -> String {
+    let mut out = String::new();
+
+    let mut i = 0;
+    while i < ins.len() {
+        let def = match Opcode::from_byte(ins[i]) {
+            Some(op) => op.definition(),
+            None => {
+                writeln!(out, "ERROR: opcode {} undefined", ins[i]).unwrap();
+                i += 1;
+                continue;
+            }
+        };
+
+        let (operands, read) = read_operands(&def, &ins[i + 1..]);
+        let operands: Vec<String> = operands.iter().map(|o| o.to_string()).collect();
+        if operands.is_empty() {
+            writeln!(out, "{:04} {}", i, def.name).unwrap();
+        } else {
+            writeln!(out, "{:04} {} {}", i, def.name, operands.join(" ")).unwrap();
+        }
+
+        i += 1 + read;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{instructions_string, make, read_operands, Opcode, OPCODES};
+
+    #[test]
+    fn test_make() {
+        let tests: [(Opcode, &[usize], &[u8]); 5] = [
+            (
+                Opcode::Constant,
+                &[65534],
+                &[Opcode::Constant as u8, 255, 254],
+            ),
+            (Opcode::Add, &[], &[Opcode::Add as u8]),
+            (Opcode::GetLocal, &[255], &[Opcode::GetLocal as u8, 255]),
+            (
+                Opcode::Closure,
+                &[65534, 255],
+                &[Opcode::Closure as u8, 255, 254, 255],
+            ),
+            (Opcode::Call, &[3], &[Opcode::Call as u8, 3]),
+        ];
+
+        for (op, operands, expected) in tests {
+            let instruction = make(op, operands);
+
+            if instruction != expected {
+                panic!(
+                    "instruction wrong for {:?}. want={:?}, got={:?}",
+                    op, expected, instruction
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_instructions_string() {
+        let instructions = [
+            make(Opcode::Add, &[]),
+            make(Opcode::GetLocal, &[1]),
+            make(Opcode::Constant, &[2]),
+            make(Opcode::Constant, &[65535]),
+            make(Opcode::Closure, &[65535, 255]),
+        ]
+        .concat();
+
+        let expected = "0000 OpAdd
+0001 OpGetLocal 1
+0003 OpConstant 2
+0006 OpConstant 65535
+0009 OpClosure 65535 255
+";
+
+        if instructions_string(&instructions) != expected {
+            panic!(
+                "instructions wrongly formatted.\nwant={:?}\ngot={:?}",
+                expected,
+                instructions_string(&instructions)
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_operands() {
+        let tests: [(Opcode, &[usize], usize); 3] = [
+            (Opcode::Constant, &[65535], 2),
+            (Opcode::GetLocal, &[255], 1),
+            (Opcode::Closure, &[65535, 255], 3),
+        ];
+
+        for (op, operands, bytes_read) in tests {
+            let instruction = make(op, operands);
+            let def = op.definition();
+
+            let (operands_read, n) = read_operands(&def, &instruction[1..]);
+            if n != bytes_read {
+                panic!("n wrong. want={}, got={}", bytes_read, n);
+            }
+            if operands_read != operands {
+                panic!(
+                    "operands wrong. want={:?}, got={:?}",
+                    operands, operands_read
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for (i, op) in OPCODES.iter().enumerate() {
+            if *op as usize != i || Opcode::from_byte(i as u8) != Some(*op) {
+                panic!(
+                    "OPCODES[{}] does not match its discriminant. got={:?}",
+                    i, op
+                );
+            }
+        }
+        if Opcode::from_byte(OPCODES.len() as u8).is_some() {
+            panic!("Opcode::from_byte accepted an undefined opcode");
+        }
+    }
+}
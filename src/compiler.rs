@@ -0,0 +1,1061 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    ast::{BlockStatement, Expression, FunctionLiteral, Identifier, Program, Statement},
+    code::{make, Instructions, Opcode},
+    object::{CompiledFunction, Object},
+    token::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolScope {
+    Global,
+    Local,
+    Free,
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub scope: SymbolScope,
+    pub index: usize,
+}
+
+/// Maps identifiers to the storage slots the VM uses for them. Each function
+/// body gets its own table enclosed by the table of the surrounding scope.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    num_definitions: usize,
+    free_symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        SymbolTable::default()
+    }
+
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..SymbolTable::default()
+        }
+    }
+
+    /// Allocates a slot for `name`. Redefining a global reuses its slot, so
+    /// functions that refer to the global see the new value, as they do in
+    /// the evaluator.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        let scope = if self.outer.is_some() {
+            SymbolScope::Local
+        } else if let Some(symbol) = self.store.get(name) {
+            return symbol.clone();
+        } else {
+            SymbolScope::Global
+        };
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope,
+            index: self.num_definitions,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        self.num_definitions += 1;
+        symbol
+    }
+
+    /// Defines the name a function is bound to inside its own body, so that
+    /// it can call itself without capturing itself as a free variable.
+    pub fn define_function_name(&mut self, name: &str) -> Symbol {
+        let symbol = Symbol {
+            name: name.to_string(),
+            scope: SymbolScope::Function,
+            index: 0,
+        };
+        self.store.insert(name.to_string(), symbol.clone());
+        symbol
+    }
+
+    fn define_free(&mut self, original: Symbol) -> Symbol {
+        let symbol = Symbol {
+            name: original.name.clone(),
+            scope: SymbolScope::Free,
+            index: self.free_symbols.len(),
+        };
+        self.free_symbols.push(original);
+        self.store.insert(symbol.name.clone(), symbol.clone());
+        symbol
+    }
+
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(symbol.clone());
+        }
+
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        if symbol.scope == SymbolScope::Global {
+            return Some(symbol);
+        }
+        Some(self.define_free(symbol))
+    }
+
+    pub fn num_definitions(&self) -> usize {
+        self.num_definitions
+    }
+
+    pub fn free_symbols(&self) -> &[Symbol] {
+        &self.free_symbols
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    UndefinedVariable { name: String, span: Span },
+    UnknownOperator { operator: String, span: Span },
+    TooManyConstants,
+    TooManyLocals { span: Span },
+    TooManyGlobals { span: Span },
+    TooManyFreeVariables { span: Span },
+    TooManyArguments { span: Span },
+    TooManyInstructions { span: Span },
+    InvalidSyntax { span: Span },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndefinedVariable { name, .. } => write!(f, "undefined variable {}", name),
+            Self::UnknownOperator { operator, .. } => write!(f, "unknown operator {}", operator),
+            Self::TooManyConstants => write!(f, "too many constants"),
+            Self::TooManyLocals { .. } => write!(f, "too many local bindings"),
+            Self::TooManyGlobals { .. } => write!(f, "too many global bindings"),
+            Self::TooManyFreeVariables { .. } => write!(f, "too many free variables"),
+            Self::TooManyArguments { .. } => write!(f, "too many arguments"),
+            Self::TooManyInstructions { .. } => write!(f, "too many instructions"),
+            Self::InvalidSyntax { .. } => write!(f, "invalid syntax"),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// The output of the compiler: the instructions of the main program and the
+/// constant pool they refer to.
+#[derive(Debug, Clone)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Object>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EmittedInstruction {
+    opcode: Opcode,
+    position: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    last_instruction: Option<EmittedInstruction>,
+    previous_instruction: Option<EmittedInstruction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Compiler {
+    constants: Vec<Object>,
+    symbol_table: SymbolTable,
+    scopes: Vec<CompilationScope>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            constants: Vec::new(),
+            symbol_table: SymbolTable::new(),
+            scopes: vec![CompilationScope::default()],
+        }
+    }
+
+    pub fn compile(&mut self, program: &Program) -> Result<(), CompileError> {
+        for statement in &program.statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    pub fn bytecode(&self) -> Bytecode {
+        Bytecode {
+            instructions: self.current_instructions().clone(),
+            constants: self.constants.clone(),
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), CompileError> {
+        match statement {
+            Statement::LetStatement(let_stmt) => {
+                // The value is compiled before the name is defined, so that
+                // in `let a = a + 1` the right-hand side sees the outer `a`.
+                // A function literal can still refer to itself through
+                // `define_function_name`.
+                match &let_stmt.value {
+                    Some(Expression::FunctionLiteral(function_literal)) => {
+                        self.compile_function_literal(function_literal, Some(&let_stmt.name))?
+                    }
+                    Some(value) => self.compile_expression(value)?,
+                    None => {
+                        self.emit(Opcode::Null, &[]);
+                    }
+                }
+
+                let symbol = self.symbol_table.define(&let_stmt.name.value);
+                let span = let_stmt.name.token.span;
+                match symbol.scope {
+                    SymbolScope::Local if symbol.index > u8::MAX as usize => {
+                        return Err(CompileError::TooManyLocals { span })
+                    }
+                    SymbolScope::Global if symbol.index > u16::MAX as usize => {
+                        return Err(CompileError::TooManyGlobals { span })
+                    }
+                    _ => {}
+                }
+
+                match symbol.scope {
+                    SymbolScope::Global => self.emit(Opcode::SetGlobal, &[symbol.index]),
+                    _ => self.emit(Opcode::SetLocal, &[symbol.index]),
+                };
+            }
+            Statement::ReturnStatement(return_stmt) => {
+                match &return_stmt.return_value {
+                    Some(value) => self.compile_expression(value)?,
+                    None => {
+                        self.emit(Opcode::Null, &[]);
+                    }
+                }
+                self.emit(Opcode::ReturnValue, &[]);
+            }
+            Statement::ExpressionStatement(expression_stmt) => {
+                if let Some(expression) = &expression_stmt.expression {
+                    self.compile_expression(expression)?;
+                    self.emit(Opcode::Pop, &[]);
+                }
+            }
+            Statement::Error(error) => {
+                return Err(CompileError::InvalidSyntax { span: error.span })
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_block_statement(&mut self, block: &BlockStatement) -> Result<(), CompileError> {
+        for statement in &block.statements {
+            self.compile_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expression: &Expression) -> Result<(), CompileError> {
+        match expression {
+            Expression::Identifier(identifier) => {
+                let symbol = match self.symbol_table.resolve(&identifier.value) {
+                    Some(symbol) => symbol,
+                    None => {
+                        return Err(CompileError::UndefinedVariable {
+                            name: identifier.value.clone(),
+                            span: identifier.token.span,
+                        })
+                    }
+                };
+                self.load_symbol(&symbol, identifier.token.span)?;
+            }
+            Expression::IntegerLiteral(integer_literal) => {
+                let index = self.add_constant(Object::Integer(integer_literal.value))?;
+                self.emit(Opcode::Constant, &[index]);
+            }
+            Expression::StringLiteral(string_literal) => {
+                let index = self.add_constant(Object::String(string_literal.value.clone()))?;
+                self.emit(Opcode::Constant, &[index]);
+            }
+            Expression::Boolean(boolean) => {
+                if boolean.value {
+                    self.emit(Opcode::True, &[]);
+                } else {
+                    self.emit(Opcode::False, &[]);
+                }
+            }
+            Expression::Prefix(prefix) => {
+                self.compile_expression(&prefix.right)?;
+
+                match prefix.operator.as_str() {
+                    "!" => self.emit(Opcode::Bang, &[]),
+                    "-" => self.emit(Opcode::Minus, &[]),
+                    operator => {
+                        return Err(CompileError::UnknownOperator {
+                            operator: operator.to_string(),
+                            span: prefix.token.span,
+                        })
+                    }
+                };
+            }
+            Expression::Infix(infix) => {
+                self.compile_expression(&infix.left)?;
+                self.compile_expression(&infix.right)?;
+
+                match infix.operator.as_str() {
+                    "+" => self.emit(Opcode::Add, &[]),
+                    "-" => self.emit(Opcode::Sub, &[]),
+                    "*" => self.emit(Opcode::Mul, &[]),
+                    "/" => self.emit(Opcode::Div, &[]),
+                    ">" => self.emit(Opcode::GreaterThan, &[]),
+                    "<" => self.emit(Opcode::LessThan, &[]),
+                    "==" => self.emit(Opcode::Equal, &[]),
+                    "!=" => self.emit(Opcode::NotEqual, &[]),
+                    operator => {
+                        return Err(CompileError::UnknownOperator {
+                            operator: operator.to_string(),
+                            span: infix.token.span,
+                        })
+                    }
+                };
+            }
+            Expression::If(if_expression) => {
+                self.compile_expression(&if_expression.condition)?;
+
+                // Emit with a bogus offset that is patched once the
+                // consequence has been compiled.
+                let jump_not_truthy_pos = self.emit(Opcode::JumpNotTruthy, &[9999]);
+
+                self.compile_block_statement(&if_expression.consequence)?;
+                if self.last_instruction_is(Opcode::Pop) {
+                    self.remove_last_pop();
+                } else {
+                    self.emit(Opcode::Null, &[]);
+                }
+
+                let jump_pos = self.emit(Opcode::Jump, &[9999]);
+
+                let span = if_expression.token.span;
+                let after_consequence_pos = self.current_instructions().len();
+                self.change_operand(jump_not_truthy_pos, after_consequence_pos, span)?;
+
+                match &if_expression.alternative {
+                    Some(alternative) => {
+                        self.compile_block_statement(alternative)?;
+                        if self.last_instruction_is(Opcode::Pop) {
+                            self.remove_last_pop();
+                        } else {
+                            self.emit(Opcode::Null, &[]);
+                        }
+                    }
+                    None => {
+                        self.emit(Opcode::Null, &[]);
+                    }
+                }
+
+                let after_alternative_pos = self.current_instructions().len();
+                self.change_operand(jump_pos, after_alternative_pos, span)?;
+            }
+            Expression::FunctionLiteral(function_literal) => {
+                self.compile_function_literal(function_literal, None)?;
+            }
+            Expression::Call(call) => {
+                self.compile_expression(&call.function)?;
+
+                for argument in &call.arguments {
+                    self.compile_expression(argument)?;
+                }
+
+                if call.arguments.len() > u8::MAX as usize {
+                    return Err(CompileError::TooManyArguments {
+                        span: call.token.span,
+                    });
+                }
+                self.emit(Opcode::Call, &[call.arguments.len()]);
+            }
+            Expression::Error(error) => {
+                return Err(CompileError::InvalidSyntax { span: error.span })
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_function_literal(
+        &mut self,
+        function_literal: &FunctionLiteral,
+        name: Option<&Identifier>,
+    ) -> Result<(), CompileError> {
+        self.enter_scope();
+
+        if let Some(name) = name {
+            self.symbol_table.define_function_name(&name.value);
+        }
+
+        for parameter in &function_literal.parameters {
+            self.symbol_table.define(&parameter.value);
+        }
+
+        self.compile_block_statement(&function_literal.body)?;
+
+        if self.last_instruction_is(Opcode::Pop) {
+            self.replace_last_pop_with_return();
+        }
+        if !self.last_instruction_is(Opcode::ReturnValue) {
+            self.emit(Opcode::Return, &[]);
+        }
+
+        let free_symbols = self.symbol_table.free_symbols().to_vec();
+        let num_locals = self.symbol_table.num_definitions();
+        if num_locals > u8::MAX as usize + 1 {
+            return Err(CompileError::TooManyLocals {
+                span: function_literal.token.span,
+            });
+        }
+        let instructions = self.leave_scope();
+
+        for symbol in &free_symbols {
+            self.load_symbol(symbol, function_literal.token.span)?;
+        }
+
+        let compiled_fn = Object::CompiledFunction(Rc::new(CompiledFunction {
+            instructions,
+            num_locals,
+            num_parameters: function_literal.parameters.len(),
+        }));
+        let index = self.add_constant(compiled_fn)?;
+        self.emit(Opcode::Closure, &[index, free_symbols.len()]);
+
+        Ok(())
+    }
+
+    fn load_symbol(&mut self, symbol: &Symbol, span: Span) -> Result<(), CompileError> {
+        match symbol.scope {
+            SymbolScope::Global => self.emit(Opcode::GetGlobal, &[symbol.index]),
+            SymbolScope::Local => self.emit(Opcode::GetLocal, &[symbol.index]),
+            // The closure records how many free variables it captures in a
+            // single byte, so the last index must be below `u8::MAX`.
+            SymbolScope::Free if symbol.index >= u8::MAX as usize => {
+                return Err(CompileError::TooManyFreeVariables { span })
+            }
+            SymbolScope::Free => self.emit(Opcode::GetFree, &[symbol.index]),
+            SymbolScope::Function => self.emit(Opcode::CurrentClosure, &[]),
+        };
+        Ok(())
+    }
+
+    fn add_constant(&mut self, obj: Object) -> Result<usize, CompileError> {
+        if self.constants.len() > u16::MAX as usize {
+            return Err(CompileError::TooManyConstants);
+        }
+        self.constants.push(obj);
+        Ok(self.constants.len() - 1)
+    }
+
+    fn emit(&mut self, op: Opcode, operands: &[usize]) -> usize {
+        let ins = make(op, operands);
+        let pos = self.add_instruction(&ins);
+
+        self.set_last_instruction(op, pos);
+
+        pos
+    }
+
+    fn add_instruction(&mut self, ins: &[u8]) -> usize {
+        let instructions = &mut self.current_scope_mut().instructions;
+        let pos_new_instruction = instructions.len();
+        instructions.extend_from_slice(ins);
+        pos_new_instruction
+    }
+
+    fn set_last_instruction(&mut self, opcode: Opcode, position: usize) {
+        let scope = self.current_scope_mut();
+        scope.previous_instruction = scope.last_instruction;
+        scope.last_instruction = Some(EmittedInstruction { opcode, position });
+    }
+
+    fn last_instruction_is(&self, op: Opcode) -> bool {
+        matches!(self.current_scope().last_instruction, Some(last) if last.opcode == op)
+    }
+
+    fn remove_last_pop(&mut self) {
+        let scope = self.current_scope_mut();
+        if let Some(last) = scope.last_instruction {
+            scope.instructions.truncate(last.position);
+            scope.last_instruction = scope.previous_instruction;
+        }
+    }
+
+    fn replace_instruction(&mut self, pos: usize, new_instruction: &[u8]) {
+        let instructions = &mut self.current_scope_mut().instructions;
+        instructions[pos..pos + new_instruction.len()].copy_from_slice(new_instruction);
+    }
+
+    fn replace_last_pop_with_return(&mut self) {
+        if let Some(last) = self.current_scope().last_instruction {
+            self.replace_instruction(last.position, &make(Opcode::ReturnValue, &[]));
+            self.current_scope_mut().last_instruction = Some(EmittedInstruction {
+                opcode: Opcode::ReturnValue,
+                position: last.position,
+            });
+        }
+    }
+
+    fn change_operand(
+        &mut self,
+        op_pos: usize,
+        operand: usize,
+        span: Span,
+    ) -> Result<(), CompileError> {
+        if operand > u16::MAX as usize {
+            return Err(CompileError::TooManyInstructions { span });
+        }
+        let op = Opcode::from_byte(self.current_instructions()[op_pos])
+            .expect("change_operand called on an invalid opcode");
+        let new_instruction = make(op, &[operand]);
+
+        self.replace_instruction(op_pos, &new_instruction);
+        Ok(())
+    }
+
+    fn current_scope(&self) -> &CompilationScope {
+        self.scopes.last().expect("compiler has no scope")
+    }
+
+    fn current_scope_mut(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().expect("compiler has no scope")
+    }
+
+    fn current_instructions(&self) -> &Instructions {
+        &self.current_scope().instructions
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        let outer = std::mem::take(&mut self.symbol_table);
+        self.symbol_table = SymbolTable::new_enclosed(outer);
+    }
+
+    fn leave_scope(&mut self) -> Instructions {
+        let scope = self.scopes.pop().expect("compiler has no scope");
+        if let Some(outer) = self.symbol_table.outer.take() {
+            self.symbol_table = *outer;
+        }
+        scope.instructions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        code::{instructions_string, make, Instructions, Opcode},
+        lexer::Lexer,
+        object::Object,
+        parser::Parser,
+    };
+
+    use super::{CompileError, Compiler, Symbol, SymbolScope, SymbolTable};
+
+    enum Constant {
+        Int(i64),
+        Str(&'static str),
+        Function(Vec<Instructions>),
+    }
+
+    fn compile(input: &str) -> Result<Compiler, CompileError> {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = match p.parse_program() {
+            Ok(program) => program,
+            Err(errors) => panic!("parser has errors for {:?}: {:?}", input, errors),
+        };
+
+        let mut compiler = Compiler::new();
+        compiler.compile(&program)?;
+        Ok(compiler)
+    }
+
+    fn run_compiler_tests(tests: Vec<(&str, Vec<Constant>, Vec<Instructions>)>) {
+        for (input, expected_constants, expected_instructions) in tests {
+            let compiler = match compile(input) {
+                Ok(compiler) => compiler,
+                Err(err) => panic!("compiler error for {:?}: {}", input, err),
+            };
+            let bytecode = compiler.bytecode();
+
+            test_instructions(input, &expected_instructions, &bytecode.instructions);
+            test_constants(input, &expected_constants, &bytecode.constants);
+        }
+    }
+
+    fn test_instructions(input: &str, expected: &[Instructions], actual: &Instructions) {
+        let concatted = expected.concat();
+        if *actual != concatted {
+            panic!(
+                "wrong instructions for {:?}.\nwant=\n{}\ngot=\n{}",
+                input,
+                instructions_string(&concatted),
+                instructions_string(actual)
+            );
+        }
+    }
+
+    fn test_constants(input: &str, expected: &[Constant], actual: &[Object]) {
+        if expected.len() != actual.len() {
+            panic!(
+                "wrong number of constants for {:?}. want={}, got={}",
+                input,
+                expected.len(),
+                actual.len()
+            );
+        }
+
+        for (i, (constant, obj)) in expected.iter().zip(actual).enumerate() {
+            match (constant, obj) {
+                (Constant::Int(want), Object::Integer(got)) if want == got => {}
+                (Constant::Str(want), Object::String(got)) if want == got => {}
+                (Constant::Function(want), Object::CompiledFunction(got)) => {
+                    test_instructions(input, want, &got.instructions)
+                }
+                (_, obj) => panic!("constant {} wrong for {:?}. got={:?}", i, input, obj),
+            }
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let tests = vec![
+            (
+                "1 + 2",
+                vec![Constant::Int(1), Constant::Int(2)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1; 2",
+                vec![Constant::Int(1), Constant::Int(2)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Pop, &[]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "2 / 1 * 3 - 4",
+                vec![
+                    Constant::Int(2),
+                    Constant::Int(1),
+                    Constant::Int(3),
+                    Constant::Int(4),
+                ],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Div, &[]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::Mul, &[]),
+                    make(Opcode::Constant, &[3]),
+                    make(Opcode::Sub, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "-1",
+                vec![Constant::Int(1)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Minus, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_boolean_expressions() {
+        let tests = vec![
+            (
+                "true",
+                vec![],
+                vec![make(Opcode::True, &[]), make(Opcode::Pop, &[])],
+            ),
+            (
+                "1 > 2",
+                vec![Constant::Int(1), Constant::Int(2)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::GreaterThan, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "1 < 2",
+                vec![Constant::Int(1), Constant::Int(2)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::LessThan, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "true != false",
+                vec![],
+                vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::False, &[]),
+                    make(Opcode::NotEqual, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "!true",
+                vec![],
+                vec![
+                    make(Opcode::True, &[]),
+                    make(Opcode::Bang, &[]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let tests = vec![
+            (
+                "if (true) { 10 }; 3333;",
+                vec![Constant::Int(10), Constant::Int(3333)],
+                vec![
+                    // 0000
+                    make(Opcode::True, &[]),
+                    // 0001
+                    make(Opcode::JumpNotTruthy, &[10]),
+                    // 0004
+                    make(Opcode::Constant, &[0]),
+                    // 0007
+                    make(Opcode::Jump, &[11]),
+                    // 0010
+                    make(Opcode::Null, &[]),
+                    // 0011
+                    make(Opcode::Pop, &[]),
+                    // 0012
+                    make(Opcode::Constant, &[1]),
+                    // 0015
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "if (true) { 10 } else { 20 }; 3333;",
+                vec![Constant::Int(10), Constant::Int(20), Constant::Int(3333)],
+                vec![
+                    // 0000
+                    make(Opcode::True, &[]),
+                    // 0001
+                    make(Opcode::JumpNotTruthy, &[10]),
+                    // 0004
+                    make(Opcode::Constant, &[0]),
+                    // 0007
+                    make(Opcode::Jump, &[13]),
+                    // 0010
+                    make(Opcode::Constant, &[1]),
+                    // 0013
+                    make(Opcode::Pop, &[]),
+                    // 0014
+                    make(Opcode::Constant, &[2]),
+                    // 0017
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let tests = vec![
+            (
+                "let one = 1; let two = 2;",
+                vec![Constant::Int(1), Constant::Int(2)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::SetGlobal, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::SetGlobal, &[1]),
+                ],
+            ),
+            (
+                "let one = 1; one;",
+                vec![Constant::Int(1)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::SetGlobal, &[0]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+            (
+                "let one = 1; let two = 2; let one = 3;",
+                vec![Constant::Int(1), Constant::Int(2), Constant::Int(3)],
+                vec![
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::SetGlobal, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::SetGlobal, &[1]),
+                    make(Opcode::Constant, &[2]),
+                    make(Opcode::SetGlobal, &[0]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_string_expressions() {
+        let tests = vec![(
+            "\"mon\" + \"key\"",
+            vec![Constant::Str("mon"), Constant::Str("key")],
+            vec![
+                make(Opcode::Constant, &[0]),
+                make(Opcode::Constant, &[1]),
+                make(Opcode::Add, &[]),
+                make(Opcode::Pop, &[]),
+            ],
+        )];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_functions() {
+        let tests = vec![
+            (
+                "fn() { return 5 + 10 }",
+                vec![
+                    Constant::Int(5),
+                    Constant::Int(10),
+                    Constant::Function(vec![
+                        make(Opcode::Constant, &[0]),
+                        make(Opcode::Constant, &[1]),
+                        make(Opcode::Add, &[]),
+                        make(Opcode::ReturnValue, &[]),
+                    ]),
+                ],
+                vec![make(Opcode::Closure, &[2, 0]), make(Opcode::Pop, &[])],
+            ),
+            (
+                "fn() { 1; 2 }",
+                vec![
+                    Constant::Int(1),
+                    Constant::Int(2),
+                    Constant::Function(vec![
+                        make(Opcode::Constant, &[0]),
+                        make(Opcode::Pop, &[]),
+                        make(Opcode::Constant, &[1]),
+                        make(Opcode::ReturnValue, &[]),
+                    ]),
+                ],
+                vec![make(Opcode::Closure, &[2, 0]), make(Opcode::Pop, &[])],
+            ),
+            (
+                "fn() { }",
+                vec![Constant::Function(vec![make(Opcode::Return, &[])])],
+                vec![make(Opcode::Closure, &[0, 0]), make(Opcode::Pop, &[])],
+            ),
+            (
+                "let oneArg = fn(a) { a }; oneArg(24);",
+                vec![
+                    Constant::Function(vec![
+                        make(Opcode::GetLocal, &[0]),
+                        make(Opcode::ReturnValue, &[]),
+                    ]),
+                    Constant::Int(24),
+                ],
+                vec![
+                    make(Opcode::Closure, &[0, 0]),
+                    make(Opcode::SetGlobal, &[0]),
+                    make(Opcode::GetGlobal, &[0]),
+                    make(Opcode::Constant, &[1]),
+                    make(Opcode::Call, &[1]),
+                    make(Opcode::Pop, &[]),
+                ],
+            ),
+        ];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = vec![(
+            "fn(a) { fn(b) { a + b } }",
+            vec![
+                Constant::Function(vec![
+                    make(Opcode::GetFree, &[0]),
+                    make(Opcode::GetLocal, &[0]),
+                    make(Opcode::Add, &[]),
+                    make(Opcode::ReturnValue, &[]),
+                ]),
+                Constant::Function(vec![
+                    make(Opcode::GetLocal, &[0]),
+                    make(Opcode::Closure, &[0, 1]),
+                    make(Opcode::ReturnValue, &[]),
+                ]),
+            ],
+            vec![make(Opcode::Closure, &[1, 0]), make(Opcode::Pop, &[])],
+        )];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let tests = vec![(
+            "let countDown = fn(x) { countDown(x - 1); }; countDown(1);",
+            vec![
+                Constant::Int(1),
+                Constant::Function(vec![
+                    make(Opcode::CurrentClosure, &[]),
+                    make(Opcode::GetLocal, &[0]),
+                    make(Opcode::Constant, &[0]),
+                    make(Opcode::Sub, &[]),
+                    make(Opcode::Call, &[1]),
+                    make(Opcode::ReturnValue, &[]),
+                ]),
+                Constant::Int(1),
+            ],
+            vec![
+                make(Opcode::Closure, &[1, 0]),
+                make(Opcode::SetGlobal, &[0]),
+                make(Opcode::GetGlobal, &[0]),
+                make(Opcode::Constant, &[2]),
+                make(Opcode::Call, &[1]),
+                make(Opcode::Pop, &[]),
+            ],
+        )];
+
+        run_compiler_tests(tests);
+    }
+
+    #[test]
+    fn test_undefined_variable() {
+        match compile("let a = 1; a + b") {
+            Err(CompileError::UndefinedVariable { name, span }) => {
+                if name != "b" || span.start.offset != 15 {
+                    panic!("wrong error. name={}, span={:?}", name, span);
+                }
+            }
+            result => panic!("expected UndefinedVariable. got={:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn test_operand_limits() {
+        let names: Vec<String> = (0..256).map(|i| format!("a{}", i)).collect();
+        let arguments = format!("let f = fn() {{}}; f({})", ["0"; 256].join(", "));
+        let free = format!(
+            "fn({}) {{ fn() {{ {} }} }}",
+            names.join(", "),
+            names.join(" + ")
+        );
+        let globals: String = (0..=u16::MAX as usize + 1)
+            .map(|i| format!("let g{} = true;", i))
+            .collect();
+        let jump = format!("if (true) {{ {} }}", "true; ".repeat(u16::MAX as usize / 2));
+
+        let tests = [
+            (arguments, "too many arguments"),
+            (free, "too many free variables"),
+            (globals, "too many global bindings"),
+            (jump, "too many instructions"),
+        ];
+
+        for (input, expected) in tests {
+            match compile(&input) {
+                Err(err) if err.to_string() == expected => {}
+                result => panic!(
+                    "expected {:?} for {:?}... got={:?}",
+                    expected,
+                    &input[..40],
+                    result.err()
+                ),
+            }
+        }
+
+        // One fewer of each still compiles.
+        let arguments = format!("let f = fn() {{}}; f({})", ["0"; 255].join(", "));
+        let free = format!(
+            "fn({}) {{ fn() {{ {} }} }}",
+            names[..255].join(", "),
+            names[..255].join(" + ")
+        );
+        for input in [arguments, free] {
+            if let Err(err) = compile(&input) {
+                panic!("compiling {:?}... failed: {}", &input[..40], err);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resolve_nested_symbols() {
+        let mut global = SymbolTable::new();
+        global.define("a");
+
+        let mut first_local = SymbolTable::new_enclosed(global);
+        first_local.define("c");
+
+        let mut second_local = SymbolTable::new_enclosed(first_local);
+        second_local.define("e");
+        second_local.define_function_name("f");
+
+        let tests = [
+            ("a", SymbolScope::Global, 0),
+            ("c", SymbolScope::Free, 0),
+            ("e", SymbolScope::Local, 0),
+            ("f", SymbolScope::Function, 0),
+        ];
+
+        for (name, scope, index) in tests {
+            let expected = Symbol {
+                name: name.to_string(),
+                scope,
+                index,
+            };
+            let result = second_local.resolve(name);
+            if result.as_ref() != Some(&expected) {
+                panic!(
+                    "name {} not resolvable. want={:?}, got={:?}",
+                    name, expected, result
+                );
+            }
+        }
+
+        let free: Vec<&str> = second_local
+            .free_symbols()
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        if free != ["c"] {
+            panic!("wrong free symbols. got={:?}", free);
+        }
+        if second_local.resolve("b").is_some() {
+            panic!("name b resolved, but was expected not to");
+        }
+    }
+}
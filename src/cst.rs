@@ -0,0 +1,1039 @@
+//! A lossless concrete syntax tree.
+//!
+//! Unlike [`crate::ast`], the tree keeps every byte of the input: whitespace
+//! and comments are tokens, missing pieces are simply absent and anything the
+//! grammar does not expect is wrapped in an `Error` node, so
+//! `cst::parse(input).to_string() == input` for every input.
+//!
+//! The tree comes in two layers. Green nodes are immutable, store only the
+//! kind, the text length and the children, and can be shared between trees.
+//! Red nodes ([`SyntaxNode`] and [`SyntaxToken`]) are cheap handles created
+//! on the fly that add the parent and the absolute offset. Typed wrappers
+//! such as [`LetStatement`] sit on top and give named access to the parts of
+//! each construct.
+//!
+//! Trivia always belongs to the innermost node that is open when it is
+//! reached, so nodes start with their first real token and comments between
+//! statements are children of the enclosing program or block.
+
+use std::{fmt, ops::Range, rc::Rc};
+
+use crate::{
+    lexer::Lexer,
+    token::TokenType::{
+        self, ASSIGN, ASTERISK, BANG, COMMA, ELSE, EOF, EQ, FALSE, FUNCTION, GT, IDENT, IF, INT,
+        LBRACE, LET, LPAREN, LT, MINUS, NOTEQ, PLUS, RBRACE, RETURN, RPAREN, SEMICOLON, SLASH,
+        STRING, TRUE,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    Program,
+    LetStatement,
+    ReturnStatement,
+    ExpressionStatement,
+    BlockStatement,
+    Identifier,
+    IntegerLiteral,
+    StringLiteral,
+    Boolean,
+    PrefixExpression,
+    InfixExpression,
+    ParenExpression,
+    IfExpression,
+    FunctionLiteral,
+    ParameterList,
+    CallExpression,
+    ArgumentList,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GreenToken {
+    kind: TokenType,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenType, text: &str) -> Self {
+        Self {
+            kind,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> TokenType {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GreenNode {
+    kind: NodeKind,
+    len: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> Self {
+        Self {
+            kind,
+            len: children.iter().map(GreenElement::len).sum(),
+            children,
+        }
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    /// Returns the length of the node's text in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+impl fmt::Display for GreenNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => write!(f, "{}", node)?,
+                GreenElement::Token(token) => write!(f, "{}", token.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    fn len(&self) -> usize {
+        match self {
+            Self::Node(node) => node.len,
+            Self::Token(token) => token.text.len(),
+        }
+    }
+}
+
+/// A node of the tree together with its position in it.
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Rc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    offset: usize,
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> Self {
+        Self(Rc::new(NodeData {
+            green,
+            parent: None,
+            offset: 0,
+        }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.0.green
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    /// Returns the byte range of the node in the root's text.
+    pub fn range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.len
+    }
+
+    pub fn children_with_tokens(&self) -> impl Iterator<Item = SyntaxElement> + '_ {
+        let mut offset = self.0.offset;
+        self.0.green.children.iter().map(move |child| {
+            let child_offset = offset;
+            offset += child.len();
+            match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: Rc::clone(green),
+                    parent: Some(self.clone()),
+                    offset: child_offset,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: Rc::clone(green),
+                    parent: self.clone(),
+                    offset: child_offset,
+                }),
+            }
+        })
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> + '_ {
+        self.children_with_tokens().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Returns the tokens that are direct children of this node.
+    pub fn tokens(&self) -> impl Iterator<Item = SyntaxToken> + '_ {
+        self.children_with_tokens().filter_map(|child| match child {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        })
+    }
+
+    /// Returns the first direct child token of type `kind`.
+    pub fn token(&self, kind: TokenType) -> Option<SyntaxToken> {
+        self.tokens().find(|token| token.kind() == kind)
+    }
+
+    /// Renders the tree with one node or token per line, for debugging.
+    pub fn debug_tree(&self) -> String {
+        let mut out = String::new();
+        self.write_debug_tree(&mut out, 0);
+        out
+    }
+
+    fn write_debug_tree(&self, out: &mut String, depth: usize) {
+        out.push_str(&format!("{}{:?}\n", "  ".repeat(depth), self));
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.write_debug_tree(out, depth + 1),
+                SyntaxElement::Token(token) => {
+                    out.push_str(&format!("{}{:?}\n", "  ".repeat(depth + 1), token))
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{:?}", self.kind(), self.range())
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+#[derive(Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    parent: SyntaxNode,
+    offset: usize,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> TokenType {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{:?} {:?}", self.kind(), self.range(), self.text())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// Parses `input` into a tree whose text is exactly `input`.
+pub fn parse(input: &str) -> SyntaxNode {
+    let tokens = Lexer::with_trivia(input)
+        .map(|tok| {
+            (
+                tok.token_type,
+                &input[tok.span.start.offset..tok.span.end.offset],
+            )
+        })
+        .collect();
+
+    let mut p = CstParser {
+        tokens,
+        position: 0,
+        stack: Vec::new(),
+    };
+    // The program covers leading trivia too.
+    p.stack.push((NodeKind::Program, Vec::new()));
+    while p.current() != EOF {
+        p.parse_statement(false);
+    }
+    p.eat_trivia();
+    let (kind, children) = p.stack.pop().unwrap();
+    SyntaxNode::new_root(Rc::new(GreenNode::new(kind, children)))
+}
+
+const LOWEST: u8 = 0;
+const PREFIX: u8 = 5;
+
+fn precedence(token_type: TokenType) -> u8 {
+    match token_type {
+        EQ | NOTEQ => 1,
+        LT | GT => 2,
+        PLUS | MINUS => 3,
+        SLASH | ASTERISK => 4,
+        LPAREN => 6,
+        _ => LOWEST,
+    }
+}
+
+fn starts_expression(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        IDENT | INT | STRING | TRUE | FALSE | BANG | MINUS | LPAREN | IF | FUNCTION
+    )
+}
+
+/// A recursive descent parser that builds green nodes directly. It never
+/// fails: a token that fits nowhere becomes an `Error` node, and every loop
+/// consumes at least one token per iteration.
+struct CstParser<'a> {
+    tokens: Vec<(TokenType, &'a str)>,
+    position: usize,
+    /// The nodes being built, innermost last.
+    stack: Vec<(NodeKind, Vec<GreenElement>)>,
+}
+
+impl CstParser<'_> {
+    /// Returns the type of the next token that is not trivia.
+    fn current(&self) -> TokenType {
+        self.tokens[self.position..]
+            .iter()
+            .map(|&(token_type, _)| token_type)
+            .find(|token_type| !token_type.is_trivia())
+            .unwrap_or(EOF)
+    }
+
+    fn push(&mut self, element: GreenElement) {
+        self.stack.last_mut().unwrap().1.push(element);
+    }
+
+    fn eat_trivia(&mut self) {
+        while let Some(&(token_type, text)) = self.tokens.get(self.position) {
+            if !token_type.is_trivia() {
+                break;
+            }
+            self.push(GreenElement::Token(Rc::new(GreenToken::new(
+                token_type, text,
+            ))));
+            self.position += 1;
+        }
+    }
+
+    /// Adds the next token that is not trivia, and the trivia before it, to
+    /// the current node.
+    fn bump(&mut self) {
+        self.eat_trivia();
+        if let Some(&(token_type, text)) = self.tokens.get(self.position) {
+            self.push(GreenElement::Token(Rc::new(GreenToken::new(
+                token_type, text,
+            ))));
+            self.position += 1;
+        }
+    }
+
+    fn eat(&mut self, token_type: TokenType) -> bool {
+        if self.current() == token_type {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn start_node(&mut self, kind: NodeKind) {
+        self.eat_trivia();
+        self.stack.push((kind, Vec::new()));
+    }
+
+    fn finish_node(&mut self) {
+        let (kind, children) = self.stack.pop().unwrap();
+        self.push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+    }
+
+    /// Returns a marker for wrapping everything added to the current node
+    /// from here on in a new node with `start_node_at`.
+    fn checkpoint(&mut self) -> usize {
+        self.eat_trivia();
+        self.stack.last().unwrap().1.len()
+    }
+
+    fn start_node_at(&mut self, checkpoint: usize, kind: NodeKind) {
+        let children = self.stack.last_mut().unwrap().1.split_off(checkpoint);
+        self.stack.push((kind, children));
+    }
+
+    fn token_node(&mut self, kind: NodeKind) {
+        self.start_node(kind);
+        self.bump();
+        self.finish_node();
+    }
+
+    fn parse_statement(&mut self, in_block: bool) {
+        match self.current() {
+            LET => {
+                self.start_node(NodeKind::LetStatement);
+                self.bump();
+                if self.current() == IDENT {
+                    self.token_node(NodeKind::Identifier);
+                }
+                self.eat(ASSIGN);
+                self.parse_optional_expression();
+                self.eat(SEMICOLON);
+                self.finish_node();
+            }
+            RETURN => {
+                self.start_node(NodeKind::ReturnStatement);
+                self.bump();
+                self.parse_optional_expression();
+                self.eat(SEMICOLON);
+                self.finish_node();
+            }
+            RBRACE if in_block => unreachable!("blocks stop at their closing brace"),
+            token_type if starts_expression(token_type) => {
+                self.start_node(NodeKind::ExpressionStatement);
+                self.parse_expression(LOWEST);
+                self.eat(SEMICOLON);
+                self.finish_node();
+            }
+            _ => self.token_node(NodeKind::Error),
+        }
+    }
+
+    fn parse_optional_expression(&mut self) {
+        if starts_expression(self.current()) {
+            self.parse_expression(LOWEST);
+        }
+    }
+
+    fn parse_expression(&mut self, min_precedence: u8) {
+        let checkpoint = self.checkpoint();
+        match self.current() {
+            IDENT => self.token_node(NodeKind::Identifier),
+            INT => self.token_node(NodeKind::IntegerLiteral),
+            STRING => self.token_node(NodeKind::StringLiteral),
+            TRUE | FALSE => self.token_node(NodeKind::Boolean),
+            BANG | MINUS => {
+                self.start_node(NodeKind::PrefixExpression);
+                self.bump();
+                if starts_expression(self.current()) {
+                    self.parse_expression(PREFIX);
+                }
+                self.finish_node();
+            }
+            LPAREN => {
+                self.start_node(NodeKind::ParenExpression);
+                self.bump();
+                self.parse_optional_expression();
+                self.eat(RPAREN);
+                self.finish_node();
+            }
+            IF => self.parse_if_expression(),
+            FUNCTION => self.parse_function_literal(),
+            _ => unreachable!("callers check starts_expression"),
+        }
+
+        loop {
+            let token_type = self.current();
+            let precedence = precedence(token_type);
+            if precedence <= min_precedence {
+                break;
+            }
+
+            if token_type == LPAREN {
+                self.start_node_at(checkpoint, NodeKind::CallExpression);
+                self.parse_list(NodeKind::ArgumentList, starts_expression, |p| {
+                    p.parse_expression(LOWEST)
+                });
+            } else {
+                self.start_node_at(checkpoint, NodeKind::InfixExpression);
+                self.bump();
+                if starts_expression(self.current()) {
+                    self.parse_expression(precedence);
+                }
+            }
+            self.finish_node();
+        }
+    }
+
+    fn parse_if_expression(&mut self) {
+        self.start_node(NodeKind::IfExpression);
+        self.bump();
+        self.eat(LPAREN);
+        self.parse_optional_expression();
+        self.eat(RPAREN);
+        if self.current() == LBRACE {
+            self.parse_block_statement();
+        }
+        if self.eat(ELSE) && self.current() == LBRACE {
+            self.parse_block_statement();
+        }
+        self.finish_node();
+    }
+
+    fn parse_function_literal(&mut self) {
+        self.start_node(NodeKind::FunctionLiteral);
+        self.bump();
+        if self.current() == LPAREN {
+            self.parse_list(
+                NodeKind::ParameterList,
+                |token_type| token_type == IDENT,
+                |p| p.token_node(NodeKind::Identifier),
+            );
+        }
+        if self.current() == LBRACE {
+            self.parse_block_statement();
+        }
+        self.finish_node();
+    }
+
+    /// Parses a parenthesized, comma-separated list whose elements start
+    /// with a token accepted by `starts_element`. Stops early at tokens that
+    /// likely belong to the enclosing construct.
+    fn parse_list(
+        &mut self,
+        kind: NodeKind,
+        starts_element: fn(TokenType) -> bool,
+        parse_element: fn(&mut Self),
+    ) {
+        self.start_node(kind);
+        self.bump();
+        loop {
+            match self.current() {
+                RPAREN => {
+                    self.bump();
+                    break;
+                }
+                EOF | LBRACE | RBRACE | SEMICOLON | LET | RETURN => break,
+                COMMA => self.bump(),
+                token_type if starts_element(token_type) => parse_element(self),
+                _ => self.token_node(NodeKind::Error),
+            }
+        }
+        self.finish_node();
+    }
+
+    fn parse_block_statement(&mut self) {
+        self.start_node(NodeKind::BlockStatement);
+        self.bump();
+        loop {
+            match self.current() {
+                RBRACE => {
+                    self.bump();
+                    break;
+                }
+                EOF => break,
+                _ => self.parse_statement(true),
+            }
+        }
+        self.finish_node();
+    }
+}
+
+/// A typed view of a node of one particular kind.
+pub trait AstNode: Sized {
+    fn cast(node: SyntaxNode) -> Option<Self>;
+    fn syntax(&self) -> &SyntaxNode;
+}
+
+macro_rules! ast_node {
+    ($name:ident) => {
+        #[derive(Debug, Clone)]
+        pub struct $name(SyntaxNode);
+
+        impl AstNode for $name {
+            fn cast(node: SyntaxNode) -> Option<Self> {
+                (node.kind() == NodeKind::$name).then(|| Self(node))
+            }
+
+            fn syntax(&self) -> &SyntaxNode {
+                &self.0
+            }
+        }
+    };
+}
+
+ast_node!(Program);
+ast_node!(LetStatement);
+ast_node!(ReturnStatement);
+ast_node!(ExpressionStatement);
+ast_node!(BlockStatement);
+ast_node!(Identifier);
+ast_node!(IntegerLiteral);
+ast_node!(StringLiteral);
+ast_node!(Boolean);
+ast_node!(PrefixExpression);
+ast_node!(InfixExpression);
+ast_node!(ParenExpression);
+ast_node!(IfExpression);
+ast_node!(FunctionLiteral);
+ast_node!(CallExpression);
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    Let(LetStatement),
+    Return(ReturnStatement),
+    Expression(ExpressionStatement),
+    /// Source the grammar does not expect.
+    Error(SyntaxNode),
+}
+
+impl AstNode for Statement {
+    fn cast(node: SyntaxNode) -> Option<Self> {
+        let statement = match node.kind() {
+            NodeKind::LetStatement => Self::Let(LetStatement(node)),
+            NodeKind::ReturnStatement => Self::Return(ReturnStatement(node)),
+            NodeKind::ExpressionStatement => Self::Expression(ExpressionStatement(node)),
+            NodeKind::Error => Self::Error(node),
+            _ => return None,
+        };
+        Some(statement)
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        match self {
+            Self::Let(let_stmt) => let_stmt.syntax(),
+            Self::Return(return_stmt) => return_stmt.syntax(),
+            Self::Expression(expression_stmt) => expression_stmt.syntax(),
+            Self::Error(node) => node,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Identifier(Identifier),
+    IntegerLiteral(IntegerLiteral),
+    StringLiteral(StringLiteral),
+    Boolean(Boolean),
+    Prefix(PrefixExpression),
+    Infix(InfixExpression),
+    Paren(ParenExpression),
+    If(IfExpression),
+    FunctionLiteral(FunctionLiteral),
+    Call(CallExpression),
+}
+
+impl AstNode for Expression {
+    fn cast(node: SyntaxNode) -> Option<Self> {
+        let expression = match node.kind() {
+            NodeKind::Identifier => Self::Identifier(Identifier(node)),
+            NodeKind::IntegerLiteral => Self::IntegerLiteral(IntegerLiteral(node)),
+            NodeKind::StringLiteral => Self::StringLiteral(StringLiteral(node)),
+            NodeKind::Boolean => Self::Boolean(Boolean(node)),
+            NodeKind::PrefixExpression => Self::Prefix(PrefixExpression(node)),
+            NodeKind::InfixExpression => Self::Infix(InfixExpression(node)),
+            NodeKind::ParenExpression => Self::Paren(ParenExpression(node)),
+            NodeKind::IfExpression => Self::If(IfExpression(node)),
+            NodeKind::FunctionLiteral => Self::FunctionLiteral(FunctionLiteral(node)),
+            NodeKind::CallExpression => Self::Call(CallExpression(node)),
+            _ => return None,
+        };
+        Some(expression)
+    }
+
+    fn syntax(&self) -> &SyntaxNode {
+        match self {
+            Self::Identifier(identifier) => identifier.syntax(),
+            Self::IntegerLiteral(integer_literal) => integer_literal.syntax(),
+            Self::StringLiteral(string_literal) => string_literal.syntax(),
+            Self::Boolean(boolean) => boolean.syntax(),
+            Self::Prefix(prefix) => prefix.syntax(),
+            Self::Infix(infix) => infix.syntax(),
+            Self::Paren(paren) => paren.syntax(),
+            Self::If(if_expression) => if_expression.syntax(),
+            Self::FunctionLiteral(function_literal) => function_literal.syntax(),
+            Self::Call(call) => call.syntax(),
+        }
+    }
+}
+
+/// Returns the children of `node` that cast to `N`.
+fn children<'a, N: AstNode + 'a>(node: &'a SyntaxNode) -> impl Iterator<Item = N> + 'a {
+    node.children().filter_map(N::cast)
+}
+
+fn child<N: AstNode>(node: &SyntaxNode) -> Option<N> {
+    children(node).next()
+}
+
+impl Program {
+    pub fn statements(&self) -> impl Iterator<Item = Statement> + '_ {
+        children(&self.0)
+    }
+}
+
+impl LetStatement {
+    pub fn name(&self) -> Option<Identifier> {
+        // In `let = x` the identifier after the `=` is the value.
+        let assign = self.0.token(ASSIGN).map(|token| token.range().start);
+        self.0
+            .children()
+            .next()
+            .filter(|node| assign.is_none_or(|assign| node.range().start < assign))
+            .and_then(Identifier::cast)
+    }
+
+    pub fn value(&self) -> Option<Expression> {
+        let name = self.name().map(|name| name.syntax().range());
+        children::<Expression>(&self.0).find(|value| Some(value.syntax().range()) != name)
+    }
+}
+
+impl ReturnStatement {
+    pub fn return_value(&self) -> Option<Expression> {
+        child(&self.0)
+    }
+}
+
+impl ExpressionStatement {
+    pub fn expression(&self) -> Option<Expression> {
+        child(&self.0)
+    }
+}
+
+impl BlockStatement {
+    pub fn statements(&self) -> impl Iterator<Item = Statement> + '_ {
+        children(&self.0)
+    }
+}
+
+impl Identifier {
+    pub fn name(&self) -> String {
+        self.0.token(IDENT).map_or_else(String::new, |token| {
+            crate::unicode::to_nfc(token.text()).into_owned()
+        })
+    }
+}
+
+impl IntegerLiteral {
+    /// Returns the value, or `None` if it does not fit in an `i64`.
+    pub fn value(&self) -> Option<i64> {
+        self.0.token(INT)?.text().parse().ok()
+    }
+}
+
+impl StringLiteral {
+    /// Returns the value with escape sequences resolved.
+    pub fn value(&self) -> String {
+        let text = self
+            .0
+            .token(STRING)
+            .map_or_else(String::new, |token| token.text().to_string());
+        Lexer::new(&text).next_token().literal.into_owned()
+    }
+}
+
+impl Boolean {
+    pub fn value(&self) -> bool {
+        self.0.token(TRUE).is_some()
+    }
+}
+
+impl PrefixExpression {
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        self.0
+            .tokens()
+            .find(|token| matches!(token.kind(), BANG | MINUS))
+    }
+
+    pub fn operand(&self) -> Option<Expression> {
+        child(&self.0)
+    }
+}
+
+impl InfixExpression {
+    pub fn left(&self) -> Option<Expression> {
+        child(&self.0)
+    }
+
+    pub fn operator(&self) -> Option<SyntaxToken> {
+        self.0
+            .tokens()
+            .find(|token| precedence(token.kind()) != LOWEST)
+    }
+
+    /// Returns the right operand, which is missing in `1 +` even though the
+    /// left one is there.
+    pub fn right(&self) -> Option<Expression> {
+        let operator = self.operator()?.range().start;
+        children::<Expression>(&self.0)
+            .find(|expression| expression.syntax().range().start > operator)
+    }
+}
+
+impl ParenExpression {
+    pub fn expression(&self) -> Option<Expression> {
+        child(&self.0)
+    }
+}
+
+impl IfExpression {
+    pub fn condition(&self) -> Option<Expression> {
+        child(&self.0)
+    }
+
+    pub fn consequence(&self) -> Option<BlockStatement> {
+        child(&self.0)
+    }
+
+    pub fn alternative(&self) -> Option<BlockStatement> {
+        children(&self.0).nth(1)
+    }
+}
+
+impl FunctionLiteral {
+    pub fn parameters(&self) -> Vec<Identifier> {
+        self.0
+            .children()
+            .find(|node| node.kind() == NodeKind::ParameterList)
+            .map_or_else(Vec::new, |list| children(&list).collect())
+    }
+
+    pub fn body(&self) -> Option<BlockStatement> {
+        child(&self.0)
+    }
+}
+
+impl CallExpression {
+    pub fn function(&self) -> Option<Expression> {
+        child(&self.0)
+    }
+
+    pub fn arguments(&self) -> Vec<Expression> {
+        self.0
+            .children()
+            .find(|node| node.kind() == NodeKind::ArgumentList)
+            .map_or_else(Vec::new, |list| children(&list).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, AstNode, Expression, NodeKind, Program, Statement};
+
+    #[test]
+    fn test_lossless() {
+        let mut inputs = vec![
+            "".to_string(),
+            "let x = 5;".to_string(),
+            "  // leading\nlet add = fn(a, /* b */ b) {\r\n\ta + b // sum\n};\n\nadd(1, 2)\n"
+                .to_string(),
+            "if (x < 1) { \"é\\n\" } else { -(1 + 2) * !y }".to_string(),
+            "let = ; ) } { ( , fn fn( if else return".to_string(),
+            "\"unterminated /* comment".to_string(),
+            "/* unterminated \"string".to_string(),
+            "let x = @ # $ ~ 5 \u{a0} ;".to_string(),
+            "f(1, 2,, 3 let g = fn(x y) { x".to_string(),
+        ];
+
+        // Random soups of tokens, trivia and stray characters.
+        let pieces = [
+            "let", "x", " ", "\n", "=", "5", ";", "(", ")", "{", "}", ",", "+", "-", "*", "/", "!",
+            "==", "<", "fn", "if", "else", "return", "\"s\"", "\"", "//c\n", "/*", "*/", "é", "@",
+            "true",
+        ];
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        for _ in 0..200 {
+            let mut input = String::new();
+            for _ in 0..30 {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                input.push_str(pieces[(seed % pieces.len() as u64) as usize]);
+            }
+            inputs.push(input);
+        }
+
+        for input in &inputs {
+            let root = parse(input);
+            if root.to_string() != *input || root.range() != (0..input.len()) {
+                panic!(
+                    "tree of {:?} is not lossless. got={:?}",
+                    input,
+                    root.to_string()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tree() {
+        let root = parse("let x = -a; // c\nf(1)");
+
+        let expected = "Program@0..21
+  LetStatement@0..11
+    LET@0..3 \"let\"
+    WHITESPACE@3..4 \" \"
+    Identifier@4..5
+      IDENT@4..5 \"x\"
+    WHITESPACE@5..6 \" \"
+    ASSIGN@6..7 \"=\"
+    WHITESPACE@7..8 \" \"
+    PrefixExpression@8..10
+      MINUS@8..9 \"-\"
+      Identifier@9..10
+        IDENT@9..10 \"a\"
+    SEMICOLON@10..11 \";\"
+  WHITESPACE@11..12 \" \"
+  COMMENT@12..16 \"// c\"
+  WHITESPACE@16..17 \"\\n\"
+  ExpressionStatement@17..21
+    CallExpression@17..21
+      Identifier@17..18
+        IDENT@17..18 \"f\"
+      ArgumentList@18..21
+        LPAREN@18..19 \"(\"
+        IntegerLiteral@19..20
+          INT@19..20 \"1\"
+        RPAREN@20..21 \")\"
+";
+        if root.debug_tree() != expected {
+            panic!(
+                "tree wrong.\nwant=\n{}\ngot=\n{}",
+                expected,
+                root.debug_tree()
+            );
+        }
+
+        let argument = root
+            .children()
+            .nth(1)
+            .and_then(|statement| statement.children().next())
+            .and_then(|call| call.children().nth(1))
+            .and_then(|arguments| arguments.children().next())
+            .unwrap();
+        let parent = argument.parent().map(|parent| parent.kind());
+        if argument.range() != (19..20) || parent != Some(NodeKind::ArgumentList) {
+            panic!("red node wrong. got={:?} in {:?}", argument, parent);
+        }
+    }
+
+    #[test]
+    fn test_typed_view() {
+        let input = "let add = fn(a, b) { return a + b * 2; };
+if (add(1, \"x\\ty\") == (3)) { true } else { !false }
+let broken = 1 +;";
+        let program = Program::cast(parse(input)).unwrap();
+        let statements: Vec<Statement> = program.statements().collect();
+        if statements.len() != 3 {
+            panic!("wrong number of statements. got={:?}", statements);
+        }
+
+        let Statement::Let(let_stmt) = &statements[0] else {
+            panic!(
+                "statements[0] is not a let statement. got={:?}",
+                statements[0]
+            );
+        };
+        let Some(Expression::FunctionLiteral(function)) = let_stmt.value() else {
+            panic!("let value is not a function. got={:?}", let_stmt.value());
+        };
+        let parameters: Vec<String> = function.parameters().iter().map(|p| p.name()).collect();
+        if let_stmt.name().map(|name| name.name()).as_deref() != Some("add")
+            || parameters != ["a", "b"]
+        {
+            panic!("let wrong. got={:?}, {:?}", let_stmt.name(), parameters);
+        }
+        let body = function.body().unwrap();
+        let Some(Statement::Return(return_stmt)) = body.statements().next() else {
+            panic!("function body wrong. got={}", body.syntax());
+        };
+        let Some(Expression::Infix(sum)) = return_stmt.return_value() else {
+            panic!("return value wrong. got={:?}", return_stmt.return_value());
+        };
+        if sum.operator().unwrap().text() != "+"
+            || sum.right().unwrap().syntax().to_string() != "b * 2"
+        {
+            panic!("infix wrong. got={}", sum.syntax());
+        }
+
+        let Statement::Expression(expression_stmt) = &statements[1] else {
+            panic!("statements[1] wrong. got={:?}", statements[1]);
+        };
+        let Some(Expression::If(if_expression)) = expression_stmt.expression() else {
+            panic!(
+                "if expression wrong. got={:?}",
+                expression_stmt.expression()
+            );
+        };
+        let Some(Expression::Infix(condition)) = if_expression.condition() else {
+            panic!("condition wrong. got={:?}", if_expression.condition());
+        };
+        let Some(Expression::Call(call)) = condition.left() else {
+            panic!("call wrong. got={:?}", condition.left());
+        };
+        match call.arguments().as_slice() {
+            [Expression::IntegerLiteral(one), Expression::StringLiteral(s)]
+                if one.value() == Some(1) && s.value() == "x\ty" => {}
+            arguments => panic!("arguments wrong. got={:?}", arguments),
+        }
+        match condition.right() {
+            Some(Expression::Paren(paren)) if paren.expression().is_some() => {}
+            right => panic!("paren wrong. got={:?}", right),
+        }
+        let alternative = if_expression.alternative().unwrap();
+        if alternative.syntax().to_string() != "{ !false }" {
+            panic!("alternative wrong. got={}", alternative.syntax());
+        }
+
+        let program = Program::cast(parse("let = y;")).unwrap();
+        match program.statements().next() {
+            Some(Statement::Let(let_stmt))
+                if let_stmt.name().is_none()
+                    && matches!(let_stmt.value(), Some(Expression::Identifier(_))) => {}
+            statement => panic!("let without a name wrong. got={:?}", statement),
+        }
+
+        let Statement::Let(broken) = &statements[2] else {
+            panic!("statements[2] wrong. got={:?}", statements[2]);
+        };
+        let Some(Expression::Infix(incomplete)) = broken.value() else {
+            panic!("broken value wrong. got={:?}", broken.value());
+        };
+        if incomplete.left().is_none() || incomplete.right().is_some() {
+            panic!("incomplete infix wrong. got={}", incomplete.syntax());
+        }
+    }
+}
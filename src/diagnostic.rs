@@ -0,0 +1,423 @@
+//! Rendering of errors as source snippets with the offending span underlined.
+//!
+//! ```text
+//! error: expected next token to be IDENT, got ASSIGN instead
+//!  --> script.mk:2:5
+//!   |
+//! 2 | let = 10;
+//!   |     ^ expected IDENT
+//! ```
+
+use std::io::IsTerminal;
+
+use crate::{
+    lexer::LexError,
+    parser::ParseError,
+    token::{Position, Span},
+    unicode::display_width,
+};
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Tabs in source lines are expanded to the next multiple of this many
+/// columns, so the carets line up whatever the terminal's tab width is.
+const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Plain text, for logs and other non-terminal output.
+    Plain,
+    /// Colors using ANSI escape sequences.
+    Ansi,
+}
+
+impl ColorMode {
+    /// Picks `Ansi` when `stream` is a terminal and the `NO_COLOR`
+    /// environment variable is unset.
+    pub fn detect(stream: &impl IsTerminal) -> Self {
+        if stream.is_terminal() && std::env::var_os("NO_COLOR").is_none() {
+            Self::Ansi
+        } else {
+            Self::Plain
+        }
+    }
+
+    fn paint(self, color: &str, text: &str) -> String {
+        match self {
+            Self::Plain => text.to_string(),
+            Self::Ansi => format!("{}{}{}", color, text, RESET),
+        }
+    }
+}
+
+/// A message attached to a span of source other than the primary one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    /// Printed next to the carets under `span`.
+    pub label: Option<String>,
+    pub secondary: Vec<Label>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+            label: None,
+            secondary: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Renders the diagnostic against `source`, which must be the text the
+    /// spans were produced from. `file_name` is only used for the location
+    /// line.
+    pub fn render(&self, file_name: &str, source: &str, color: ColorMode) -> String {
+        // Each underline is (span, marker, message, color), primary first.
+        let mut underlines = vec![(self.span, '^', self.label.as_deref().unwrap_or(""), RED)];
+        for label in &self.secondary {
+            underlines.push((label.span, '-', &label.message, BLUE));
+        }
+
+        let mut lines: Vec<usize> = underlines.iter().map(|u| u.0.start.line).collect();
+        lines.sort_unstable();
+        lines.dedup();
+
+        let width = lines.last().map_or(1, |line| line.to_string().len());
+        let gutter = color.paint(BLUE, &format!("{} |", " ".repeat(width)));
+
+        let mut out = format!(
+            "{}{}\n{}{} {}:{}:{}\n{}\n",
+            color.paint(RED, "error"),
+            color.paint(BOLD, &format!(": {}", self.message)),
+            " ".repeat(width),
+            color.paint(BLUE, "-->"),
+            file_name,
+            self.span.start.line,
+            self.span.start.column,
+            gutter,
+        );
+
+        let mut previous = None;
+        for &line in &lines {
+            if previous.is_some_and(|previous| line > previous + 1) {
+                out.push_str(&color.paint(BLUE, "..."));
+                out.push('\n');
+            }
+            previous = Some(line);
+
+            let text = line_text(source, line);
+            out.push_str(&color.paint(BLUE, &format!("{:>width$} |", line, width = width)));
+            if !text.is_empty() {
+                out.push(' ');
+                out.push_str(&expand_tabs(text));
+            }
+            out.push('\n');
+
+            for (span, marker, message, marker_color) in &underlines {
+                if span.start.line != line {
+                    continue;
+                }
+                let (indent, len) = underline(source, text, span.start, span.end);
+                let indent = " ".repeat(indent);
+                let mut carets = marker.to_string().repeat(len);
+                if !message.is_empty() {
+                    carets.push(' ');
+                    carets.push_str(message);
+                }
+                out.push_str(&format!(
+                    "{} {}{}\n",
+                    gutter,
+                    indent,
+                    color.paint(marker_color, &carets)
+                ));
+            }
+        }
+
+        if let Some(help) = &self.help {
+            out.push_str(&format!(
+                "{}\n{} {} {}\n",
+                gutter,
+                " ".repeat(width),
+                color.paint(BLUE, "="),
+                color.paint(BOLD, &format!("help: {}", help))
+            ));
+        }
+
+        out
+    }
+}
+
+/// Returns the text of the 1-based `line` of `source` without its line
+/// terminator, or "" past the end of the input.
+fn line_text(source: &str, line: usize) -> &str {
+    source
+        .split('\n')
+        .nth(line - 1)
+        .map_or("", |text| text.strip_suffix('\r').unwrap_or(text))
+}
+
+/// Returns the column after `ch` when it is displayed at `column`.
+fn advance(column: usize, ch: char) -> usize {
+    if ch == '\t' {
+        column + TAB_WIDTH - column % TAB_WIDTH
+    } else {
+        column + display_width(ch)
+    }
+}
+
+/// Returns the number of columns `text` takes up once tabs are expanded.
+fn display_columns(text: &str) -> usize {
+    text.chars().fold(0, advance)
+}
+
+/// Replaces the tabs in `text` with spaces up to the next tab stop.
+fn expand_tabs(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut column = 0;
+    for ch in text.chars() {
+        let next = advance(column, ch);
+        if ch == '\t' {
+            out.extend(std::iter::repeat_n(' ', next - column));
+        } else {
+            out.push(ch);
+        }
+        column = next;
+    }
+    out
+}
+
+/// Returns the number of columns before an underline of `start..end` in
+/// `text` and the underline's width in columns, counting wide characters as
+/// two columns and tabs up to the next tab stop. Spans running past the end
+/// of the line are cut off there and empty spans get a single marker.
+fn underline(source: &str, text: &str, start: Position, end: Position) -> (usize, usize) {
+    let offset = start.offset.min(source.len());
+    let line_start = source[..offset]
+        .rfind('\n')
+        .map_or(0, |newline| newline + 1);
+    let column = (offset - line_start).min(text.len());
+
+    let indent = display_columns(&text[..column]);
+    let end = end
+        .offset
+        .saturating_sub(line_start)
+        .clamp(column, text.len());
+    let len = (display_columns(&text[..end]) - indent).max(1);
+
+    (indent, len)
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Self {
+        let diagnostic = Diagnostic::error(err.to_string(), err.span());
+        match err {
+            LexError::MixedScriptIdentifier { scripts, .. } => diagnostic
+                .with_label(format!("mixes {}", scripts.join(" and ")))
+                .with_help("write each identifier in a single script"),
+            LexError::UnterminatedString { span } => {
+                let quote = Span::new(
+                    span.start,
+                    Position::new(
+                        span.start.line,
+                        span.start.column + 1,
+                        span.start.offset + 1,
+                    ),
+                );
+                Diagnostic::error(err.to_string(), quote)
+                    .with_label("string starts here")
+                    .with_help("add a closing `\"`")
+            }
+            LexError::InvalidEscape { .. } => diagnostic.with_help(
+                "the valid escapes are \\n, \\t, \\\\, \\\" and \\u{...} with 1 to 6 hex digits",
+            ),
+            LexError::UnterminatedBlockComment { .. } => diagnostic
+                .with_label("comment starts here")
+                .with_help("block comments nest, so each `/*` needs its own `*/`"),
+            LexError::InvalidUtf8 { .. } => diagnostic
+                .with_label("replaced with U+FFFD")
+                .with_help("save the file as UTF-8"),
+        }
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Self {
+        let diagnostic = Diagnostic::error(err.to_string(), err.span());
+        match err {
+            ParseError::Lex(err) => err.into(),
+            ParseError::UnexpectedToken { expected, .. } => {
+                diagnostic.with_label(format!("expected {:?}", expected))
+            }
+            ParseError::InvalidInteger(_) => diagnostic
+                .with_label("out of range")
+                .with_help(format!("integers must be at most {}", i64::MAX)),
+            ParseError::NoPrefixParseFn(_) => diagnostic.with_label("expected an expression"),
+            ParseError::UnexpectedEof(_) => diagnostic.with_label("input ends here"),
+            ParseError::TooDeeplyNested(_) => diagnostic
+                .with_label("nesting limit reached")
+                .with_help("split the expression up with `let` bindings"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::Lexer,
+        parser::Parser,
+        token::{Position, Span},
+    };
+
+    use super::{ColorMode, Diagnostic};
+
+    fn parse_error_diagnostics(input: &str) -> Vec<Diagnostic> {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let (_, errors) = p.parse_program_with_recovery();
+        errors.iter().map(Diagnostic::from).collect()
+    }
+
+    #[test]
+    fn test_render_parse_errors() {
+        let tests = [
+            (
+                "let x = 5;\nlet = 10;",
+                "error: expected next token to be IDENT, got ASSIGN instead
+ --> test.mk:2:5
+  |
+2 | let = 10;
+  |     ^ expected IDENT
+",
+            ),
+            (
+                "let x = 99999999999999999999;",
+                "error: could not parse 99999999999999999999 as integer
+ --> test.mk:1:9
+  |
+1 | let x = 99999999999999999999;
+  |         ^^^^^^^^^^^^^^^^^^^^ out of range
+  |
+  = help: integers must be at most 9223372036854775807
+",
+            ),
+            (
+                "add(1,\n\t2",
+                "error: unexpected end of input
+ --> test.mk:2:3
+  |
+2 |     2
+  |      ^ input ends here
+",
+            ),
+            (
+                "let 名前 = \"値\";\tlet = 1;",
+                "error: expected next token to be IDENT, got ASSIGN instead
+ --> test.mk:1:19
+  |
+1 | let 名前 = \"値\";    let = 1;
+  |                         ^ expected IDENT
+",
+            ),
+            (
+                "let s = \"abc\n\nlet t = 1;",
+                "error: unterminated string literal
+ --> test.mk:1:9
+  |
+1 | let s = \"abc
+  |         ^ string starts here
+  |
+  = help: add a closing `\"`
+",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let diagnostics = parse_error_diagnostics(input);
+            let rendered = diagnostics[0].render("test.mk", input, ColorMode::Plain);
+            if rendered != expected {
+                panic!(
+                    "render wrong for {:?}.\nexpected=\n{}\ngot=\n{}",
+                    input, expected, rendered
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_secondary_labels() {
+        let input = "let x = 1;\nlet y = 2;\nlet z = 3;\nlet x = 4;\n";
+        let first = Span::new(Position::new(1, 5, 4), Position::new(1, 6, 5));
+        let second = Span::new(Position::new(4, 5, 37), Position::new(4, 6, 38));
+
+        let diagnostic = Diagnostic::error("x is bound twice", second)
+            .with_label("second binding")
+            .with_secondary(first, "first binding")
+            .with_help("rename one of them");
+
+        let expected = "error: x is bound twice
+ --> lint.mk:4:5
+  |
+1 | let x = 1;
+  |     - first binding
+...
+4 | let x = 4;
+  |     ^ second binding
+  |
+  = help: rename one of them
+";
+        let rendered = diagnostic.render("lint.mk", input, ColorMode::Plain);
+        if rendered != expected {
+            panic!("render wrong.\nexpected=\n{}\ngot=\n{}", expected, rendered);
+        }
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let input = "let = 1;";
+        let rendered = parse_error_diagnostics(input)[0].render("a.mk", input, ColorMode::Ansi);
+
+        let expected = "\x1b[1;31merror\x1b[0m\x1b[1m: expected next token to be IDENT, got ASSIGN instead\x1b[0m
+ \x1b[1;34m-->\x1b[0m a.mk:1:5
+\x1b[1;34m  |\x1b[0m
+\x1b[1;34m1 |\x1b[0m let = 1;
+\x1b[1;34m  |\x1b[0m     \x1b[1;31m^ expected IDENT\x1b[0m
+";
+        if rendered != expected {
+            panic!(
+                "render wrong.\nexpected={:?}\ngot=     {:?}",
+                expected, rendered
+            );
+        }
+    }
+}
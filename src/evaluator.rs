@@ -0,0 +1,655 @@
+use std::{panic, rc::Rc, thread};
+
+use crate::{
+    ast::{BlockStatement, Expression, IfExpression, Program, Statement},
+    object::{Env, Environment, Function, Object},
+};
+
+/// The stack size of the thread [`with_eval_stack`] runs on.
+pub const STACK_SIZE: usize = 128 * 1024 * 1024;
+
+/// A generous bound on the native stack one call takes. An unoptimised build
+/// uses about 8 KiB for a call nested in a few expressions.
+const CALL_STACK_SIZE: usize = 32 * 1024;
+
+/// Calls nested deeper than this fail with a "stack overflow" error instead
+/// of overflowing the native stack of a thread started by
+/// [`with_eval_stack`].
+const MAX_CALL_DEPTH: usize = STACK_SIZE / CALL_STACK_SIZE;
+
+/// Runs `f` on a thread with a stack of [`STACK_SIZE`] bytes, which holds
+/// [`eval`] up to its maximum call depth. The stack of the main thread, let
+/// alone that of other threads, is too small for deep recursion.
+pub fn with_eval_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn the evaluator thread")
+            .join()
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    })
+}
+
+/// Evaluates `program` in `env`, returning the value of the last statement,
+/// or `None` if that statement does not produce a value (e.g. `let`).
+pub fn eval(program: &Program, env: &Env) -> Option<Object> {
+    let mut result = None;
+
+    for statement in &program.statements {
+        result = eval_statement(statement, env);
+
+        match result {
+            Some(Object::ReturnValue(value)) => return Some(*value),
+            Some(Object::Error(_)) => return result,
+            _ => {}
+        }
+    }
+
+    result
+}
+
+pub fn eval_block_statement(block: &BlockStatement, env: &Env) -> Option<Object> {
+    let mut result = None;
+
+    for statement in &block.statements {
+        result = eval_statement(statement, env);
+
+        if let Some(Object::ReturnValue(_) | Object::Error(_)) = result {
+            return result;
+        }
+    }
+
+    result
+}
+
+fn eval_statement(statement: &Statement, env: &Env) -> Option<Object> {
+    match statement {
+        Statement::LetStatement(let_stmt) => {
+            let val = eval_optional_expression(&let_stmt.value, env);
+            if val.is_error() {
+                return Some(val);
+            }
+            env.borrow_mut().set(let_stmt.name.value.clone(), val);
+            None
+        }
+        Statement::ReturnStatement(return_stmt) => {
+            let val = eval_optional_expression(&return_stmt.return_value, env);
+            if val.is_error() {
+                return Some(val);
+            }
+            Some(Object::ReturnValue(Box::new(val)))
+        }
+        Statement::ExpressionStatement(expression_stmt) => {
+            Some(eval_optional_expression(&expression_stmt.expression, env))
+        }
+        Statement::Error(_) => Some(Object::Error("invalid syntax".to_string())),
+    }
+}
+
+fn eval_optional_expression(expression: &Option<Expression>, env: &Env) -> Object {
+    expression
+        .as_ref()
+        .map_or(Object::Null, |expression| eval_expression(expression, env))
+}
+
+fn eval_expression(expression: &Expression, env: &Env) -> Object {
+    match expression {
+        Expression::Identifier(identifier) => match env.borrow().get(&identifier.value) {
+            Some(val) => val,
+            None => Object::Error(format!("identifier not found: {}", identifier.value)),
+        },
+        Expression::IntegerLiteral(integer_literal) => Object::Integer(integer_literal.value),
+        Expression::StringLiteral(string_literal) => Object::String(string_literal.value.clone()),
+        Expression::Boolean(boolean) => Object::Boolean(boolean.value),
+        Expression::Prefix(prefix) => {
+            let right = eval_expression(&prefix.right, env);
+            if right.is_error() {
+                return right;
+            }
+            eval_prefix_expression(&prefix.operator, right)
+        }
+        Expression::Infix(infix) => {
+            let left = eval_expression(&infix.left, env);
+            if left.is_error() {
+                return left;
+            }
+            let right = eval_expression(&infix.right, env);
+            if right.is_error() {
+                return right;
+            }
+            eval_infix_expression(&infix.operator, left, right)
+        }
+        Expression::If(if_expression) => eval_if_expression(if_expression, env),
+        Expression::FunctionLiteral(function_literal) => Object::Function(Rc::new(Function {
+            parameters: function_literal.parameters.clone(),
+            body: function_literal.body.clone(),
+            env: env.clone(),
+        })),
+        Expression::Call(call) => {
+            let function = eval_expression(&call.function, env);
+            if function.is_error() {
+                return function;
+            }
+            let args = match eval_expressions(&call.arguments, env) {
+                Ok(args) => args,
+                Err(err) => return err,
+            };
+            apply_function(function, args, env)
+        }
+        Expression::Error(_) => Object::Error("invalid syntax".to_string()),
+    }
+}
+
+fn eval_expressions(expressions: &[Expression], env: &Env) -> Result<Vec<Object>, Object> {
+    let mut result = Vec::with_capacity(expressions.len());
+
+    for expression in expressions {
+        let evaluated = eval_expression(expression, env);
+        if evaluated.is_error() {
+            return Err(evaluated);
+        }
+        result.push(evaluated);
+    }
+
+    Ok(result)
+}
+
+fn apply_function(function: Object, args: Vec<Object>, env: &Env) -> Object {
+    let function = match function {
+        Object::Function(function) => function,
+        obj => return Object::Error(format!("not a function: {}", obj.object_type())),
+    };
+
+    if function.parameters.len() != args.len() {
+        return Object::Error(format!(
+            "wrong number of arguments: want={}, got={}",
+            function.parameters.len(),
+            args.len()
+        ));
+    }
+
+    if env.borrow().call_depth() >= MAX_CALL_DEPTH {
+        return Object::Error("stack overflow".to_string());
+    }
+
+    let extended_env = Environment::new_call(function.env.clone(), env);
+    for (param, arg) in function.parameters.iter().zip(args) {
+        extended_env.borrow_mut().set(param.value.clone(), arg);
+    }
+
+    match eval_block_statement(&function.body, &extended_env) {
+        Some(Object::ReturnValue(value)) => *value,
+        Some(evaluated) => evaluated,
+        None => Object::Null,
+    }
+}
+
+fn eval_if_expression(if_expression: &IfExpression, env: &Env) -> Object {
+    let condition = eval_expression(&if_expression.condition, env);
+    if condition.is_error() {
+        return condition;
+    }
+
+    let evaluated = if is_truthy(&condition) {
+        eval_block_statement(&if_expression.consequence, env)
+    } else if let Some(alternative) = &if_expression.alternative {
+        eval_block_statement(alternative, env)
+    } else {
+        None
+    };
+
+    evaluated.unwrap_or(Object::Null)
+}
+
+fn eval_prefix_expression(operator: &str, right: Object) -> Object {
+    match (operator, right) {
+        ("!", right) => Object::Boolean(!is_truthy(&right)),
+        ("-", Object::Integer(value)) => match value.checked_neg() {
+            Some(value) => Object::Integer(value),
+            None => Object::Error(format!("integer overflow: -{}", value)),
+        },
+        (operator, right) => Object::Error(format!(
+            "unknown operator: {}{}",
+            operator,
+            right.object_type()
+        )),
+    }
+}
+
+fn eval_infix_expression(operator: &str, left: Object, right: Object) -> Object {
+    match (left, right) {
+        (Object::Integer(left), Object::Integer(right)) => {
+            eval_integer_infix_expression(operator, left, right)
+        }
+        (Object::String(left), Object::String(right)) => {
+            eval_string_infix_expression(operator, left, right)
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if operator == "==" => {
+            Object::Boolean(left == right)
+        }
+        (Object::Boolean(left), Object::Boolean(right)) if operator == "!=" => {
+            Object::Boolean(left != right)
+        }
+        (left, right) if left.object_type() != right.object_type() => Object::Error(format!(
+            "type mismatch: {} {} {}",
+            left.object_type(),
+            operator,
+            right.object_type()
+        )),
+        (left, right) => Object::Error(format!(
+            "unknown operator: {} {} {}",
+            left.object_type(),
+            operator,
+            right.object_type()
+        )),
+    }
+}
+
+fn eval_integer_infix_expression(operator: &str, left: i64, right: i64) -> Object {
+    let value = match operator {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" if right == 0 => return Object::Error("division by zero".to_string()),
+        "/" => left.checked_div(right),
+        "<" => return Object::Boolean(left < right),
+        ">" => return Object::Boolean(left > right),
+        "==" => return Object::Boolean(left == right),
+        "!=" => return Object::Boolean(left != right),
+        _ => return Object::Error(format!("unknown operator: INTEGER {} INTEGER", operator)),
+    };
+
+    match value {
+        Some(value) => Object::Integer(value),
+        None => Object::Error(format!("integer overflow: {} {} {}", left, operator, right)),
+    }
+}
+
+fn eval_string_infix_expression(operator: &str, left: String, right: String) -> Object {
+    match operator {
+        "+" => Object::String(left + &right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => Object::Error(format!("unknown operator: STRING {} STRING", operator)),
+    }
+}
+
+fn is_truthy(obj: &Object) -> bool {
+    match obj {
+        Object::Null => false,
+        Object::Boolean(value) => *value,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ast::Node, lexer::Lexer, object::Environment, parser::Parser};
+
+    use super::{eval, with_eval_stack, Object};
+
+    fn test_eval(input: &str) -> Option<Object> {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = match p.parse_program() {
+            Ok(program) => program,
+            Err(errors) => panic!("parser has errors for {:?}: {:?}", input, errors),
+        };
+        let env = Environment::new();
+
+        eval(&program, &env)
+    }
+
+    fn test_integer_object(obj: Option<Object>, expected: i64) -> bool {
+        match obj {
+            Some(Object::Integer(value)) if value == expected => true,
+            Some(Object::Integer(value)) => {
+                println!("object has wrong value. got={}, want={}", value, expected);
+                false
+            }
+            obj => {
+                println!("object is not Integer. got={:?}", obj);
+                false
+            }
+        }
+    }
+
+    fn test_boolean_object(obj: Option<Object>, expected: bool) -> bool {
+        match obj {
+            Some(Object::Boolean(value)) if value == expected => true,
+            Some(Object::Boolean(value)) => {
+                println!("object has wrong value. got={}, want={}", value, expected);
+                false
+            }
+            obj => {
+                println!("object is not Boolean. got={:?}", obj);
+                false
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_integer_expression() {
+        let tests = [
+            ("5", 5),
+            ("10", 10),
+            ("-5", -5),
+            ("-10", -10),
+            ("5 + 5 + 5 + 5 - 10", 10),
+            ("2 * 2 * 2 * 2 * 2", 32),
+            ("-50 + 100 + -50", 0),
+            ("5 * 2 + 10", 20),
+            ("5 + 2 * 10", 25),
+            ("20 + 2 * -10", 0),
+            ("50 / 2 * 2 + 10", 60),
+            ("3 * 3 * 3 + 10", 37),
+        ];
+
+        for (input, expected) in tests {
+            if !test_integer_object(test_eval(input), expected) {
+                panic!("test_integer_object failed for {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_eval_boolean_expression() {
+        let tests = [
+            ("1 < 2", true),
+            ("1 > 2", false),
+            ("1 < 1", false),
+            ("1 == 1", true),
+            ("1 != 1", false),
+            ("1 == 2", false),
+            ("1 < 2 == 2 > 1", true),
+            ("1 < 2 != 2 > 1", false),
+            ("\"a\" == \"a\"", true),
+            ("\"a\" != \"a\"", false),
+        ];
+
+        for (input, expected) in tests {
+            if !test_boolean_object(test_eval(input), expected) {
+                panic!("test_boolean_object failed for {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bang_operator() {
+        let tests = [
+            ("!5", false),
+            ("!!5", true),
+            ("let t = 1 < 2; !t", false),
+            ("let t = 1 < 2; !!t", true),
+            ("!\"\"", false),
+        ];
+
+        for (input, expected) in tests {
+            if !test_boolean_object(test_eval(input), expected) {
+                panic!("test_boolean_object failed for {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_return_statements() {
+        let tests = [
+            ("return 10;", 10),
+            ("return 10; 9;", 10),
+            ("return 2 * 5; 9;", 10),
+            ("9; return 2 * 5; 9;", 10),
+        ];
+
+        for (input, expected) in tests {
+            if !test_integer_object(test_eval(input), expected) {
+                panic!("test_integer_object failed for {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let tests = [
+            ("let t = 1 < 2; 5 + t;", "type mismatch: INTEGER + BOOLEAN"),
+            (
+                "let t = 1 < 2; 5 + t; 5;",
+                "type mismatch: INTEGER + BOOLEAN",
+            ),
+            ("let t = 1 < 2; -t", "unknown operator: -BOOLEAN"),
+            (
+                "let t = 1 < 2; t + t;",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            (
+                "let t = 1 < 2; 5; t + t; 5",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("foobar", "identifier not found: foobar"),
+            ("\"Hello\" - \"World\"", "unknown operator: STRING - STRING"),
+            ("10 / 0", "division by zero"),
+            (
+                "9223372036854775807 + 1",
+                "integer overflow: 9223372036854775807 + 1",
+            ),
+            ("let x = y; x", "identifier not found: y"),
+        ];
+
+        for (input, expected) in tests {
+            match test_eval(input) {
+                Some(Object::Error(message)) => {
+                    if message != expected {
+                        panic!(
+                            "wrong error message for {:?}. expected={:?}, got={:?}",
+                            input, expected, message
+                        );
+                    }
+                }
+                obj => panic!("no error object returned for {:?}. got={:?}", input, obj),
+            }
+        }
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let tests = [
+            ("let a = 5; a;", 5),
+            ("let a = 5 * 5; a;", 25),
+            ("let a = 5; let b = a; b;", 5),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", 15),
+        ];
+
+        for (input, expected) in tests {
+            if !test_integer_object(test_eval(input), expected) {
+                panic!("test_integer_object failed for {:?}", input);
+            }
+        }
+
+        if test_eval("let a = 5;").is_some() {
+            panic!("let statement should not produce a value");
+        }
+    }
+
+    #[test]
+    fn test_string_concatenation() {
+        match test_eval("\"Hello\" + \" \" + \"World!\"") {
+            Some(Object::String(value)) if value == "Hello World!" => {}
+            obj => panic!("object is not String \"Hello World!\". got={:?}", obj),
+        }
+    }
+
+    #[test]
+    fn test_if_else_expressions() {
+        let tests = [
+            ("if (true) { 10 }", Some(10)),
+            ("if (false) { 10 }", None),
+            ("if (1) { 10 }", Some(10)),
+            ("if (1 < 2) { 10 }", Some(10)),
+            ("if (1 > 2) { 10 }", None),
+            ("if (1 > 2) { 10 } else { 20 }", Some(20)),
+            ("if (1 < 2) { 10 } else { 20 }", Some(10)),
+            ("if (1 < 2) { let x = 10; }", None),
+        ];
+
+        for (input, expected) in tests {
+            let evaluated = test_eval(input);
+            match expected {
+                Some(expected) => {
+                    if !test_integer_object(evaluated, expected) {
+                        panic!("test_integer_object failed for {:?}", input);
+                    }
+                }
+                None => {
+                    if !matches!(evaluated, Some(Object::Null)) {
+                        panic!("object is not Null for {:?}. got={:?}", input, evaluated);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_nested_return_statements() {
+        let input = "
+        if (10 > 1) {
+          if (10 > 1) {
+            return 10;
+          }
+
+          return 1;
+        }
+        ";
+
+        if !test_integer_object(test_eval(input), 10) {
+            panic!("test_integer_object failed for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_boolean_literals() {
+        let tests = [
+            ("true", true),
+            ("false", false),
+            ("!true", false),
+            ("!!false", false),
+            ("true == true", true),
+            ("true != false", true),
+            ("(1 < 2) == true", true),
+            ("(1 > 2) == true", false),
+        ];
+
+        for (input, expected) in tests {
+            if !test_boolean_object(test_eval(input), expected) {
+                panic!("test_boolean_object failed for {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_function_object() {
+        let input = "fn(x) { x + 2; };";
+
+        let func = match test_eval(input) {
+            Some(Object::Function(func)) => func,
+            obj => panic!("object is not Function. got={:?}", obj),
+        };
+
+        if func.parameters.len() != 1 {
+            panic!(
+                "function has wrong parameters. Parameters={:?}",
+                func.parameters
+            );
+        }
+        if func.parameters[0].value != "x" {
+            panic!("parameter is not 'x'. got={:?}", func.parameters[0]);
+        }
+        if func.body.string() != "(x + 2)" {
+            panic!("body is not (x + 2). got={}", func.body.string());
+        }
+    }
+
+    #[test]
+    fn test_function_application() {
+        let tests = [
+            ("let identity = fn(x) { x; }; identity(5);", 5),
+            ("let identity = fn(x) { return x; }; identity(5);", 5),
+            ("let double = fn(x) { x * 2; }; double(5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", 10),
+            ("let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));", 20),
+            ("fn(x) { x; }(5)", 5),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(10);",
+                55,
+            ),
+        ];
+
+        for (input, expected) in tests {
+            if !test_integer_object(test_eval(input), expected) {
+                panic!("test_integer_object failed for {:?}", input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_closures() {
+        let input = "
+        let newAdder = fn(x) {
+          fn(y) { x + y };
+        };
+
+        let addTwo = newAdder(2);
+        addTwo(2);";
+
+        if !test_integer_object(test_eval(input), 4) {
+            panic!("test_integer_object failed for {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_function_errors() {
+        let tests = [
+            ("true + false;", "unknown operator: BOOLEAN + BOOLEAN"),
+            (
+                "if (10 > 1) { return true + false; }",
+                "unknown operator: BOOLEAN + BOOLEAN",
+            ),
+            ("let x = 5; x(1)", "not a function: INTEGER"),
+            (
+                "fn(x, y) { x }(1)",
+                "wrong number of arguments: want=2, got=1",
+            ),
+            ("fn(x) { x }(y)", "identifier not found: y"),
+        ];
+
+        for (input, expected) in tests {
+            match test_eval(input) {
+                Some(Object::Error(message)) => {
+                    if message != expected {
+                        panic!(
+                            "wrong error message for {:?}. expected={:?}, got={:?}",
+                            input, expected, message
+                        );
+                    }
+                }
+                obj => panic!("no error object returned for {:?}. got={:?}", input, obj),
+            }
+        }
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        with_eval_stack(|| {
+            let sum = "let s = fn(n) { if (n == 0) { 0 } else { n + s(n - 1) } };";
+            let input = format!("{} s(1000)", sum);
+            if !test_integer_object(test_eval(&input), 500500) {
+                panic!("test_integer_object failed for {:?}", input);
+            }
+
+            let input = format!("{} s(100000)", sum);
+            match test_eval(&input) {
+                Some(Object::Error(message)) if message == "stack overflow" => {}
+                obj => panic!("runaway recursion not stopped. got={:?}", obj),
+            }
+        });
+    }
+}
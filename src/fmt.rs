@@ -0,0 +1,650 @@
+//! The canonical Monkey source formatter.
+//!
+//! The program is turned into a document of text, line breaks and groups,
+//! which is then laid out so that each group is kept on one line if it fits
+//! in the line width and broken across lines otherwise. Comments are not part
+//! of the AST, so they are attached to the statement next to them: a comment
+//! on its own line stays before the following statement, and a comment on the
+//! same line as the end of a statement (or inside it, outside any nested
+//! block) follows that statement. A single blank line between statements is
+//! kept.
+
+use crate::{
+    ast::{BlockStatement, Expression, Identifier, Node, Program, Statement},
+    lexer::Lexer,
+    parser::{ParseError, Parser},
+    token::Span,
+};
+
+pub const DEFAULT_WIDTH: usize = 80;
+const INDENT: usize = 4;
+
+const LOWEST: u8 = 0;
+const EQUALS: u8 = 1;
+const LESS_GREATER: u8 = 2;
+const SUM: u8 = 3;
+const PRODUCT: u8 = 4;
+const PREFIX: u8 = 5;
+const CALL: u8 = 6;
+
+/// Formats `source` with the default line width.
+pub fn format(source: &str) -> Result<String, Vec<ParseError>> {
+    format_with_width(source, DEFAULT_WIDTH)
+}
+
+/// Formats `source`, breaking call arguments and function parameters across
+/// lines when they would not fit in `width` columns.
+pub fn format_with_width(source: &str, width: usize) -> Result<String, Vec<ParseError>> {
+    let mut p = Parser::new(Lexer::with_comments(source));
+    let program = p.parse_program()?;
+
+    let mut formatter = Formatter {
+        source,
+        comments: p.comments().to_vec(),
+        used: vec![false; p.comments().len()],
+    };
+    let doc = formatter.program(&program);
+
+    let mut out = render(&doc, width);
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[derive(Debug)]
+enum Doc {
+    Text(String),
+    /// A space, or a newline if the enclosing group is broken.
+    Line,
+    /// Nothing, or a newline if the enclosing group is broken.
+    SoftLine,
+    /// Always a newline. Breaks every enclosing group.
+    HardLine,
+    /// Breaks every enclosing group without printing anything.
+    BreakParent,
+    Concat(Vec<Doc>),
+    /// Indents the lines started inside it.
+    Nest(Box<Doc>),
+    Group(Box<Doc>),
+}
+
+fn text(s: impl Into<String>) -> Doc {
+    Doc::Text(s.into())
+}
+
+fn nest(doc: Doc) -> Doc {
+    Doc::Nest(Box::new(doc))
+}
+
+fn group(doc: Doc) -> Doc {
+    Doc::Group(Box::new(doc))
+}
+
+impl Doc {
+    fn has_forced_break(&self) -> bool {
+        match self {
+            Self::HardLine | Self::BreakParent => true,
+            Self::Text(_) | Self::Line | Self::SoftLine => false,
+            Self::Concat(docs) => docs.iter().any(Doc::has_forced_break),
+            Self::Nest(doc) | Self::Group(doc) => doc.has_forced_break(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut stack = vec![(0, Mode::Break, doc)];
+
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(s) => {
+                out.push_str(s);
+                column = match s.rfind('\n') {
+                    Some(newline) => s[newline + 1..].chars().count(),
+                    None => column + s.chars().count(),
+                };
+            }
+            Doc::Line if mode == Mode::Flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => {
+                // Blank lines carry no indentation.
+                out.truncate(out.trim_end_matches(' ').len());
+                out.push('\n');
+                out.push_str(&" ".repeat(indent));
+                column = indent;
+            }
+            Doc::BreakParent => {}
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            Doc::Nest(doc) => stack.push((indent + INDENT, mode, doc)),
+            Doc::Group(doc) => {
+                let flat = mode == Mode::Flat
+                    || (!doc.has_forced_break()
+                        && fits(width as isize - column as isize, doc, &stack));
+                let mode = if flat { Mode::Flat } else { Mode::Break };
+                stack.push((indent, mode, doc));
+            }
+        }
+    }
+
+    out
+}
+
+/// Reports whether `doc` laid out flat, followed by `rest` up to its next
+/// line break, fits in `remaining` columns.
+fn fits(mut remaining: isize, doc: &Doc, rest: &[(usize, Mode, &Doc)]) -> bool {
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut rest = rest.iter().rev().map(|&(_, mode, doc)| (mode, doc));
+
+    while remaining >= 0 {
+        let (mode, doc) = match stack.pop().or_else(|| rest.next()) {
+            Some(next) => next,
+            None => return true,
+        };
+        match doc {
+            Doc::Text(s) => match s.split_once('\n') {
+                Some((first_line, _)) => return remaining >= first_line.chars().count() as isize,
+                None => remaining -= s.chars().count() as isize,
+            },
+            Doc::Line if mode == Mode::Flat => remaining -= 1,
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => return true,
+            Doc::BreakParent => {}
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (mode, doc))),
+            Doc::Nest(doc) | Doc::Group(doc) => stack.push((mode, doc)),
+        }
+    }
+
+    false
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: Vec<Span>,
+    used: Vec<bool>,
+}
+
+impl Formatter<'_> {
+    fn program(&mut self, program: &Program) -> Doc {
+        self.statements(&program.statements, 0, self.source.len(), false)
+    }
+
+    /// Lays out `statements` one per line together with the comments between
+    /// `start` and `end`.
+    fn statements(
+        &mut self,
+        statements: &[Statement],
+        start: usize,
+        end: usize,
+        in_block: bool,
+    ) -> Doc {
+        // Each item is a comment or statement with its first and last source
+        // line, used to keep blank lines.
+        let mut items: Vec<(usize, usize, Doc)> = Vec::new();
+        let mut position = start;
+
+        for (i, statement) in statements.iter().enumerate() {
+            let span = statement.span();
+            let leading = self.take_comments(position, span.start.offset, |_| true);
+            items.extend(self.comment_lines(&leading, true));
+
+            let is_last = i + 1 == statements.len();
+            let mut doc = vec![self.statement(statement, in_block && is_last)];
+            let mut end_line = span.end.line;
+
+            let next = statements
+                .get(i + 1)
+                .map_or(end, |next| next.span().start.offset);
+            let trailing = self.take_comments(span.start.offset, next, |comment| {
+                comment.start.offset < span.end.offset || comment.start.line == span.end.line
+            });
+            for (i, (_, comment_end, line)) in
+                self.comment_lines(&trailing, false).into_iter().enumerate()
+            {
+                doc.push(if i == 0 { text(" ") } else { Doc::HardLine });
+                doc.push(line);
+                end_line = end_line.max(comment_end);
+            }
+
+            items.push((span.start.line, end_line, Doc::Concat(doc)));
+            position = span.end.offset;
+        }
+
+        let dangling = self.take_comments(position, end, |_| true);
+        items.extend(self.comment_lines(&dangling, true));
+
+        let mut docs = Vec::new();
+        let mut previous_end = None;
+        for (start_line, end_line, doc) in items {
+            if let Some(previous_end) = previous_end {
+                docs.push(Doc::HardLine);
+                if start_line > previous_end + 1 {
+                    docs.push(Doc::HardLine);
+                }
+            }
+            docs.push(doc);
+            previous_end = Some(end_line);
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Joins `comments` with spaces into lines, with their first and last
+    /// source line. A line comment always ends its line, and with
+    /// `keep_lines` so does the end of a source line.
+    fn comment_lines(&self, comments: &[Span], keep_lines: bool) -> Vec<(usize, usize, Doc)> {
+        let mut lines: Vec<(usize, usize, Vec<Doc>)> = Vec::new();
+        let mut line_open = false;
+        for comment in comments {
+            let comment_text = self.text(*comment);
+            match lines.last_mut() {
+                Some((_, end_line, docs))
+                    if line_open && (!keep_lines || *end_line == comment.start.line) =>
+                {
+                    docs.push(text(" "));
+                    docs.push(text(comment_text));
+                    *end_line = comment.end.line;
+                }
+                _ => lines.push((
+                    comment.start.line,
+                    comment.end.line,
+                    vec![text(comment_text)],
+                )),
+            }
+            line_open = !comment_text.starts_with("//");
+            if !line_open {
+                // Nothing may follow a line comment on its line.
+                lines.last_mut().unwrap().2.push(Doc::BreakParent);
+            }
+        }
+
+        lines
+            .into_iter()
+            .map(|(start_line, end_line, docs)| (start_line, end_line, Doc::Concat(docs)))
+            .collect()
+    }
+
+    /// Removes and returns the unused comments starting in `start..end` that
+    /// satisfy `filter`.
+    fn take_comments(
+        &mut self,
+        start: usize,
+        end: usize,
+        filter: impl Fn(&Span) -> bool,
+    ) -> Vec<Span> {
+        let mut taken = Vec::new();
+        for (comment, used) in self.comments.iter().zip(self.used.iter_mut()) {
+            if !*used && (start..end).contains(&comment.start.offset) && filter(comment) {
+                *used = true;
+                taken.push(*comment);
+            }
+        }
+        taken
+    }
+
+    fn has_comments(&self, start: usize, end: usize) -> bool {
+        self.comments
+            .iter()
+            .zip(&self.used)
+            .any(|(comment, used)| !used && (start..end).contains(&comment.start.offset))
+    }
+
+    fn text(&self, span: Span) -> &str {
+        self.source[span.start.offset..span.end.offset].trim_end()
+    }
+
+    /// `omit_semicolon` is set for the last statement of a block, whose value
+    /// is the value of the block.
+    fn statement(&mut self, statement: &Statement, omit_semicolon: bool) -> Doc {
+        match statement {
+            Statement::LetStatement(let_stmt) => Doc::Concat(vec![
+                text(format!("let {} = ", let_stmt.name.value)),
+                self.optional_expression(&let_stmt.value),
+                text(";"),
+            ]),
+            Statement::ReturnStatement(return_stmt) => Doc::Concat(vec![
+                text("return "),
+                self.optional_expression(&return_stmt.return_value),
+                text(";"),
+            ]),
+            Statement::ExpressionStatement(expression_stmt) => {
+                let mut docs = vec![self.optional_expression(&expression_stmt.expression)];
+                if !omit_semicolon {
+                    docs.push(text(";"));
+                }
+                Doc::Concat(docs)
+            }
+            Statement::Error(error) => text(self.text(error.span)),
+        }
+    }
+
+    fn optional_expression(&mut self, expression: &Option<Expression>) -> Doc {
+        match expression {
+            Some(expression) => self.expression(expression),
+            None => text(""),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Doc {
+        match expression {
+            Expression::Identifier(identifier) => text(identifier.value.as_str()),
+            Expression::IntegerLiteral(integer_literal) => text(integer_literal.value.to_string()),
+            Expression::StringLiteral(string_literal) => text(string_literal.string()),
+            Expression::Boolean(boolean) => text(boolean.value.to_string()),
+            Expression::Prefix(prefix) => Doc::Concat(vec![
+                text(prefix.operator.as_str()),
+                self.operand(&prefix.right, PREFIX, false),
+            ]),
+            Expression::Infix(infix) => {
+                let precedence = infix_precedence(&infix.operator);
+                Doc::Concat(vec![
+                    self.operand(&infix.left, precedence, false),
+                    text(format!(" {} ", infix.operator)),
+                    self.operand(&infix.right, precedence, true),
+                ])
+            }
+            Expression::If(if_expression) => {
+                let mut docs = vec![
+                    text("if ("),
+                    self.expression(&if_expression.condition),
+                    text(") "),
+                    self.block(&if_expression.consequence),
+                ];
+                if let Some(alternative) = &if_expression.alternative {
+                    docs.push(text(" else "));
+                    docs.push(self.block(alternative));
+                }
+                Doc::Concat(docs)
+            }
+            Expression::FunctionLiteral(function_literal) => {
+                let parameters = function_literal
+                    .parameters
+                    .iter()
+                    .map(|Identifier { value, .. }| text(value.as_str()))
+                    .collect();
+                Doc::Concat(vec![
+                    text("fn"),
+                    list(parameters),
+                    text(" "),
+                    self.block(&function_literal.body),
+                ])
+            }
+            Expression::Call(call) => {
+                let arguments = call
+                    .arguments
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect();
+                Doc::Concat(vec![
+                    self.operand(&call.function, CALL, false),
+                    list(arguments),
+                ])
+            }
+            Expression::Error(error) => text(self.text(error.span)),
+        }
+    }
+
+    /// Formats an operand of an operator with `precedence`, parenthesized if
+    /// it would otherwise bind differently. Infix operators are left
+    /// associative, so a right operand of equal precedence needs parentheses.
+    fn operand(&mut self, expression: &Expression, precedence: u8, is_right: bool) -> Doc {
+        let own = match expression {
+            Expression::Infix(infix) => infix_precedence(&infix.operator),
+            Expression::Prefix(_) => PREFIX,
+            _ => CALL,
+        };
+        let doc = self.expression(expression);
+        if own < precedence || (is_right && own == precedence) {
+            Doc::Concat(vec![text("("), doc, text(")")])
+        } else {
+            doc
+        }
+    }
+
+    fn block(&mut self, block: &BlockStatement) -> Doc {
+        let start = block.token.span.end.offset;
+        let end = block.rbrace.span.start.offset;
+        let has_comments = self.has_comments(start, end);
+
+        if block.statements.is_empty() && !has_comments {
+            return text("{}");
+        }
+
+        let collapsible = !has_comments
+            && matches!(
+                block.statements.as_slice(),
+                [Statement::ExpressionStatement(_)]
+            );
+        let body = self.statements(&block.statements, start, end, true);
+        if collapsible {
+            group(Doc::Concat(vec![
+                text("{"),
+                nest(Doc::Concat(vec![Doc::Line, body])),
+                Doc::Line,
+                text("}"),
+            ]))
+        } else {
+            Doc::Concat(vec![
+                text("{"),
+                nest(Doc::Concat(vec![Doc::HardLine, body])),
+                Doc::HardLine,
+                text("}"),
+            ])
+        }
+    }
+}
+
+/// A parenthesized, comma-separated list that puts each element on its own
+/// line when it does not fit on one.
+fn list(elements: Vec<Doc>) -> Doc {
+    if elements.is_empty() {
+        return text("()");
+    }
+
+    let mut inner = vec![Doc::SoftLine];
+    for (i, element) in elements.into_iter().enumerate() {
+        if i > 0 {
+            inner.push(text(","));
+            inner.push(Doc::Line);
+        }
+        inner.push(element);
+    }
+    group(Doc::Concat(vec![
+        text("("),
+        nest(Doc::Concat(inner)),
+        Doc::SoftLine,
+        text(")"),
+    ]))
+}
+
+fn infix_precedence(operator: &str) -> u8 {
+    match operator {
+        "==" | "!=" => EQUALS,
+        "<" | ">" => LESS_GREATER,
+        "+" | "-" => SUM,
+        "*" | "/" => PRODUCT,
+        _ => LOWEST,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    use super::{format, format_with_width};
+
+    #[test]
+    fn test_format() {
+        let tests = [
+            ("", ""),
+            ("let   x=5", "let x = 5;\n"),
+            ("return x*y", "return x * y;\n"),
+            ("1+2*3;(1+2)*3", "1 + 2 * 3;\n(1 + 2) * 3;\n"),
+            ("a-(b-c); (a-b)-c; -(a+b); -a*b", "a - (b - c);\na - b - c;\n-(a + b);\n-a * b;\n"),
+            ("a<b==true; !(a==b)", "a < b == true;\n!(a == b);\n"),
+            ("(-f)(x); (f)(x); -f(x)", "(-f)(x);\nf(x);\n-f(x);\n"),
+            ("\"a\\tb\\u{41}\"", "\"a\\tbA\";\n"),
+            (
+                "let add=fn(x,y){x+y};add(1,2)",
+                "let add = fn(x, y) { x + y };\nadd(1, 2);\n",
+            ),
+            ("let f = fn() {}", "let f = fn() {};\n"),
+            (
+                "if(x>1){x}else{let y=x;y*2}",
+                "if (x > 1) { x } else {\n    let y = x;\n    y * 2\n};\n",
+            ),
+            (
+                "let f = fn(n) { if (n < 2) { return n; }; f(n - 1) + f(n - 2) };",
+                "let f = fn(n) {\n    if (n < 2) {\n        return n;\n    };\n    f(n - 1) + f(n - 2)\n};\n",
+            ),
+            ("let a = 1;\n\n\n\nlet b = 2;\nlet c = 3;", "let a = 1;\n\nlet b = 2;\nlet c = 3;\n"),
+        ];
+
+        for (input, expected) in tests {
+            match format(input) {
+                Ok(output) if output == expected => {}
+                result => panic!(
+                    "format({:?}) wrong.\nexpected={:?}\ngot=     {:?}",
+                    input, expected, result
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let input = "let result = combine(firstArgument, secondArgument, fn(a, b) { a + b });";
+
+        let expected = "let result = combine(
+    firstArgument,
+    secondArgument,
+    fn(a, b) { a + b }
+);
+";
+        let output = format_with_width(input, 40).unwrap();
+        if output != expected {
+            panic!(
+                "wrapped output wrong.\nexpected=\n{}\ngot=\n{}",
+                expected, output
+            );
+        }
+
+        let input = "let f = fn(alpha, beta, gamma, delta) { alpha };";
+        let expected = "let f = fn(
+    alpha,
+    beta,
+    gamma,
+    delta
+) { alpha };
+";
+        let output = format_with_width(input, 30).unwrap();
+        if output != expected {
+            panic!(
+                "wrapped output wrong.\nexpected=\n{}\ngot=\n{}",
+                expected, output
+            );
+        }
+
+        let output = format_with_width(input, 80).unwrap();
+        if output != "let f = fn(alpha, beta, gamma, delta) { alpha };\n" {
+            panic!("unwrapped output wrong. got=\n{}", output);
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// header
+
+let x = 5; // five
+/* before y */ let y = x  /* inner */ + 1;
+let f = fn(a) { // opening
+    // leading
+    a * 2 // double
+
+    // dangling
+};
+f(x) /* a */ /* b */
+// the end
+";
+
+        let expected = "// header
+
+let x = 5; // five
+/* before y */
+let y = x + 1; /* inner */
+let f = fn(a) {
+    // opening
+    // leading
+    a * 2 // double
+
+    // dangling
+};
+f(x); /* a */ /* b */
+// the end
+";
+        match format(input) {
+            Ok(output) if output == expected => {}
+            result => panic!("format wrong.\nexpected=\n{}\ngot=\n{:?}", expected, result),
+        }
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let inputs = [
+            "let x = 5; let y = fn(a, b) { if (a > b) { a } else { b } }; y(x, 10)",
+            "let f = fn(n) { if (n < 2) { return n; } f(n - 1) + f(n - 2) }; f(20);",
+            "let compose = fn(f, g) { fn(x) { g(f(x)) } };\n\n\ncompose(fn(a) { a + 1 }, fn(b) { b * 2 })(5)",
+            "let a = -(1 + 2) * -3 - (4 - (5 - 6)) / !(true == false);",
+            "add(1, // one\n 2) /* sum */; // done\nlet s = \"line\\nbreak\";",
+            "/*\n * block\n */\nlet x = 1;\n  /* multi\n     line */ let y = 2;",
+            "let longName = someFunction(argumentNumberOne, argumentNumberTwo, argumentNumberThree, fn(p, q) { p + q });",
+            "if (x) { } else { /* nothing */ }",
+            "// only a comment",
+        ];
+
+        for input in inputs {
+            let once = match format(input) {
+                Ok(once) => once,
+                Err(errors) => panic!("format({:?}) failed: {:?}", input, errors),
+            };
+            let twice = format(&once).unwrap();
+            if once != twice {
+                panic!(
+                    "format is not idempotent for {:?}.\nonce=\n{}\ntwice=\n{}",
+                    input, once, twice
+                );
+            }
+
+            let before = parse(input).unwrap().string();
+            let after = parse(&once).unwrap().string();
+            if before != after {
+                panic!(
+                    "format changed the program {:?}.\nbefore={}\nafter= {}",
+                    input, before, after
+                );
+            }
+
+            let comments = |s: &str| s.matches("//").count() + s.matches("/*").count();
+            if comments(input) != comments(&once) {
+                panic!("format lost comments of {:?}. got=\n{}", input, once);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        match format("let = 1;") {
+            Err(errors) if errors.len() == 1 => {}
+            result => panic!("expected one parse error. got={:?}", result),
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Incremental reparsing of edited source text.
+//!
+//! A [`Document`] remembers, for every top-level statement, where it starts
+//! and how far into the input the lexer had read once the statement was
+//! parsed. An edit can only change statements whose parse read past the
+//! start of the edit, so parsing restarts at the first of those. It stops as
+//! soon as the parser reaches a token that started an old statement after the
+//! edit: from there on the input is unchanged, so the remaining statements
+//! are reused with their positions shifted. The result is always identical to
+//! parsing the whole new text.
+
+use std::ops::Range;
+
+use crate::{
+    ast::{ErrorNode, Folder, Program},
+    lexer::Lexer,
+    parser::{ParseError, Parser},
+    token::{Position, Span, Token},
+};
+
+/// Replaces the bytes in `range` of the previous text with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl Edit {
+    pub fn new(range: Range<usize>, replacement: impl Into<String>) -> Self {
+        Edit {
+            range,
+            replacement: replacement.into(),
+        }
+    }
+}
+
+/// How many top-level statements an edit reused or parsed again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Reparse {
+    /// Statements before the edit that were kept as they were.
+    pub reused_before: usize,
+    pub reparsed: usize,
+    /// Statements after the edit that were kept and shifted.
+    pub reused_after: usize,
+}
+
+/// What the parse of one top-level statement depended on.
+#[derive(Debug, Clone)]
+struct Entry {
+    start: Position,
+    /// Byte offset up to which the lexer had read when the statement ended.
+    examined: usize,
+    /// Errors reported while parsing the statement.
+    errors: Vec<ParseError>,
+}
+
+/// A source text together with its parse, kept up to date across edits.
+#[derive(Debug, Default)]
+pub struct Document {
+    text: String,
+    program: Program,
+    /// Errors reported while reading the first two tokens.
+    leading_errors: Vec<ParseError>,
+    /// One entry per statement of `program`.
+    entries: Vec<Entry>,
+}
+
+impl Document {
+    pub fn new(text: impl Into<String>) -> Self {
+        let mut document = Document::default();
+        document.edit(&Edit::new(0..0, text));
+        document
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Returns the errors of the current parse in the order a full parse
+    /// reports them.
+    pub fn errors(&self) -> impl Iterator<Item = &ParseError> {
+        self.leading_errors
+            .iter()
+            .chain(self.entries.iter().flat_map(|entry| &entry.errors))
+    }
+
+    /// Applies `edit` to the text and updates the parse.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds or does not lie on `char`
+    /// boundaries.
+    pub fn edit(&mut self, edit: &Edit) -> Reparse {
+        let Range { start, end } = edit.range;
+
+        // The lexer reads one character past each token, so a statement is
+        // only safe if its parse stopped reading strictly before the edit.
+        let reused_before = self
+            .entries
+            .iter()
+            .take_while(|entry| entry.examined < start)
+            .count();
+        // The last statement read up to the end of the input, so a statement
+        // always follows the reused ones.
+        let restart = if reused_before == 0 {
+            Position::default()
+        } else {
+            self.entries[reused_before].start
+        };
+
+        let old_end = restart.advance(&self.text[restart.offset..end]);
+        self.text.replace_range(start..end, &edit.replacement);
+        let new_end = restart.advance(&self.text[restart.offset..start + edit.replacement.len()]);
+        let shift = Shift { old_end, new_end };
+
+        let old_statements = self.program.statements.split_off(reused_before);
+        let old_entries = self.entries.split_off(reused_before);
+
+        let mut parser = Parser::new(Lexer::starting_at(&self.text, restart));
+        // Reading the first two tokens of a later statement repeats errors
+        // already recorded with the statement before it.
+        let leading_errors = parser.take_errors();
+        if reused_before == 0 {
+            self.leading_errors = leading_errors;
+        }
+
+        let mut reparsed = 0;
+        let resume = loop {
+            let position = parser.position();
+            if let Some(index) = shift.unshift(position.offset).and_then(|offset| {
+                old_entries
+                    .binary_search_by_key(&offset, |entry| entry.start.offset)
+                    .ok()
+            }) {
+                break Some(index);
+            }
+
+            let Some(statement) = parser.next_statement() else {
+                break None;
+            };
+            self.program.statements.push(statement);
+            self.entries.push(Entry {
+                start: position,
+                examined: parser.examined(),
+                errors: parser.take_errors(),
+            });
+            reparsed += 1;
+        };
+
+        let mut reused_after = 0;
+        if let Some(index) = resume {
+            let mut shift = shift;
+            for (statement, entry) in old_statements.into_iter().zip(old_entries).skip(index) {
+                self.program
+                    .statements
+                    .push(shift.fold_statement(statement));
+                self.entries.push(Entry {
+                    start: shift.position(entry.start),
+                    examined: shift.offset(entry.examined),
+                    errors: entry
+                        .errors
+                        .into_iter()
+                        .map(|error| shift.error(error))
+                        .collect(),
+                });
+                reused_after += 1;
+            }
+        }
+
+        Reparse {
+            reused_before,
+            reparsed,
+            reused_after,
+        }
+    }
+}
+
+/// Moves positions at or after the end of an edit to where the same text is
+/// after the edit.
+#[derive(Debug, Clone, Copy)]
+struct Shift {
+    old_end: Position,
+    new_end: Position,
+}
+
+impl Shift {
+    fn offset(&self, offset: usize) -> usize {
+        offset - self.old_end.offset + self.new_end.offset
+    }
+
+    /// Maps an offset in the new text back to the old one, if it lies after
+    /// the edit.
+    fn unshift(&self, offset: usize) -> Option<usize> {
+        (offset >= self.new_end.offset).then(|| offset - self.new_end.offset + self.old_end.offset)
+    }
+
+    fn position(&self, position: Position) -> Position {
+        let column = if position.line == self.old_end.line {
+            position.column - self.old_end.column + self.new_end.column
+        } else {
+            position.column
+        };
+        Position::new(
+            position.line - self.old_end.line + self.new_end.line,
+            column,
+            self.offset(position.offset),
+        )
+    }
+
+    fn span(&self, span: Span) -> Span {
+        Span::new(self.position(span.start), self.position(span.end))
+    }
+
+    fn error(&self, mut error: ParseError) -> ParseError {
+        let span = error.span_mut();
+        *span = self.span(*span);
+        error
+    }
+}
+
+impl Folder for Shift {
+    fn fold_error_node(&mut self, error: ErrorNode) -> ErrorNode {
+        ErrorNode {
+            token: self.fold_token(error.token),
+            span: self.span(error.span),
+        }
+    }
+
+    fn fold_token(&mut self, token: Token<'static>) -> Token<'static> {
+        Token {
+            span: self.span(token.span),
+            ..token
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Document, Edit, Reparse};
+    use crate::parse_with_recovery;
+
+    fn check(document: &Document) {
+        let (program, errors) = parse_with_recovery(document.text());
+        let got = format!("{:?}", document.program());
+        if got != format!("{:?}", program) {
+            panic!(
+                "program of {:?} differs from a full parse. got={}",
+                document.text(),
+                got
+            );
+        }
+        let got: Vec<_> = document.errors().cloned().collect();
+        if got != errors {
+            panic!(
+                "errors of {:?} differ from a full parse. expected={:?}, got={:?}",
+                document.text(),
+                errors,
+                got
+            );
+        }
+    }
+
+    #[test]
+    fn test_reuse() {
+        let text = "let a = 1;\nlet b = fn(x) {\n  x * 2\n};\nlet c = b(a);\nc;\n";
+        let tests = [
+            // Inside the second statement, on a line of its own.
+            (
+                Edit::new(
+                    text.find("2").unwrap()..text.find("2").unwrap() + 1,
+                    "3 + 4",
+                ),
+                Reparse {
+                    reused_before: 1,
+                    reparsed: 1,
+                    reused_after: 2,
+                },
+            ),
+            // Inserting a whole statement between two others.
+            (
+                Edit::new(text.find("c;").unwrap()..text.find("c;").unwrap(), "é;\n"),
+                Reparse {
+                    reused_before: 2,
+                    reparsed: 2,
+                    reused_after: 1,
+                },
+            ),
+            // Opening a block comment swallows the rest of the input.
+            (
+                Edit::new(
+                    text.find("let b").unwrap()..text.find("let b").unwrap(),
+                    "/*",
+                ),
+                Reparse {
+                    reused_before: 0,
+                    reparsed: 1,
+                    reused_after: 0,
+                },
+            ),
+            (
+                Edit::new(0..text.len(), ""),
+                Reparse {
+                    reused_before: 0,
+                    reparsed: 0,
+                    reused_after: 0,
+                },
+            ),
+        ];
+
+        for (edit, expected) in tests {
+            let mut document = Document::new(text);
+            let reparse = document.edit(&edit);
+            if reparse != expected {
+                panic!(
+                    "reparse of {:?} wrong. expected={:?}, got={:?}",
+                    edit, expected, reparse
+                );
+            }
+            check(&document);
+        }
+    }
+
+    #[test]
+    fn test_random_edits() {
+        let pieces = [
+            "",
+            "let ",
+            "x",
+            " ",
+            "\n",
+            "\r\n",
+            " = ",
+            "5",
+            ";",
+            "(",
+            ")",
+            "{",
+            "}",
+            ", ",
+            " + ",
+            "*",
+            "!",
+            "==",
+            "fn(a) { a }",
+            "if (x) { 1 } else { 2 }",
+            "return ",
+            "\"s\"",
+            "\"",
+            "\"\\q\"",
+            "// c\n",
+            "/*",
+            "*/",
+            "é",
+            "@",
+            "true",
+        ];
+        let start = "let a = 1;\nlet add = fn(x, y) {\n  x + y\n};\n// sum\nadd(a, 2);\n\
+                     if (a < 2) { \"é\" } else { -a }\nreturn add;\n";
+
+        let mut seed: u64 = 0x2545f4914f6cdd1d;
+        let mut random = |bound: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % bound as u64) as usize
+        };
+
+        let mut reused = 0;
+        for _ in 0..50 {
+            let mut document = Document::new(start);
+            check(&document);
+            for _ in 0..40 {
+                let text = document.text();
+                let boundaries: Vec<usize> = text
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain([text.len()])
+                    .collect();
+                let first = random(boundaries.len());
+                let last = (first + random(6)).min(boundaries.len() - 1);
+                let edit = Edit::new(
+                    boundaries[first]..boundaries[last],
+                    pieces[random(pieces.len())],
+                );
+                let reparse = document.edit(&edit);
+                reused += reparse.reused_before + reparse.reused_after;
+                check(&document);
+            }
+        }
+        if reused == 0 {
+            panic!("no statements were reused");
+        }
+    }
+}
@@ -0,0 +1,523 @@
+//! A small JSON value type with a parser and a compact serializer.
+//!
+//! Object members keep their source order, and integers that fit in an `i64`
+//! are kept exact rather than going through `f64`.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn object<'a>(members: impl IntoIterator<Item = (&'a str, Value)>) -> Self {
+        Self::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Returns the value of the member `key` if this is an object that has one.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Self::Int(n)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Self::Int(n as i64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Self {
+        Self::Array(values)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Int(n) => write!(f, "{}", n),
+            Self::Float(n) if n.is_finite() => write!(f, "{:?}", n),
+            Self::Float(_) => write!(f, "null"),
+            Self::String(s) => write_string(f, s),
+            Self::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Self::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            ch if ch.is_control() => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub message: String,
+    /// Byte offset into the input where the error was detected.
+    pub offset: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+/// How deeply arrays and objects may nest. Parsing recurses once per level,
+/// so untrusted input must not be able to nest without bound.
+const MAX_DEPTH: usize = 512;
+
+/// Parses a complete JSON document.
+pub fn parse(input: &str) -> Result<Value, JsonError> {
+    let mut parser = JsonParser {
+        input,
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.position != input.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    input: &'a str,
+    position: usize,
+    /// Number of arrays and objects enclosing the current position.
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError {
+            message: message.to_string(),
+            offset: self.position,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), JsonError> {
+        if self.peek() == Some(byte) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", byte as char)))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{' | b'[') if self.depth == MAX_DEPTH => Err(self.error("nesting too deep")),
+            Some(b'{') => {
+                self.depth += 1;
+                let value = self.parse_object();
+                self.depth -= 1;
+                value
+            }
+            Some(b'[') => {
+                self.depth += 1;
+                let value = self.parse_array();
+                self.depth -= 1;
+                value
+            }
+            Some(b'"') => Ok(Value::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(_) => self.parse_literal(),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_literal(&mut self) -> Result<Value, JsonError> {
+        for (literal, value) in [
+            ("null", Value::Null),
+            ("true", Value::Bool(true)),
+            ("false", Value::Bool(false)),
+        ] {
+            if self.input[self.position..].starts_with(literal) {
+                self.position += literal.len();
+                return Ok(value);
+            }
+        }
+        Err(self.error("expected a value"))
+    }
+
+    fn parse_object(&mut self) -> Result<Value, JsonError> {
+        let mut members = Vec::new();
+        self.expect(b'{')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a member name"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            members.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, JsonError> {
+        let mut values = Vec::new();
+        self.expect(b'[')?;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        let mut value = String::new();
+        self.expect(b'"')?;
+        loop {
+            let rest = &self.input[self.position..];
+            let ch = match rest.chars().next() {
+                Some(ch) => ch,
+                None => return Err(self.error("unterminated string")),
+            };
+            match ch {
+                '"' => {
+                    self.position += 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            self.position += 1;
+                            value.push(self.parse_unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.position += 1;
+                    value.push(escaped);
+                }
+                ch if ch.is_control() => return Err(self.error("control character in string")),
+                ch => {
+                    self.position += ch.len_utf8();
+                    value.push(ch);
+                }
+            }
+        }
+    }
+
+    /// Parses the hex digits of a `\u` escape, combining surrogate pairs.
+    fn parse_unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.parse_hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.input[self.position..].starts_with("\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.position += 2;
+            let low = self.parse_hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("unpaired surrogate"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self
+            .input
+            .get(self.position..self.position + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("expected 4 hex digits"))?;
+        self.position += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+
+    fn parse_number(&mut self) -> Result<Value, JsonError> {
+        let start = self.position;
+        let mut is_float = false;
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        while let Some(b) = self.peek() {
+            match b {
+                b'0'..=b'9' => {}
+                b'.' | b'e' | b'E' | b'+' | b'-' => is_float = true,
+                _ => break,
+            }
+            self.position += 1;
+        }
+
+        let text = &self.input[start..self.position];
+        if !is_float {
+            if let Ok(n) = text.parse() {
+                return Ok(Value::Int(n));
+            }
+        }
+        text.parse().map(Value::Float).map_err(|_| JsonError {
+            message: format!("invalid number {}", text),
+            offset: start,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Value};
+
+    #[test]
+    fn test_parse() {
+        let input = r#" {"a": [1, -2, 3.5, 1e3], "b": {"c": null, "d": true, "e": false},
+            "s": "q\"\\\/\b\f\n\r\t\u00e9\ud83d\ude00", "big": 9223372036854775807} "#;
+
+        let value = parse(input).unwrap();
+
+        let expected = Value::object([
+            (
+                "a",
+                Value::Array(vec![
+                    Value::Int(1),
+                    Value::Int(-2),
+                    Value::Float(3.5),
+                    Value::Float(1000.0),
+                ]),
+            ),
+            (
+                "b",
+                Value::object([
+                    ("c", Value::Null),
+                    ("d", Value::Bool(true)),
+                    ("e", Value::Bool(false)),
+                ]),
+            ),
+            ("s", Value::from("q\"\\/\u{8}\u{c}\n\r\té😀")),
+            ("big", Value::Int(i64::MAX)),
+        ]);
+        if value != expected {
+            panic!("parse wrong. got={:?}", value);
+        }
+        if value
+            .get("b")
+            .and_then(|b| b.get("d"))
+            .and_then(Value::as_bool)
+            != Some(true)
+        {
+            panic!("get wrong. got={:?}", value.get("b"));
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = Value::object([
+            ("name", Value::from("let x = \"1\";\n\u{1}")),
+            ("items", Value::Array(vec![Value::Int(-7), Value::Null])),
+            ("empty", Value::object([])),
+            ("ratio", Value::Float(0.25)),
+        ]);
+
+        let serialized = value.to_string();
+        let expected =
+            r#"{"name":"let x = \"1\";\n\u0001","items":[-7,null],"empty":{},"ratio":0.25}"#;
+        if serialized != expected {
+            panic!("serialized wrong. got={}", serialized);
+        }
+        if parse(&serialized).as_ref() != Ok(&value) {
+            panic!("round trip wrong. got={:?}", parse(&serialized));
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let tests = [
+            ("", "unexpected end of input at byte 0"),
+            ("[1, 2", "expected ',' or ']' at byte 5"),
+            ("{\"a\" 1}", "expected ':' at byte 5"),
+            ("{1: 2}", "expected a member name at byte 1"),
+            ("\"abc", "unterminated string at byte 4"),
+            ("\"\\x\"", "invalid escape at byte 2"),
+            ("\"\\ud800\"", "unpaired surrogate at byte 7"),
+            ("nul", "expected a value at byte 0"),
+            ("1 2", "trailing characters at byte 2"),
+            ("-", "invalid number - at byte 0"),
+        ];
+
+        for (input, expected) in tests {
+            match parse(input) {
+                Err(err) if err.to_string() == expected => {}
+                result => panic!(
+                    "parse({:?}) wrong. want={:?}, got={:?}",
+                    input, expected, result
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = format!("{}{}", "[".repeat(512), "]".repeat(512));
+        if let Err(err) = parse(&nested) {
+            panic!("parsing 512 nested arrays failed: {}", err);
+        }
+
+        let input = "[{\"a\":".repeat(100_000);
+        match parse(&input) {
+            Err(err) if err.to_string() == "nesting too deep at byte 1536" => {}
+            result => panic!("deep nesting not rejected. got={:?}", result.err()),
+        }
+    }
+}
@@ -1,155 +1,646 @@
-use crate::token::{
-    lookup_ident, Token,
-    TokenType::{
-        self, ASSIGN, ASTERISK, BANG, COMMA, EOF, EQ, GT, ILLEGAL, INT, LBRACE, LPAREN, LT, MINUS,
-        NOTEQ, PLUS, RBRACE, RPAREN, SEMICOLON, SLASH,
+use std::{borrow::Cow, collections::VecDeque, fmt, io::BufRead, iter::FusedIterator};
+
+use crate::{
+    reader::ReaderLexer,
+    token::{
+        lookup_ident, Position, Span, Token,
+        TokenType::{
+            self, ASSIGN, ASTERISK, BANG, COMMA, COMMENT, EOF, EQ, GT, ILLEGAL, INT, LBRACE,
+            LPAREN, LT, MINUS, NOTEQ, PLUS, RBRACE, RPAREN, SEMICOLON, SLASH, STRING, WHITESPACE,
+        },
     },
+    unicode,
 };
 
+/// Character used for `ch` once the whole input has been consumed.
+const EOF_CHAR: char = '\0';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    MixedScriptIdentifier {
+        identifier: String,
+        scripts: Vec<&'static str>,
+        span: Span,
+    },
+    UnterminatedString {
+        span: Span,
+    },
+    InvalidEscape {
+        escape: String,
+        span: Span,
+    },
+    UnterminatedBlockComment {
+        span: Span,
+    },
+    /// A byte sequence that is not valid UTF-8, read by a `ReaderLexer` and
+    /// replaced with U+FFFD.
+    InvalidUtf8 {
+        span: Span,
+    },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::MixedScriptIdentifier { span, .. } => *span,
+            Self::UnterminatedString { span } => *span,
+            Self::InvalidEscape { span, .. } => *span,
+            Self::UnterminatedBlockComment { span } => *span,
+            Self::InvalidUtf8 { span } => *span,
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Self::MixedScriptIdentifier { span, .. } => span,
+            Self::UnterminatedString { span } => span,
+            Self::InvalidEscape { span, .. } => span,
+            Self::UnterminatedBlockComment { span } => span,
+            Self::InvalidUtf8 { span } => span,
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MixedScriptIdentifier {
+                identifier,
+                scripts,
+                ..
+            } => write!(
+                f,
+                "identifier {} mixes confusable scripts: {}",
+                identifier,
+                scripts.join(", ")
+            ),
+            Self::UnterminatedString { .. } => write!(f, "unterminated string literal"),
+            Self::InvalidEscape { escape, .. } => {
+                write!(f, "invalid escape sequence {}", escape)
+            }
+            Self::UnterminatedBlockComment { .. } => write!(f, "unterminated block comment"),
+            Self::InvalidUtf8 { .. } => write!(f, "invalid UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for LexError {}
+
+/// Converts source text into tokens.
+///
+/// `position` and `read_position` are byte offsets into `input`, so every
+/// step of the lexer is O(1) and identifiers and numbers can be sliced out of
+/// the input directly.
 #[derive(Debug, Clone)]
 pub struct Lexer<'a> {
     input: &'a str,
     position: usize,
     read_position: usize,
-    ch: u8,
+    ch: char,
+    line: usize,
+    column: usize,
+    errors: Vec<LexError>,
+    comments: Vec<Span>,
+    /// Whether the spans of skipped comments are recorded in `comments`.
+    record_comments: bool,
+    /// Whether whitespace and comments are returned as tokens.
+    trivia: bool,
+    /// Number of characters decoded, which tests use to check that lexing
+    /// reads each character once.
+    #[cfg(test)]
+    chars_read: usize,
 }
 
-impl Lexer<'_> {
-    pub fn new(input: &str) -> Lexer {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
         let mut l = Lexer {
             input,
             position: 0,
             read_position: 0,
-            ch: b' ',
+            ch: ' ',
+            line: 1,
+            column: 0,
+            errors: Vec::new(),
+            comments: Vec::new(),
+            record_comments: false,
+            trivia: false,
+            #[cfg(test)]
+            chars_read: 0,
+        };
+        l.read_char();
+        l
+    }
+
+    /// Creates a lexer that returns every run of whitespace and every comment
+    /// as a `WHITESPACE` or `COMMENT` token instead of skipping them, so the
+    /// tokens cover the whole input.
+    pub fn with_trivia(input: &'a str) -> Self {
+        let mut l = Lexer::new(input);
+        l.trivia = true;
+        l
+    }
+
+    /// Creates a lexer that skips whitespace and comments like `new`, but
+    /// records the span of every comment it skips. See [`Lexer::comments`].
+    pub fn with_comments(input: &'a str) -> Self {
+        let mut l = Lexer::new(input);
+        l.record_comments = true;
+        l
+    }
+
+    /// Creates a lexer that reads its input from `reader` a chunk at a time
+    /// instead of holding all of it in memory. See [`crate::reader`].
+    pub fn from_reader<R: BufRead>(reader: R) -> ReaderLexer<R> {
+        ReaderLexer::new(reader)
+    }
+
+    /// Creates a lexer that begins at `start`, the position of a token in
+    /// `input`, as if everything before it had already been read.
+    pub fn starting_at(input: &'a str, start: Position) -> Self {
+        let mut l = Lexer {
+            input,
+            read_position: start.offset,
+            line: start.line,
+            column: start.column - 1,
+            ..Lexer::new("")
         };
         l.read_char();
         l
     }
 
-    pub fn next_token(&mut self) -> Token {
+    /// Returns the byte offset up to which the input has been read. Every
+    /// token returned so far depends only on the input before it.
+    pub fn examined(&self) -> usize {
+        self.read_position
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    /// Removes and returns the errors reported since the last call.
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Returns the spans of the comments skipped so far, in source order.
+    /// Always empty unless the lexer was created with `with_comments`.
+    pub fn comments(&self) -> &[Span] {
+        &self.comments
+    }
+
+    pub fn next_token(&mut self) -> Token<'a> {
         let mut tok = Token::new();
 
-        self.skip_whitespace();
+        if !self.trivia {
+            self.skip_whitespace_and_comments();
+        } else if let Some(tok) = self.read_trivia() {
+            return tok;
+        }
+
+        let start = self.current_position();
 
         match self.ch {
-            b'=' => {
-                if self.peek_char() == b'=' {
-                    let ch = self.ch;
+            '=' => {
+                if self.peek_char() == '=' {
                     self.read_char();
-                    let literal = format!("{}{}", ch as char, self.ch as char);
-                    tok = Token {
-                        token_type: EQ,
-                        literal,
-                    }
+                    tok = self.new_token(EQ, start);
                 } else {
-                    tok = new_token(ASSIGN, self.ch);
+                    tok = self.new_token(ASSIGN, start);
                 }
             }
-            b'+' => tok = new_token(PLUS, self.ch),
-            b'-' => tok = new_token(MINUS, self.ch),
-            b'!' => {
-                if self.peek_char() == b'=' {
-                    let ch = self.ch;
+            '+' => tok = self.new_token(PLUS, start),
+            '-' => tok = self.new_token(MINUS, start),
+            '!' => {
+                if self.peek_char() == '=' {
                     self.read_char();
-                    let literal = format!("{}{}", ch as char, self.ch as char);
-                    tok = Token {
-                        token_type: NOTEQ,
-                        literal,
-                    }
+                    tok = self.new_token(NOTEQ, start);
                 } else {
-                    tok = new_token(BANG, self.ch);
+                    tok = self.new_token(BANG, start);
                 }
             }
-            b'/' => tok = new_token(SLASH, self.ch),
-            b'*' => tok = new_token(ASTERISK, self.ch),
-            b'<' => tok = new_token(LT, self.ch),
-            b'>' => tok = new_token(GT, self.ch),
-            b';' => tok = new_token(SEMICOLON, self.ch),
-            b',' => tok = new_token(COMMA, self.ch),
-            b'{' => tok = new_token(LBRACE, self.ch),
-            b'}' => tok = new_token(RBRACE, self.ch),
-            b'(' => tok = new_token(LPAREN, self.ch),
-            b')' => tok = new_token(RPAREN, self.ch),
-            0 => {
-                tok.literal = "".to_string();
+            '/' => tok = self.new_token(SLASH, start),
+            '*' => tok = self.new_token(ASTERISK, start),
+            '<' => tok = self.new_token(LT, start),
+            '>' => tok = self.new_token(GT, start),
+            ';' => tok = self.new_token(SEMICOLON, start),
+            ',' => tok = self.new_token(COMMA, start),
+            '{' => tok = self.new_token(LBRACE, start),
+            '}' => tok = self.new_token(RBRACE, start),
+            '(' => tok = self.new_token(LPAREN, start),
+            ')' => tok = self.new_token(RPAREN, start),
+            '"' => {
+                tok.token_type = STRING;
+                tok.literal = self.read_string(start);
+                tok.span = Span::new(start, self.current_position());
+                return tok;
+            }
+            EOF_CHAR if self.is_at_end() => {
                 tok.token_type = EOF;
+                tok.span = Span::new(start, start);
+                return tok;
             }
             _ => {
-                if is_letter(self.ch) {
-                    tok.literal = self.read_identifier();
+                if is_identifier_start(self.ch) {
+                    tok.literal = unicode::to_nfc(self.read_identifier());
                     tok.token_type = lookup_ident(&tok.literal);
+                    tok.span = Span::new(start, self.current_position());
+                    if let Some(scripts) = unicode::confusable_scripts(&tok.literal) {
+                        self.errors.push(LexError::MixedScriptIdentifier {
+                            identifier: tok.literal.to_string(),
+                            scripts,
+                            span: tok.span,
+                        });
+                    }
                     return tok;
                 } else if self.ch.is_ascii_digit() {
                     tok.token_type = INT;
-                    tok.literal = self.read_number();
+                    tok.literal = Cow::Borrowed(self.read_number());
+                    tok.span = Span::new(start, self.current_position());
                     return tok;
                 } else {
-                    tok = new_token(ILLEGAL, self.ch);
+                    tok = self.new_token(ILLEGAL, start);
                 }
             }
         };
 
         self.read_char();
+        tok.span.end = self.current_position();
         tok
     }
 
+    /// Builds a token whose literal runs from `start` through the current
+    /// character.
+    fn new_token(&self, token_type: TokenType, start: Position) -> Token<'a> {
+        let end = self.read_position;
+        Token {
+            token_type,
+            literal: Cow::Borrowed(&self.input[start.offset..end]),
+            span: Span::new(start, start),
+        }
+    }
+
+    fn current_position(&self) -> Position {
+        Position::new(self.line, self.column, self.position)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.input.len()
+    }
+
     fn read_char(&mut self) {
-        self.ch = self
-            .input
-            .chars()
-            .nth(self.read_position)
-            .map_or(0, |ch| ch as u8);
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 0;
+        }
+        self.column += 1;
+
         self.position = self.read_position;
-        self.read_position += 1;
+        #[cfg(test)]
+        {
+            self.chars_read += 1;
+        }
+        match self.input[self.read_position..].chars().next() {
+            Some(ch) => {
+                self.ch = ch;
+                self.read_position += ch.len_utf8();
+            }
+            None => self.ch = EOF_CHAR,
+        }
     }
 
-    fn peek_char(&self) -> u8 {
-        self.input
+    fn peek_char(&self) -> char {
+        self.input[self.read_position..]
             .chars()
-            .nth(self.read_position)
-            .map_or(0, |ch| ch as u8)
+            .next()
+            .unwrap_or(EOF_CHAR)
     }
 
-    fn read_identifier(&mut self) -> String {
+    fn read_identifier(&mut self) -> &'a str {
         let position = self.position;
-        while is_letter(self.ch) {
+        while is_identifier_continue(self.ch) {
+            self.read_char();
+        }
+        &self.input[position..self.position]
+    }
+
+    /// Reads a run of whitespace or a single comment.
+    fn read_trivia(&mut self) -> Option<Token<'a>> {
+        let start = self.current_position();
+        let token_type = match (self.ch, self.peek_char()) {
+            (ch, _) if ch.is_ascii_whitespace() => {
+                while self.ch.is_ascii_whitespace() {
+                    self.read_char();
+                }
+                WHITESPACE
+            }
+            ('/', '/') => {
+                self.skip_line_comment();
+                COMMENT
+            }
+            ('/', '*') => {
+                self.skip_block_comment();
+                COMMENT
+            }
+            _ => return None,
+        };
+
+        let span = Span::new(start, self.current_position());
+        Some(Token {
+            token_type,
+            literal: Cow::Borrowed(&self.input[start.offset..span.end.offset]),
+            span,
+        })
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            while self.ch.is_ascii_whitespace() {
+                self.read_char();
+            }
+
+            let start = self.current_position();
+            match (self.ch, self.peek_char()) {
+                ('/', '/') => self.skip_line_comment(),
+                ('/', '*') => self.skip_block_comment(),
+                _ => break,
+            }
+            if self.record_comments {
+                self.comments
+                    .push(Span::new(start, self.current_position()));
+            }
+        }
+    }
+
+    fn skip_line_comment(&mut self) {
+        while self.ch != '\n' && !self.is_at_end() {
             self.read_char();
         }
-        self.input[position..self.position].to_string()
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch.is_ascii_whitespace() {
+    /// Skips a `/* ... */` comment, which may contain nested block comments.
+    fn skip_block_comment(&mut self) {
+        let start = self.current_position();
+        let mut depth = 0;
+
+        loop {
+            match (self.ch, self.peek_char()) {
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char();
+                }
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char();
+                    if depth == 0 {
+                        self.read_char();
+                        return;
+                    }
+                }
+                (EOF_CHAR, _) if self.is_at_end() => {
+                    let end = Position::new(start.line, start.column + 2, start.offset + 2);
+                    self.errors.push(LexError::UnterminatedBlockComment {
+                        span: Span::new(start, end),
+                    });
+                    return;
+                }
+                _ => {}
+            }
+            self.read_char();
+        }
+    }
+
+    /// Reads a double-quoted string starting at the opening quote and returns
+    /// its value with escape sequences resolved. The value borrows from the
+    /// input unless the string contains an escape sequence.
+    fn read_string(&mut self, start: Position) -> Cow<'a, str> {
+        let mut value: Option<String> = None;
+
+        self.read_char();
+        let content_start = self.position;
+        let content_end = loop {
+            match self.ch {
+                '"' => {
+                    let end = self.position;
+                    self.read_char();
+                    break end;
+                }
+                EOF_CHAR if self.is_at_end() => {
+                    self.errors.push(LexError::UnterminatedString {
+                        span: Span::new(start, self.current_position()),
+                    });
+                    break self.position;
+                }
+                '\\' => {
+                    let value = value.get_or_insert_with(|| {
+                        self.input[content_start..self.position].to_string()
+                    });
+                    let escape_start = self.current_position();
+                    self.read_char();
+                    let ch = match self.ch {
+                        'n' => Some('\n'),
+                        't' => Some('\t'),
+                        '\\' => Some('\\'),
+                        '"' => Some('"'),
+                        'u' => self.read_unicode_escape(),
+                        EOF_CHAR if self.is_at_end() => continue,
+                        _ => None,
+                    };
+                    self.read_char();
+
+                    match ch {
+                        Some(ch) => value.push(ch),
+                        None => self.errors.push(LexError::InvalidEscape {
+                            escape: self.input[escape_start.offset..self.position].to_string(),
+                            span: Span::new(escape_start, self.current_position()),
+                        }),
+                    }
+                }
+                ch => {
+                    if let Some(value) = &mut value {
+                        value.push(ch);
+                    }
+                    self.read_char();
+                }
+            }
+        };
+
+        match value {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&self.input[content_start..content_end]),
+        }
+    }
+
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if self.peek_char() != '{' {
+            return None;
+        }
+        self.read_char();
+
+        let mut value = 0;
+        let mut digits = 0;
+        while digits < 6 {
+            match self.peek_char().to_digit(16) {
+                Some(digit) => value = value * 16 + digit,
+                None => break,
+            }
+            digits += 1;
             self.read_char();
         }
+
+        if digits == 0 || self.peek_char() != '}' {
+            return None;
+        }
+        self.read_char();
+
+        char::from_u32(value)
     }
 
-    fn read_number(&mut self) -> String {
+    fn read_number(&mut self) -> &'a str {
         let position = self.position;
         while self.ch.is_ascii_digit() {
             self.read_char();
         }
-        self.input[position..self.position].to_string()
+        &self.input[position..self.position]
     }
 }
 
-fn new_token(token_type: TokenType, ch: u8) -> Token {
-    Token {
-        token_type,
-        literal: (ch as char).to_string(),
+/// Identifiers follow UAX #31 (`XID_Start XID_Continue*`), additionally
+/// allowing a leading underscore.
+fn is_identifier_start(ch: char) -> bool {
+    ch == '_' || unicode::is_xid_start(ch)
+}
+
+fn is_identifier_continue(ch: char) -> bool {
+    unicode::is_xid_continue(ch)
+}
+
+/// Yields the tokens of the input, ending before `EOF`.
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        let tok = self.next_token();
+        (tok.token_type != EOF).then_some(tok)
     }
 }
 
-fn is_letter(ch: u8) -> bool {
-    ch.is_ascii_alphabetic() || ch == b'_'
+impl FusedIterator for Lexer<'_> {}
+
+/// A position in a `TokenStream` to come back to with `rewind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint(usize);
+
+/// Buffers the tokens of a lexer so that any number of tokens can be looked
+/// at ahead of the current one, and so that a parser can save its place with
+/// `checkpoint` and return to it with `rewind` to try another parse.
+///
+/// After the end of the input the current token stays at `EOF`. Tokens
+/// behind the current one are dropped unless a checkpoint still refers to
+/// them.
+#[derive(Debug, Clone)]
+pub struct TokenStream<'a> {
+    lexer: Lexer<'a>,
+    buffer: VecDeque<Token<'a>>,
+    /// Index in the whole stream of `buffer[0]`.
+    start: usize,
+    /// Index in the whole stream of the current token.
+    position: usize,
+    /// Positions saved by `checkpoint` and not yet rewound to or committed,
+    /// innermost last.
+    checkpoints: Vec<usize>,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(mut lexer: Lexer<'a>) -> Self {
+        let first = lexer.next_token();
+        TokenStream {
+            lexer,
+            buffer: VecDeque::from([first]),
+            start: 0,
+            position: 0,
+            checkpoints: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> &Token<'a> {
+        &self.buffer[self.position - self.start]
+    }
+
+    /// Returns the token `k` places after the current one, reading it from
+    /// the lexer if needed. `peek(0)` is the current token.
+    pub fn peek(&mut self, k: usize) -> &Token<'a> {
+        let index = self.position - self.start + k;
+        while self.buffer.len() <= index {
+            let tok = self.lexer.next_token();
+            self.buffer.push_back(tok);
+        }
+        &self.buffer[index]
+    }
+
+    /// Moves to the next token.
+    pub fn advance(&mut self) {
+        self.peek(1);
+        self.position += 1;
+
+        let keep = self.checkpoints.first().copied().unwrap_or(self.position);
+        while self.start < keep {
+            self.buffer.pop_front();
+            self.start += 1;
+        }
+    }
+
+    /// Saves the current position. Every checkpoint must later be passed to
+    /// either `rewind` or `commit`, innermost first.
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.checkpoints.push(self.position);
+        Checkpoint(self.position)
+    }
+
+    /// Returns to `checkpoint`, so the tokens read since are read again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `checkpoint` is not the innermost pending checkpoint.
+    pub fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.commit(checkpoint);
+        self.position = checkpoint.0;
+    }
+
+    /// Keeps the tokens read since `checkpoint`, which can no longer be
+    /// rewound to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `checkpoint` is not the innermost pending checkpoint.
+    pub fn commit(&mut self, checkpoint: Checkpoint) {
+        match self.checkpoints.pop() {
+            Some(position) if position == checkpoint.0 => {}
+            _ => panic!("{:?} is not the innermost checkpoint", checkpoint),
+        }
+    }
+
+    pub fn lexer(&self) -> &Lexer<'a> {
+        &self.lexer
+    }
+
+    /// Removes and returns the errors the lexer reported since the last call.
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        self.lexer.take_errors()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Lexer;
-    use crate::token::TokenType::{
-        ASSIGN, ASTERISK, BANG, COMMA, ELSE, EOF, EQ, FALSE, FUNCTION, GT, IDENT, IF, INT, LBRACE,
-        LET, LPAREN, LT, MINUS, NOTEQ, PLUS, RBRACE, RETURN, RPAREN, SEMICOLON, SLASH, TRUE,
+
+    use super::{LexError, Lexer, TokenStream};
+    use crate::token::{
+        Position, Span,
+        TokenType::{
+            ASSIGN, ASTERISK, BANG, COMMA, COMMENT, ELSE, EOF, EQ, FALSE, FUNCTION, GT, IDENT, IF,
+            ILLEGAL, INT, LBRACE, LET, LPAREN, LT, MINUS, NOTEQ, PLUS, RBRACE, RETURN, RPAREN,
+            SEMICOLON, SLASH, STRING, TRUE, WHITESPACE,
+        },
     };
 
     #[test]
@@ -162,7 +653,7 @@ mod tests {
         };
 
         let result = add(five, ten);
-        !-/*5;
+        !-/ *5;
         5 < 10 > 5;
 
         if (5 < 10) {
@@ -272,4 +763,490 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "let five = 5;\n  five == 10;\n";
+
+        let tests = [
+            ((1, 1, 0), (1, 4, 3)),
+            ((1, 5, 4), (1, 9, 8)),
+            ((1, 10, 9), (1, 11, 10)),
+            ((1, 12, 11), (1, 13, 12)),
+            ((1, 13, 12), (1, 14, 13)),
+            ((2, 3, 16), (2, 7, 20)),
+            ((2, 8, 21), (2, 10, 23)),
+            ((2, 11, 24), (2, 13, 26)),
+            ((2, 13, 26), (2, 14, 27)),
+            ((3, 1, 28), (3, 1, 28)),
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for (i, (start, end)) in tests.into_iter().enumerate() {
+            let tok = l.next_token();
+            let expected = Span::new(
+                Position::new(start.0, start.1, start.2),
+                Position::new(end.0, end.1, end.2),
+            );
+
+            if tok.span != expected {
+                panic!(
+                    "tests[{}] - span wrong for {:?}. expected={:?}, got={:?}",
+                    i, tok.literal, expected, tok.span
+                );
+            }
+            if input[tok.span.start.offset..tok.span.end.offset] != tok.literal {
+                panic!(
+                    "tests[{}] - span does not cover literal {:?}",
+                    i, tok.literal
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_input() {
+        let input = "5 ≠ 6;\n🐒 x";
+
+        let tests = [
+            (INT, "5", (1, 1, 0)),
+            (ILLEGAL, "≠", (1, 3, 2)),
+            (INT, "6", (1, 5, 6)),
+            (SEMICOLON, ";", (1, 6, 7)),
+            (ILLEGAL, "🐒", (2, 1, 9)),
+            (IDENT, "x", (2, 3, 14)),
+            (EOF, "", (2, 4, 15)),
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for (i, (token_type, literal, start)) in tests.into_iter().enumerate() {
+            let tok = l.next_token();
+
+            if tok.token_type != token_type {
+                panic!(
+                    "tests[{}] - tokentype wrong. expected={:?}, got={:?}",
+                    i, token_type, tok.token_type
+                );
+            }
+            if tok.literal != literal {
+                panic!(
+                    "tests[{}] - Literal wrong. expected={}, got={}",
+                    i, literal, tok.literal
+                );
+            }
+            let start = Position::new(start.0, start.1, start.2);
+            if tok.span.start != start {
+                panic!(
+                    "tests[{}] - span.start wrong. expected={:?}, got={:?}",
+                    i, start, tok.span.start
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_lexing_scales_linearly() {
+        let chunk = "let résumé = add(five, 10) * -3 != 7; // note\n/* block */ \"str\\n\"\n";
+        for count in [1, 5_000, 40_000] {
+            let input = chunk.repeat(count);
+            let mut l = Lexer::new(&input);
+            while l.next_token().token_type != EOF {}
+
+            // Each step of the lexer is O(1), so reading every character once
+            // (plus the end of input) makes lexing linear in the input.
+            let chars = input.chars().count();
+            if l.chars_read > chars + 1 {
+                panic!("lexing {} characters read {} of them", chars, l.chars_read);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unicode_identifiers() {
+        let input = "let 変数 = 5; let café = x1 + _y; let cafe\u{301} = 3; let 名前の長さ = 変数;";
+
+        let tests = [
+            (LET, "let"),
+            (IDENT, "変数"),
+            (ASSIGN, "="),
+            (INT, "5"),
+            (SEMICOLON, ";"),
+            (LET, "let"),
+            (IDENT, "café"),
+            (ASSIGN, "="),
+            (IDENT, "x1"),
+            (PLUS, "+"),
+            (IDENT, "_y"),
+            (SEMICOLON, ";"),
+            (LET, "let"),
+            (IDENT, "café"),
+            (ASSIGN, "="),
+            (INT, "3"),
+            (SEMICOLON, ";"),
+            (LET, "let"),
+            (IDENT, "名前の長さ"),
+            (ASSIGN, "="),
+            (IDENT, "変数"),
+            (SEMICOLON, ";"),
+            (EOF, ""),
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for (i, tt) in tests.into_iter().enumerate() {
+            let tok = l.next_token();
+
+            if tok.token_type != tt.0 {
+                panic!(
+                    "tests[{}] - tokentype wrong. expected={:?}, got={:?}",
+                    i, tt.0, tok.token_type
+                );
+            }
+
+            if tok.literal != tt.1 {
+                panic!(
+                    "tests[{}] - Literal wrong. expected={}, got={}",
+                    i, tt.1, tok.literal
+                )
+            }
+        }
+
+        if !l.errors().is_empty() {
+            panic!("lexer has errors: {:?}", l.errors());
+        }
+    }
+
+    #[test]
+    fn test_mixed_script_identifiers() {
+        let input = "let pаypal = 1;";
+
+        let mut l = Lexer::new(input);
+        while l.next_token().token_type != EOF {}
+
+        let expected = LexError::MixedScriptIdentifier {
+            identifier: "pаypal".to_string(),
+            scripts: vec!["Latin", "Cyrillic"],
+            span: Span::new(Position::new(1, 5, 4), Position::new(1, 11, 11)),
+        };
+        if l.errors() != [expected.clone()] {
+            panic!(
+                "errors wrong. expected={:?}, got={:?}",
+                [expected],
+                l.errors()
+            );
+        }
+        if expected.to_string() != "identifier pаypal mixes confusable scripts: Latin, Cyrillic" {
+            panic!("error message wrong. got={}", expected);
+        }
+    }
+
+    #[test]
+    fn test_string_literals() {
+        let input = r#""foobar" "foo bar" "" "a\tb\nc" "say \"hi\" \\o/" "\u{41}\u{1F412}""#;
+
+        let tests = [
+            (STRING, "foobar"),
+            (STRING, "foo bar"),
+            (STRING, ""),
+            (STRING, "a\tb\nc"),
+            (STRING, "say \"hi\" \\o/"),
+            (STRING, "A🐒"),
+            (EOF, ""),
+        ];
+
+        let mut l = Lexer::new(input);
+
+        for (i, tt) in tests.into_iter().enumerate() {
+            let tok = l.next_token();
+
+            if tok.token_type != tt.0 {
+                panic!(
+                    "tests[{}] - tokentype wrong. expected={:?}, got={:?}",
+                    i, tt.0, tok.token_type
+                );
+            }
+
+            if tok.literal != tt.1 {
+                panic!(
+                    "tests[{}] - Literal wrong. expected={:?}, got={:?}",
+                    i, tt.1, tok.literal
+                )
+            }
+        }
+
+        if !l.errors().is_empty() {
+            panic!("lexer has errors: {:?}", l.errors());
+        }
+    }
+
+    #[test]
+    fn test_string_literal_errors() {
+        let tests = [
+            (
+                r#""abc"#,
+                LexError::UnterminatedString {
+                    span: Span::new(Position::new(1, 1, 0), Position::new(1, 5, 4)),
+                },
+            ),
+            (
+                r#""a\qb""#,
+                LexError::InvalidEscape {
+                    escape: r"\q".to_string(),
+                    span: Span::new(Position::new(1, 3, 2), Position::new(1, 5, 4)),
+                },
+            ),
+            (
+                r#""\u{110000}""#,
+                LexError::InvalidEscape {
+                    escape: r"\u{110000}".to_string(),
+                    span: Span::new(Position::new(1, 2, 1), Position::new(1, 12, 11)),
+                },
+            ),
+            (
+                r#""\u{12""#,
+                LexError::InvalidEscape {
+                    escape: r"\u{12".to_string(),
+                    span: Span::new(Position::new(1, 2, 1), Position::new(1, 7, 6)),
+                },
+            ),
+            (
+                r#""abc\"#,
+                LexError::UnterminatedString {
+                    span: Span::new(Position::new(1, 1, 0), Position::new(1, 6, 5)),
+                },
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut l = Lexer::new(input);
+
+            let tok = l.next_token();
+            if tok.token_type != STRING {
+                panic!("tokentype wrong for {:?}. got={:?}", input, tok.token_type);
+            }
+            if l.next_token().token_type != EOF {
+                panic!("expected EOF after {:?}", input);
+            }
+
+            if l.errors() != [expected.clone()] {
+                panic!(
+                    "errors wrong for {:?}. expected={:?}, got={:?}",
+                    input,
+                    [expected],
+                    l.errors()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "// leading comment
+        let x = 5; // trailing comment
+        /* block */ x /* nested /* inner */ still comment */ / 2;
+        /*
+         * multi-line
+         */
+        x * 3 //";
+
+        let tests = [
+            (LET, "let"),
+            (IDENT, "x"),
+            (ASSIGN, "="),
+            (INT, "5"),
+            (SEMICOLON, ";"),
+            (IDENT, "x"),
+            (SLASH, "/"),
+            (INT, "2"),
+            (SEMICOLON, ";"),
+            (IDENT, "x"),
+            (ASTERISK, "*"),
+            (INT, "3"),
+            (EOF, ""),
+        ];
+
+        let mut l = Lexer::with_comments(input);
+
+        for (i, tt) in tests.into_iter().enumerate() {
+            let tok = l.next_token();
+
+            if tok.token_type != tt.0 {
+                panic!(
+                    "tests[{}] - tokentype wrong. expected={:?}, got={:?}",
+                    i, tt.0, tok.token_type
+                );
+            }
+
+            if tok.literal != tt.1 {
+                panic!(
+                    "tests[{}] - Literal wrong. expected={}, got={}",
+                    i, tt.1, tok.literal
+                )
+            }
+        }
+
+        if !l.errors().is_empty() {
+            panic!("lexer has errors: {:?}", l.errors());
+        }
+
+        let mut plain = Lexer::new(input);
+        while plain.next_token().token_type != EOF {}
+        if !plain.comments().is_empty() {
+            panic!(
+                "comments recorded without with_comments. got={:?}",
+                plain.comments()
+            );
+        }
+
+        let comments: Vec<&str> = l
+            .comments()
+            .iter()
+            .map(|span| &input[span.start.offset..span.end.offset])
+            .collect();
+        let expected = [
+            "// leading comment",
+            "// trailing comment",
+            "/* block */",
+            "/* nested /* inner */ still comment */",
+            "/*\n         * multi-line\n         */",
+            "//",
+        ];
+        if comments != expected {
+            panic!("comments wrong. got={:?}", comments);
+        }
+        if l.comments()[1].start != Position::new(2, 20, 38) {
+            panic!("comments[1] start wrong. got={:?}", l.comments()[1].start);
+        }
+    }
+
+    #[test]
+    fn test_trivia() {
+        let input = "let x = 5; // five\r\n\t/* a /* b */ */x\"é\\n\" /* open";
+
+        let tests = [
+            (LET, "let"),
+            (WHITESPACE, " "),
+            (IDENT, "x"),
+            (WHITESPACE, " "),
+            (ASSIGN, "="),
+            (WHITESPACE, " "),
+            (INT, "5"),
+            (SEMICOLON, ";"),
+            (WHITESPACE, " "),
+            (COMMENT, "// five\r"),
+            (WHITESPACE, "\n\t"),
+            (COMMENT, "/* a /* b */ */"),
+            (IDENT, "x"),
+            (STRING, "é\n"),
+            (WHITESPACE, " "),
+            (COMMENT, "/* open"),
+            (EOF, ""),
+        ];
+
+        let mut l = Lexer::with_trivia(input);
+        let mut text = String::new();
+
+        for (i, tt) in tests.into_iter().enumerate() {
+            let tok = l.next_token();
+
+            if tok.token_type != tt.0 || tok.literal != tt.1 {
+                panic!(
+                    "tests[{}] - token wrong. expected={:?}, got={:?}",
+                    i, tt, tok
+                );
+            }
+            text.push_str(&input[tok.span.start.offset..tok.span.end.offset]);
+        }
+
+        if text != input {
+            panic!("token spans do not cover the input. got={:?}", text);
+        }
+        if l.errors().len() != 1 {
+            panic!("errors wrong. got={:?}", l.errors());
+        }
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let input = "let x = 5;\n  /* outer /* inner */ never closed\n";
+
+        let mut l = Lexer::new(input);
+        while l.next_token().token_type != EOF {}
+
+        let expected = LexError::UnterminatedBlockComment {
+            span: Span::new(Position::new(2, 3, 13), Position::new(2, 5, 15)),
+        };
+        if l.errors() != [expected.clone()] {
+            panic!(
+                "errors wrong. expected={:?}, got={:?}",
+                [expected],
+                l.errors()
+            );
+        }
+    }
+
+    #[test]
+    fn test_iterator() {
+        let literals: Vec<_> = Lexer::new("let x = 5;")
+            .map(|tok| tok.literal.into_owned())
+            .collect();
+        if literals != ["let", "x", "=", "5", ";"] {
+            panic!("tokens wrong. got={:?}", literals);
+        }
+
+        let mut l = Lexer::new("x");
+        l.next();
+        if l.next().is_some() || l.next().is_some() {
+            panic!("lexer yields tokens after the end of the input");
+        }
+    }
+
+    #[test]
+    fn test_token_stream() {
+        let mut tokens = TokenStream::new(Lexer::new("let x = fn(y) { y };"));
+
+        let tests = [(0, "let"), (3, "fn"), (10, ";"), (11, ""), (20, "")];
+        for (k, literal) in tests {
+            if tokens.peek(k).literal != literal {
+                panic!(
+                    "peek({}) wrong. expected={:?}, got={:?}",
+                    k,
+                    literal,
+                    tokens.peek(k)
+                );
+            }
+        }
+
+        let outer = tokens.checkpoint();
+        tokens.advance();
+        tokens.advance();
+        let inner = tokens.checkpoint();
+        tokens.advance();
+        tokens.advance();
+        tokens.rewind(inner);
+        if tokens.current().literal != "=" {
+            panic!("rewind wrong. got={:?}", tokens.current());
+        }
+        tokens.advance();
+        tokens.rewind(outer);
+        if tokens.current().literal != "let" {
+            panic!("rewind wrong. got={:?}", tokens.current());
+        }
+
+        let checkpoint = tokens.checkpoint();
+        for _ in 0..20 {
+            tokens.advance();
+        }
+        tokens.commit(checkpoint);
+        tokens.advance();
+        if tokens.current().token_type != EOF || tokens.buffer.len() > 2 {
+            panic!(
+                "stream past the end wrong. current={:?}, buffered={}",
+                tokens.current(),
+                tokens.buffer.len()
+            );
+        }
+    }
 }
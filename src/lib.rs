@@ -0,0 +1,45 @@
+//! A lexer, parser and interpreter for the Monkey programming language.
+//!
+//! ```
+//! use monkey_rust::ast::Statement;
+//!
+//! let program = monkey_rust::parse("let answer = 6 * 7;").unwrap();
+//! assert!(matches!(program.statements[0], Statement::LetStatement(_)));
+//! assert_eq!(program.string(), "let answer = (6 * 7);");
+//! ```
+
+pub mod ast;
+pub mod code;
+pub mod compiler;
+pub mod cst;
+pub mod diagnostic;
+pub mod evaluator;
+pub mod fmt;
+pub mod incremental;
+pub mod json;
+pub mod lexer;
+pub mod lsp;
+pub mod object;
+pub mod parser;
+pub mod reader;
+pub mod serialize;
+pub mod sexp;
+pub mod token;
+mod unicode;
+pub mod vm;
+
+pub use ast::Program;
+pub use lexer::{LexError, Lexer, TokenStream};
+pub use parser::{ParseError, Parser};
+
+/// Parses `input` as a complete Monkey program.
+pub fn parse(input: &str) -> Result<Program, Vec<ParseError>> {
+    Parser::new(Lexer::new(input)).parse_program()
+}
+
+/// Parses `input`, recovering from syntax errors. The returned `Program`
+/// contains `Error` placeholder nodes wherever parsing failed, alongside one
+/// `ParseError` per failure.
+pub fn parse_with_recovery(input: &str) -> (Program, Vec<ParseError>) {
+    Parser::new(Lexer::new(input)).parse_program_with_recovery()
+}
@@ -0,0 +1,1001 @@
+//! A Language Server Protocol server for Monkey, speaking JSON-RPC over any
+//! reader and writer pair (stdin and stdout in the `lsp` binary).
+//!
+//! Documents are fully re-parsed with error recovery on every change, so
+//! symbols, hover and go-to-definition keep working in files with syntax
+//! errors.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+};
+
+use crate::{
+    ast::{Expression, FunctionLiteral, Node, Program, Statement},
+    json::{self, Value},
+    parse_with_recovery,
+    token::{Position, Span, KEYWORDS},
+    ParseError,
+};
+
+/// The largest message body accepted, well above any document an editor
+/// sends but small enough to allocate.
+const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP enumerations.
+const TEXT_DOCUMENT_SYNC_FULL: i64 = 1;
+const DIAGNOSTIC_SEVERITY_ERROR: i64 = 1;
+const SYMBOL_KIND_FUNCTION: i64 = 12;
+const SYMBOL_KIND_VARIABLE: i64 = 13;
+const COMPLETION_KIND_FUNCTION: i64 = 3;
+const COMPLETION_KIND_VARIABLE: i64 = 6;
+const COMPLETION_KIND_KEYWORD: i64 = 14;
+
+/// Reads one `Content-Length` framed message, returning `None` at end of
+/// input. A frame without a valid length, with a body over
+/// `MAX_CONTENT_LENGTH` bytes or with a body that is not UTF-8 is consumed and
+/// reported as an `InvalidData` error, so the next message can still be read.
+pub fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<String>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+
+    let content_length = content_length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+    })?;
+    if content_length > MAX_CONTENT_LENGTH {
+        io::copy(
+            &mut Read::take(input, content_length as u64),
+            &mut io::sink(),
+        )?;
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is too large", content_length),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message<W: Write>(output: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Serves requests from `input` until the client sends `exit` or closes the
+/// stream. Returns whether the client shut the server down properly first.
+/// Malformed messages are answered with a parse error; only a failure to read
+/// or write the streams ends the loop early.
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<bool> {
+    let mut server = Server::new();
+    loop {
+        let body = match read_message(&mut input) {
+            Ok(Some(body)) => body,
+            Ok(None) => return Ok(false),
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                let response = error_response(Value::Null, PARSE_ERROR, &err.to_string());
+                write_message(&mut output, &response)?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let message = match json::parse(&body) {
+            Ok(message) => message,
+            Err(err) => {
+                let response = error_response(Value::Null, PARSE_ERROR, &err.to_string());
+                write_message(&mut output, &response)?;
+                continue;
+            }
+        };
+        if message.get("method").and_then(Value::as_str) == Some("exit") {
+            return Ok(server.shutdown);
+        }
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handles one request or notification, returning the messages to send
+    /// back: a response for requests, and any notifications it triggers.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let id = message.get("id").cloned();
+        let method = match message.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            // A response to a request we never send, or garbage.
+            None => {
+                return match id {
+                    Some(id) if message.get("result").is_none() => {
+                        vec![error_response(id, INVALID_REQUEST, "missing method")]
+                    }
+                    _ => Vec::new(),
+                }
+            }
+        };
+        let params = message.get("params").unwrap_or(&Value::Null);
+
+        let result = match method {
+            "initialize" => Ok(initialize_result()),
+            "initialized" => return Vec::new(),
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::Null)
+            }
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                let uri = document.and_then(|d| d.get("uri")).and_then(Value::as_str);
+                let text = document.and_then(|d| d.get("text")).and_then(Value::as_str);
+                return match (uri, text) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_string()),
+                    _ => Vec::new(),
+                };
+            }
+            "textDocument/didChange" => {
+                // With full sync the last change holds the whole new text.
+                let uri = document_uri(params);
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Value::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Value::as_str);
+                return match (uri, text) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_string()),
+                    _ => Vec::new(),
+                };
+            }
+            "textDocument/didClose" => {
+                return match document_uri(params) {
+                    Some(uri) => {
+                        self.documents.remove(uri);
+                        vec![publish_diagnostics(uri, Vec::new())]
+                    }
+                    None => Vec::new(),
+                };
+            }
+            "textDocument/documentSymbol" => self.document(params).map(Document::symbols),
+            "textDocument/hover" => self
+                .document_position(params)
+                .map(|(document, offset)| document.hover(offset)),
+            "textDocument/definition" => {
+                self.document_position(params).map(|(document, offset)| {
+                    document.definition(document_uri(params).unwrap(), offset)
+                })
+            }
+            "textDocument/completion" => self
+                .document_position(params)
+                .map(|(document, offset)| document.completion(offset)),
+            _ if id.is_none() => return Vec::new(),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        };
+
+        match (id, result) {
+            (Some(id), Ok(result)) => vec![Value::object([
+                ("jsonrpc", Value::from("2.0")),
+                ("id", id),
+                ("result", result),
+            ])],
+            (Some(id), Err((code, message))) => vec![error_response(id, code, &message)],
+            (None, _) => Vec::new(),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: String) -> Vec<Value> {
+        let document = Document::new(text);
+        let diagnostics = document.diagnostics();
+        self.documents.insert(uri.to_string(), document);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    fn document(&self, params: &Value) -> Result<&Document, (i64, String)> {
+        let uri = document_uri(params)
+            .ok_or_else(|| (INVALID_PARAMS, "missing textDocument.uri".to_string()))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("unknown document {}", uri)))?;
+        Ok(document)
+    }
+
+    fn document_position(&self, params: &Value) -> Result<(&Document, usize), (i64, String)> {
+        let document = self.document(params)?;
+        let position = params.get("position");
+        let line = position.and_then(|p| p.get("line")).and_then(Value::as_i64);
+        let character = position
+            .and_then(|p| p.get("character"))
+            .and_then(Value::as_i64);
+        match (line, character) {
+            (Some(line), Some(character)) if line >= 0 && character >= 0 => {
+                Ok((document, document.offset(line as usize, character as usize)))
+            }
+            _ => Err((INVALID_PARAMS, "missing position".to_string())),
+        }
+    }
+}
+
+fn document_uri(params: &Value) -> Option<&str> {
+    params
+        .get("textDocument")
+        .and_then(|document| document.get("uri"))
+        .and_then(Value::as_str)
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    Value::object([
+        ("jsonrpc", Value::from("2.0")),
+        ("id", id),
+        (
+            "error",
+            Value::object([
+                ("code", Value::from(code)),
+                ("message", Value::from(message)),
+            ]),
+        ),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    Value::object([
+        ("jsonrpc", Value::from("2.0")),
+        ("method", Value::from("textDocument/publishDiagnostics")),
+        (
+            "params",
+            Value::object([
+                ("uri", Value::from(uri)),
+                ("diagnostics", Value::Array(diagnostics)),
+            ]),
+        ),
+    ])
+}
+
+fn initialize_result() -> Value {
+    Value::object([
+        (
+            "capabilities",
+            Value::object([
+                ("textDocumentSync", Value::from(TEXT_DOCUMENT_SYNC_FULL)),
+                ("documentSymbolProvider", Value::from(true)),
+                ("hoverProvider", Value::from(true)),
+                ("definitionProvider", Value::from(true)),
+                ("completionProvider", Value::object([])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Value::object([
+                ("name", Value::from("monkey-lsp")),
+                ("version", Value::from(env!("CARGO_PKG_VERSION"))),
+            ]),
+        ),
+    ])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BindingKind {
+    Let,
+    Function,
+    Parameter,
+}
+
+/// A name introduced by a `let` statement or a function parameter.
+#[derive(Debug)]
+struct Binding {
+    name: String,
+    kind: BindingKind,
+    name_span: Span,
+    /// The whole `let` statement, or the parameter itself.
+    span: Span,
+    /// Shown on hover.
+    detail: String,
+    /// The block or function the binding is visible in.
+    scope: Span,
+    /// Byte offset from which the name refers to this binding.
+    visible_from: usize,
+    /// The `let` binding of the function this binding is nested in.
+    parent: Option<usize>,
+}
+
+/// An occurrence of a name in the source, and the binding it refers to.
+#[derive(Debug)]
+struct Reference {
+    span: Span,
+    binding: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Document {
+    text: String,
+    line_starts: Vec<usize>,
+    errors: Vec<ParseError>,
+    bindings: Vec<Binding>,
+    references: Vec<Reference>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let (program, errors) = parse_with_recovery(&text);
+
+        let mut document = Self {
+            text,
+            line_starts,
+            errors,
+            bindings: Vec::new(),
+            references: Vec::new(),
+        };
+        document.index_program(&program);
+        document
+    }
+
+    fn index_program(&mut self, program: &Program) {
+        let scope = Span::new(Position::default(), self.end_position());
+        for statement in &program.statements {
+            self.index_statement(statement, scope, None);
+        }
+    }
+
+    fn end_position(&self) -> Position {
+        Position::new(
+            self.line_starts.len(),
+            self.text.len() - self.line_starts.last().unwrap() + 1,
+            self.text.len(),
+        )
+    }
+
+    fn index_statement(&mut self, statement: &Statement, scope: Span, parent: Option<usize>) {
+        match statement {
+            Statement::LetStatement(let_stmt) => {
+                let function = match &let_stmt.value {
+                    Some(Expression::FunctionLiteral(function)) => Some(function),
+                    _ => None,
+                };
+                let span = statement.span();
+                // Functions may call themselves; other values only see the
+                // name once the statement is over, so `let x = x + 1`
+                // refers to the previous `x`.
+                let visible_from = match function {
+                    Some(_) => let_stmt.name.token.span.start.offset,
+                    None => span.end.offset,
+                };
+                let binding = self.bind(Binding {
+                    name: let_stmt.name.value.clone(),
+                    kind: if function.is_some() {
+                        BindingKind::Function
+                    } else {
+                        BindingKind::Let
+                    },
+                    name_span: let_stmt.name.token.span,
+                    span,
+                    detail: let_stmt.string(),
+                    scope,
+                    visible_from,
+                    parent,
+                });
+
+                match function {
+                    Some(function) => self.index_function(function, Some(binding)),
+                    None => {
+                        if let Some(value) = &let_stmt.value {
+                            self.index_expression(value, parent);
+                        }
+                    }
+                }
+            }
+            Statement::ReturnStatement(return_stmt) => {
+                if let Some(value) = &return_stmt.return_value {
+                    self.index_expression(value, parent);
+                }
+            }
+            Statement::ExpressionStatement(expression_stmt) => {
+                if let Some(expression) = &expression_stmt.expression {
+                    self.index_expression(expression, parent);
+                }
+            }
+            Statement::Error(_) => {}
+        }
+    }
+
+    fn index_expression(&mut self, expression: &Expression, parent: Option<usize>) {
+        match expression {
+            Expression::Identifier(identifier) => {
+                let span = identifier.token.span;
+                let binding = self.resolve(&identifier.value, span.start.offset);
+                self.references.push(Reference { span, binding });
+            }
+            Expression::Prefix(prefix) => self.index_expression(&prefix.right, parent),
+            Expression::Infix(infix) => {
+                self.index_expression(&infix.left, parent);
+                self.index_expression(&infix.right, parent);
+            }
+            Expression::If(if_expression) => {
+                self.index_expression(&if_expression.condition, parent);
+                let blocks =
+                    std::iter::once(&if_expression.consequence).chain(&if_expression.alternative);
+                for block in blocks {
+                    for statement in &block.statements {
+                        self.index_statement(statement, block.span(), parent);
+                    }
+                }
+            }
+            Expression::FunctionLiteral(function) => self.index_function(function, parent),
+            Expression::Call(call) => {
+                self.index_expression(&call.function, parent);
+                for argument in &call.arguments {
+                    self.index_expression(argument, parent);
+                }
+            }
+            Expression::IntegerLiteral(_)
+            | Expression::StringLiteral(_)
+            | Expression::Boolean(_)
+            | Expression::Error(_) => {}
+        }
+    }
+
+    fn index_function(&mut self, function: &FunctionLiteral, parent: Option<usize>) {
+        let scope = function.span();
+        for parameter in &function.parameters {
+            self.bind(Binding {
+                name: parameter.value.clone(),
+                kind: BindingKind::Parameter,
+                name_span: parameter.token.span,
+                span: parameter.token.span,
+                detail: format!("(parameter) {}", parameter.value),
+                scope,
+                visible_from: parameter.token.span.start.offset,
+                parent,
+            });
+        }
+        for statement in &function.body.statements {
+            self.index_statement(statement, function.body.span(), parent);
+        }
+    }
+
+    fn bind(&mut self, binding: Binding) -> usize {
+        let index = self.bindings.len();
+        self.references.push(Reference {
+            span: binding.name_span,
+            binding: Some(index),
+        });
+        self.bindings.push(binding);
+        index
+    }
+
+    /// Finds the binding `name` refers to at `offset`: the innermost visible
+    /// one, and the latest of those if it was rebound in the same scope.
+    fn resolve(&self, name: &str, offset: usize) -> Option<usize> {
+        self.visible_bindings(offset)
+            .filter(|&i| self.bindings[i].name == name)
+            .max_by_key(|&i| {
+                let binding = &self.bindings[i];
+                (binding.scope.start.offset, binding.visible_from)
+            })
+    }
+
+    fn visible_bindings(&self, offset: usize) -> impl Iterator<Item = usize> + '_ {
+        self.bindings
+            .iter()
+            .enumerate()
+            .filter(move |(_, binding)| {
+                binding.visible_from <= offset
+                    && binding.scope.start.offset <= offset
+                    && offset <= binding.scope.end.offset
+            })
+            .map(|(i, _)| i)
+    }
+
+    fn reference_at(&self, offset: usize) -> Option<&Reference> {
+        self.references.iter().find(|reference| {
+            reference.span.start.offset <= offset && offset <= reference.span.end.offset
+        })
+    }
+
+    fn diagnostics(&self) -> Vec<Value> {
+        self.errors
+            .iter()
+            .map(|err| {
+                Value::object([
+                    ("range", self.range(err.span())),
+                    ("severity", Value::from(DIAGNOSTIC_SEVERITY_ERROR)),
+                    ("source", Value::from("monkey")),
+                    ("message", Value::from(err.to_string())),
+                ])
+            })
+            .collect()
+    }
+
+    fn symbols(&self) -> Value {
+        self.symbols_in(None)
+    }
+
+    fn symbols_in(&self, parent: Option<usize>) -> Value {
+        let symbols = self
+            .bindings
+            .iter()
+            .enumerate()
+            .filter(|(_, binding)| {
+                binding.parent == parent && binding.kind != BindingKind::Parameter
+            })
+            .map(|(i, binding)| {
+                let kind = match binding.kind {
+                    BindingKind::Function => SYMBOL_KIND_FUNCTION,
+                    _ => SYMBOL_KIND_VARIABLE,
+                };
+                Value::object([
+                    ("name", Value::from(binding.name.as_str())),
+                    ("kind", Value::from(kind)),
+                    ("range", self.range(binding.span)),
+                    ("selectionRange", self.range(binding.name_span)),
+                    ("children", self.symbols_in(Some(i))),
+                ])
+            })
+            .collect();
+        Value::Array(symbols)
+    }
+
+    fn hover(&self, offset: usize) -> Value {
+        let reference = match self.reference_at(offset) {
+            Some(reference) => reference,
+            None => return Value::Null,
+        };
+        let binding = match reference.binding {
+            Some(binding) => &self.bindings[binding],
+            None => return Value::Null,
+        };
+
+        Value::object([
+            (
+                "contents",
+                Value::object([
+                    ("kind", Value::from("markdown")),
+                    (
+                        "value",
+                        Value::from(format!("```monkey\n{}\n```", binding.detail)),
+                    ),
+                ]),
+            ),
+            ("range", self.range(reference.span)),
+        ])
+    }
+
+    fn definition(&self, uri: &str, offset: usize) -> Value {
+        match self
+            .reference_at(offset)
+            .and_then(|reference| reference.binding)
+        {
+            Some(binding) => Value::object([
+                ("uri", Value::from(uri)),
+                ("range", self.range(self.bindings[binding].name_span)),
+            ]),
+            None => Value::Null,
+        }
+    }
+
+    fn completion(&self, offset: usize) -> Value {
+        let mut items = Vec::new();
+        let mut seen = Vec::new();
+        let mut visible: Vec<usize> = self.visible_bindings(offset).collect();
+        visible.sort_by(|&a, &b| self.bindings[a].name.cmp(&self.bindings[b].name));
+        for i in visible {
+            let binding = &self.bindings[i];
+            if seen.contains(&binding.name.as_str()) {
+                continue;
+            }
+            seen.push(&binding.name);
+            let kind = match binding.kind {
+                BindingKind::Function => COMPLETION_KIND_FUNCTION,
+                _ => COMPLETION_KIND_VARIABLE,
+            };
+            items.push(Value::object([
+                ("label", Value::from(binding.name.as_str())),
+                ("kind", Value::from(kind)),
+                ("detail", Value::from(binding.detail.as_str())),
+            ]));
+        }
+        for (keyword, _) in KEYWORDS {
+            items.push(Value::object([
+                ("label", Value::from(keyword)),
+                ("kind", Value::from(COMPLETION_KIND_KEYWORD)),
+            ]));
+        }
+        Value::Array(items)
+    }
+
+    fn range(&self, span: Span) -> Value {
+        Value::object([
+            ("start", self.position(span.start.offset)),
+            ("end", self.position(span.end.offset)),
+        ])
+    }
+
+    /// Converts a byte offset into an LSP position, whose `character` counts
+    /// UTF-16 code units.
+    fn position(&self, offset: usize) -> Value {
+        let offset = offset.min(self.text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.line_starts[line]..offset]
+            .encode_utf16()
+            .count();
+        Value::object([
+            ("line", Value::from(line)),
+            ("character", Value::from(character)),
+        ])
+    }
+
+    /// Converts an LSP position into a byte offset, clamping positions past
+    /// the end of a line or of the document.
+    fn offset(&self, line: usize, character: usize) -> usize {
+        let start = match self.line_starts.get(line) {
+            Some(&start) => start,
+            None => return self.text.len(),
+        };
+        let mut units = 0;
+        for (i, ch) in self.text[start..].char_indices() {
+            if units >= character || ch == '\n' {
+                return start + i;
+            }
+            units += ch.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::{self, Value};
+
+    use super::{read_message, run, Server};
+
+    const URI: &str = "file:///test.mk";
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        server.handle(&Value::object([
+            ("jsonrpc", Value::from("2.0")),
+            ("method", Value::from("textDocument/didOpen")),
+            (
+                "params",
+                Value::object([(
+                    "textDocument",
+                    Value::object([
+                        ("uri", Value::from(URI)),
+                        ("languageId", Value::from("monkey")),
+                        ("version", Value::from(1i64)),
+                        ("text", Value::from(text)),
+                    ]),
+                )]),
+            ),
+        ]))
+    }
+
+    fn request(server: &mut Server, method: &str, line: i64, character: i64) -> Value {
+        let mut replies = server.handle(&Value::object([
+            ("jsonrpc", Value::from("2.0")),
+            ("id", Value::from(7i64)),
+            ("method", Value::from(method)),
+            (
+                "params",
+                Value::object([
+                    ("textDocument", Value::object([("uri", Value::from(URI))])),
+                    (
+                        "position",
+                        Value::object([
+                            ("line", Value::from(line)),
+                            ("character", Value::from(character)),
+                        ]),
+                    ),
+                ]),
+            ),
+        ]));
+        if replies.len() != 1 || replies[0].get("id") != Some(&Value::Int(7)) {
+            panic!("expected one response to {}. got={:?}", method, replies);
+        }
+        match replies.pop().unwrap().get("result") {
+            Some(result) => result.clone(),
+            None => panic!("{} has no result", method),
+        }
+    }
+
+    fn range(value: &Value) -> String {
+        let position = |key: &str| {
+            let position = value.get(key).unwrap();
+            format!(
+                "{}:{}",
+                position.get("line").unwrap(),
+                position.get("character").unwrap()
+            )
+        };
+        format!("{}-{}", position("start"), position("end"))
+    }
+
+    #[test]
+    fn test_run() {
+        let mut input = String::new();
+        for body in [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ] {
+            input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        }
+
+        let mut output = Vec::new();
+        if !run(input.as_bytes(), &mut output).unwrap() {
+            panic!("run did not report a clean shutdown");
+        }
+
+        let mut output = output.as_slice();
+        let initialize = json::parse(&read_message(&mut output).unwrap().unwrap()).unwrap();
+        let capabilities = initialize
+            .get("result")
+            .and_then(|result| result.get("capabilities"));
+        if capabilities.and_then(|c| c.get("hoverProvider")) != Some(&Value::Bool(true)) {
+            panic!("initialize result wrong. got={}", initialize);
+        }
+        let shutdown = read_message(&mut output).unwrap().unwrap();
+        if shutdown != r#"{"jsonrpc":"2.0","id":2,"result":null}"# {
+            panic!("shutdown response wrong. got={}", shutdown);
+        }
+        if read_message(&mut output).unwrap().is_some() {
+            panic!("unexpected extra output");
+        }
+    }
+
+    #[test]
+    fn test_malformed_frames() {
+        let mut input = b"Content-Type: text/plain\r\n\r\n".to_vec();
+        input.extend_from_slice(b"Content-Length: 4\r\n\r\n\"\xff\"\n");
+        let nested = "[".repeat(200_000);
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"shutdown"}"#;
+        for body in [nested.as_str(), body] {
+            input.extend_from_slice(
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes(),
+            );
+        }
+        // Skipping the oversized body consumes the rest of the input.
+        input.extend_from_slice(b"Content-Length: 1000000000000\r\n\r\n[]");
+
+        let mut output = Vec::new();
+        run(input.as_slice(), &mut output).unwrap();
+
+        let mut output = output.as_slice();
+        let parse_error = |output: &mut &[u8]| {
+            let reply = json::parse(&read_message(output).unwrap().unwrap()).unwrap();
+            let code = reply.get("error").and_then(|error| error.get("code"));
+            if code != Some(&Value::from(-32700i64)) {
+                panic!(
+                    "malformed frame not reported as a parse error. got={}",
+                    reply
+                );
+            }
+        };
+        for _ in 0..3 {
+            parse_error(&mut output);
+        }
+        let shutdown = read_message(&mut output).unwrap().unwrap();
+        if shutdown != r#"{"jsonrpc":"2.0","id":1,"result":null}"# {
+            panic!("message after malformed frames wrong. got={}", shutdown);
+        }
+        parse_error(&mut output);
+        if read_message(&mut output).unwrap().is_some() {
+            panic!("unexpected extra output");
+        }
+    }
+
+    #[test]
+    fn test_exit_without_shutdown() {
+        let body = r#"{"jsonrpc":"2.0","method":"exit"}"#;
+        let input = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        if run(input.as_bytes(), Vec::new()).unwrap() {
+            panic!("exit without shutdown reported as clean");
+        }
+    }
+
+    #[test]
+    fn test_publish_diagnostics() {
+        let mut server = Server::new();
+        let notifications = open(&mut server, "let x = 1;\nlet = 2;\nlet y = x +;");
+
+        let params = match notifications.as_slice() {
+            [notification] => notification.get("params").unwrap(),
+            _ => panic!("expected one notification. got={:?}", notifications),
+        };
+        let diagnostics = params.get("diagnostics").and_then(Value::as_array).unwrap();
+
+        let got: Vec<(String, &str)> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    range(d.get("range").unwrap()),
+                    d.get("message").and_then(Value::as_str).unwrap(),
+                )
+            })
+            .collect();
+        let expected = [
+            (
+                "1:4-1:5".to_string(),
+                "expected next token to be IDENT, got ASSIGN instead",
+            ),
+            (
+                "2:11-2:12".to_string(),
+                "no prefix parse function for SEMICOLON found",
+            ),
+        ];
+        if got != expected {
+            panic!("diagnostics wrong. got={:?}", got);
+        }
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let mut server = Server::new();
+        open(
+            &mut server,
+            "let x = 1;\nlet add = fn(a, b) {\n  let sum = a + b;\n  sum\n};\nlet = oops;",
+        );
+
+        let symbols = request(&mut server, "textDocument/documentSymbol", 0, 0);
+        let describe = |symbol: &Value| {
+            format!(
+                "{} {} {} {}",
+                symbol.get("name").and_then(Value::as_str).unwrap(),
+                symbol.get("kind").unwrap(),
+                range(symbol.get("range").unwrap()),
+                range(symbol.get("selectionRange").unwrap()),
+            )
+        };
+
+        let symbols = symbols.as_array().unwrap();
+        let got: Vec<String> = symbols.iter().map(describe).collect();
+        if got != ["x 13 0:0-0:9 0:4-0:5", "add 12 1:0-4:1 1:4-1:7"] {
+            panic!("symbols wrong. got={:?}", got);
+        }
+        let children = symbols[1]
+            .get("children")
+            .and_then(Value::as_array)
+            .unwrap();
+        let got: Vec<String> = children.iter().map(describe).collect();
+        if got != ["sum 13 2:2-2:17 2:6-2:9"] {
+            panic!("children wrong. got={:?}", got);
+        }
+    }
+
+    #[test]
+    fn test_hover_and_definition() {
+        let mut server = Server::new();
+        open(
+            &mut server,
+            "let x = 1;\nlet x = x + 1;\nlet f = fn(x) { f(x) };\nf(x);\ny",
+        );
+
+        let tests = [
+            // The `x` in `x + 1` is the first binding.
+            (1, 8, Some("0:4-0:5"), Some("let x = 1;")),
+            // The `x` in `f(x)` inside the function is the parameter.
+            (2, 18, Some("2:11-2:12"), Some("(parameter) x")),
+            // `f` refers to itself inside its body.
+            (2, 16, Some("2:4-2:5"), Some("let f = fn(x) f(x);")),
+            // The top-level `x` after both bindings is the second one.
+            (3, 2, Some("1:4-1:5"), Some("let x = (x + 1);")),
+            // Hovering over a binding's own name.
+            (0, 4, Some("0:4-0:5"), Some("let x = 1;")),
+            // Unbound names and whitespace have nothing.
+            (4, 0, None, None),
+            (1, 13, None, None),
+        ];
+
+        for (line, character, definition, hover) in tests {
+            let result = request(&mut server, "textDocument/definition", line, character);
+            let got = result.get("range").map(range);
+            if got.as_deref() != definition {
+                panic!(
+                    "definition at {}:{} wrong. want={:?}, got={}",
+                    line, character, definition, result
+                );
+            }
+
+            let result = request(&mut server, "textDocument/hover", line, character);
+            let got = result
+                .get("contents")
+                .and_then(|contents| contents.get("value"))
+                .and_then(Value::as_str);
+            let want = hover.map(|hover| format!("```monkey\n{}\n```", hover));
+            if got != want.as_deref() {
+                panic!(
+                    "hover at {}:{} wrong. want={:?}, got={}",
+                    line, character, want, result
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut server = Server::new();
+        open(
+            &mut server,
+            "let total = 0;\nlet add = fn(amount) {\n  \n};\nlet later = 1;",
+        );
+
+        let result = request(&mut server, "textDocument/completion", 2, 2);
+        let labels: Vec<&str> = result
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item.get("label").and_then(Value::as_str).unwrap())
+            .collect();
+        let expected = [
+            "add", "amount", "total", "fn", "let", "true", "false", "if", "else", "return",
+        ];
+        if labels != expected {
+            panic!("completion labels wrong. got={:?}", labels);
+        }
+    }
+
+    #[test]
+    fn test_utf16_positions() {
+        let mut server = Server::new();
+        open(&mut server, "let s = \"😀\"; let 名前 = s;\n名前");
+
+        // The emoji takes two UTF-16 code units, so `名前` starts at 18.
+        let result = request(&mut server, "textDocument/definition", 1, 1);
+        let got = result.get("range").map(range);
+        if got.as_deref() != Some("0:18-0:20") {
+            panic!("definition wrong. got={}", result);
+        }
+    }
+
+    #[test]
+    fn test_unknown_method() {
+        let mut server = Server::new();
+        let replies = server.handle(
+            &json::parse(r#"{"jsonrpc":"2.0","id":"a","method":"workspace/symbol"}"#).unwrap(),
+        );
+        let expected = r#"{"jsonrpc":"2.0","id":"a","error":{"code":-32601,"message":"unknown method workspace/symbol"}}"#;
+        if replies.len() != 1 || replies[0].to_string() != expected {
+            panic!("unknown method reply wrong. got={:?}", replies);
+        }
+
+        let replies = server.handle(
+            &json::parse(r#"{"jsonrpc":"2.0","method":"$/cancelRequest","params":{"id":1}}"#)
+                .unwrap(),
+        );
+        if !replies.is_empty() {
+            panic!("notifications must not be answered. got={:?}", replies);
+        }
+    }
+}
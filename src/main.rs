@@ -1,11 +1,16 @@
-use repl::start;
+use std::process::ExitCode;
 
-mod ast;
-mod lexer;
-mod parser;
+use monkey_rust::evaluator::with_eval_stack;
+
+mod cli;
 mod repl;
-mod token;
 
-fn main() {
-    start();
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match cli::parse_args(&args) {
+        // Evaluation recurses on the native stack, so every command runs on
+        // a thread with room for deeply recursive programs.
+        Ok(command) => with_eval_stack(|| cli::execute(command)),
+        Err(message) => cli::usage_error(&message),
+    }
 }
@@ -0,0 +1,194 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use crate::{
+    ast::{BlockStatement, Identifier, Node},
+    code::Instructions,
+};
+
+pub const INTEGER_OBJ: &str = "INTEGER";
+pub const BOOLEAN_OBJ: &str = "BOOLEAN";
+pub const STRING_OBJ: &str = "STRING";
+pub const NULL_OBJ: &str = "NULL";
+pub const RETURN_VALUE_OBJ: &str = "RETURN_VALUE";
+pub const ERROR_OBJ: &str = "ERROR";
+pub const FUNCTION_OBJ: &str = "FUNCTION";
+pub const COMPILED_FUNCTION_OBJ: &str = "COMPILED_FUNCTION";
+pub const CLOSURE_OBJ: &str = "CLOSURE";
+
+#[derive(Debug, Clone)]
+pub enum Object {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Null,
+    ReturnValue(Box<Object>),
+    Error(String),
+    Function(Rc<Function>),
+    CompiledFunction(Rc<CompiledFunction>),
+    Closure(Rc<Closure>),
+}
+
+impl Object {
+    pub fn object_type(&self) -> &'static str {
+        match self {
+            Self::Integer(_) => INTEGER_OBJ,
+            Self::Boolean(_) => BOOLEAN_OBJ,
+            Self::String(_) => STRING_OBJ,
+            Self::Null => NULL_OBJ,
+            Self::ReturnValue(_) => RETURN_VALUE_OBJ,
+            Self::Error(_) => ERROR_OBJ,
+            Self::Function(_) => FUNCTION_OBJ,
+            Self::CompiledFunction(_) => COMPILED_FUNCTION_OBJ,
+            Self::Closure(_) => CLOSURE_OBJ,
+        }
+    }
+
+    pub fn inspect(&self) -> String {
+        match self {
+            Self::Integer(value) => value.to_string(),
+            Self::Boolean(value) => value.to_string(),
+            Self::String(value) => value.clone(),
+            Self::Null => "null".to_string(),
+            Self::ReturnValue(value) => value.inspect(),
+            Self::Error(message) => format!("ERROR: {}", message),
+            Self::Function(function) => function.inspect(),
+            Self::CompiledFunction(function) => {
+                format!("CompiledFunction[{:p}]", Rc::as_ptr(function))
+            }
+            Self::Closure(closure) => format!("Closure[{:p}]", Rc::as_ptr(closure)),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Self::Error(_))
+    }
+}
+
+#[derive(Clone)]
+pub struct Function {
+    pub parameters: Vec<Identifier>,
+    pub body: BlockStatement,
+    pub env: Env,
+}
+
+impl Function {
+    pub fn inspect(&self) -> String {
+        let params: Vec<String> = self.parameters.iter().map(|p| p.string()).collect();
+        format!("fn({}) {{\n{}\n}}", params.join(", "), self.body.string())
+    }
+}
+
+// The captured environment usually contains the function itself, so it is
+// left out to keep the output finite.
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("parameters", &self.parameters)
+            .field("body", &self.body)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A function body compiled to bytecode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub num_locals: usize,
+    pub num_parameters: usize,
+}
+
+/// A compiled function together with the free variables it captured.
+#[derive(Debug, Clone)]
+pub struct Closure {
+    pub func: Rc<CompiledFunction>,
+    pub free: Vec<Object>,
+}
+
+pub type Env = Rc<RefCell<Environment>>;
+
+/// Variable bindings of one scope, falling back to the enclosing scope on
+/// lookup.
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Object>,
+    outer: Option<Env>,
+    /// Number of function calls active while this scope is in use.
+    call_depth: usize,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        Rc::new(RefCell::new(Environment::default()))
+    }
+
+    pub fn new_enclosed(outer: Env) -> Env {
+        let call_depth = outer.borrow().call_depth;
+        Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+            call_depth,
+        }))
+    }
+
+    /// Creates the scope of a function called from `caller`. Lookups fall
+    /// back to `outer`, the scope the function was defined in.
+    pub fn new_call(outer: Env, caller: &Env) -> Env {
+        Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(outer),
+            call_depth: caller.borrow().call_depth + 1,
+        }))
+    }
+
+    pub fn call_depth(&self) -> usize {
+        self.call_depth
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        match self.store.get(name) {
+            Some(obj) => Some(obj.clone()),
+            None => self
+                .outer
+                .as_ref()
+                .and_then(|outer| outer.borrow().get(name)),
+        }
+    }
+
+    pub fn set(&mut self, name: String, val: Object) {
+        self.store.insert(name, val);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Environment, Object};
+
+    #[test]
+    fn test_enclosed_environment() {
+        let outer = Environment::new();
+        outer.borrow_mut().set("x".to_string(), Object::Integer(1));
+        outer.borrow_mut().set("y".to_string(), Object::Integer(2));
+
+        let inner = Environment::new_enclosed(outer.clone());
+        inner.borrow_mut().set("y".to_string(), Object::Integer(3));
+
+        let tests = [("x", Some(1)), ("y", Some(3)), ("z", None)];
+        for (name, expected) in tests {
+            let actual = match inner.borrow().get(name) {
+                Some(Object::Integer(value)) => Some(value),
+                None => None,
+                obj => panic!("object is not Integer. got={:?}", obj),
+            };
+            if actual != expected {
+                panic!(
+                    "inner.get({}) wrong. expected={:?}, got={:?}",
+                    name, expected, actual
+                );
+            }
+        }
+
+        if !matches!(outer.borrow().get("y"), Some(Object::Integer(2))) {
+            panic!("inner binding leaked into the outer environment");
+        }
+    }
+}
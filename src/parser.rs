@@ -1,12 +1,16 @@
+use std::{collections::HashMap, fmt};
+
 use crate::{
     ast::{
-        Expression, ExpressionStatement, Identifier, IntegerLiteral, LetStatement, Program,
-        ReturnStatement, Statement,
+        BlockStatement, Boolean, CallExpression, ErrorNode, Expression, ExpressionStatement,
+        FunctionLiteral, Identifier, IfExpression, InfixExpression, IntegerLiteral, LetStatement,
+        PrefixExpression, Program, ReturnStatement, Statement, StringLiteral,
     },
-    lexer::Lexer,
-    token::{Token, TokenType},
+    lexer::{LexError, Lexer, TokenStream},
+    token::{Position, Span, Token, TokenType},
 };
 
+#[derive(Clone, Copy)]
 enum Operator {
     Lowest,
     Equals,
@@ -17,156 +21,657 @@ enum Operator {
     Call,
 }
 
+const PRECEDENCES: [(TokenType, Operator); 9] = [
+    (TokenType::EQ, Operator::Equals),
+    (TokenType::NOTEQ, Operator::Equals),
+    (TokenType::LT, Operator::LessGrater),
+    (TokenType::GT, Operator::LessGrater),
+    (TokenType::PLUS, Operator::Sum),
+    (TokenType::MINUS, Operator::Sum),
+    (TokenType::SLASH, Operator::Product),
+    (TokenType::ASTERISK, Operator::Product),
+    (TokenType::LPAREN, Operator::Call),
+];
+
+fn lookup_precedence(t: TokenType) -> usize {
+    PRECEDENCES
+        .iter()
+        .find_map(|p| if p.0 == t { Some(p.1) } else { None })
+        .unwrap_or(Operator::Lowest) as usize
+}
+
+/// How deeply expressions may nest. Parsing recurses once per level, so a
+/// long run of `(` or `-` would otherwise overflow the stack. A level can take
+/// over 16 KiB of stack in an unoptimised build, and this must fit in the
+/// 2 MiB stack of a spawned thread.
+const MAX_NESTING_DEPTH: usize = 64;
+
+type PrefixParseFn<'a> = fn(&mut Parser<'a>) -> Option<Expression>;
+type InfixParseFn<'a> = fn(&mut Parser<'a>, Expression) -> Option<Expression>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Lex(LexError),
+    UnexpectedToken {
+        expected: TokenType,
+        found: Token<'static>,
+    },
+    InvalidInteger(Token<'static>),
+    NoPrefixParseFn(Token<'static>),
+    UnexpectedEof(Token<'static>),
+    TooDeeplyNested(Token<'static>),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Lex(err) => err.span(),
+            Self::UnexpectedToken { found, .. } => found.span,
+            Self::InvalidInteger(token) => token.span,
+            Self::NoPrefixParseFn(token) => token.span,
+            Self::UnexpectedEof(token) => token.span,
+            Self::TooDeeplyNested(token) => token.span,
+        }
+    }
+
+    pub(crate) fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Self::Lex(err) => err.span_mut(),
+            Self::UnexpectedToken { found, .. } => &mut found.span,
+            Self::InvalidInteger(token) => &mut token.span,
+            Self::NoPrefixParseFn(token) => &mut token.span,
+            Self::UnexpectedEof(token) => &mut token.span,
+            Self::TooDeeplyNested(token) => &mut token.span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lex(err) => err.fmt(f),
+            Self::UnexpectedToken { expected, found } => write!(
+                f,
+                "expected next token to be {:?}, got {:?} instead",
+                expected, found.token_type
+            ),
+            Self::InvalidInteger(token) => {
+                write!(f, "could not parse {} as integer", token.literal)
+            }
+            Self::NoPrefixParseFn(token) => {
+                write!(
+                    f,
+                    "no prefix parse function for {:?} found",
+                    token.token_type
+                )
+            }
+            Self::UnexpectedEof(_) => write!(f, "unexpected end of input"),
+            Self::TooDeeplyNested(_) => write!(f, "expression nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[derive(Debug, Clone)]
-struct Parser<'a> {
-    l: Lexer<'a>,
-    errors: Vec<String>,
+pub struct Parser<'a> {
+    tokens: TokenStream<'a>,
+    errors: Vec<ParseError>,
+    /// Number of expressions being parsed that enclose the current token.
+    depth: usize,
 
-    cur_token: Token,
-    peek_token: Token,
+    prefix_parse_fns: HashMap<TokenType, PrefixParseFn<'a>>,
+    infix_parse_fns: HashMap<TokenType, InfixParseFn<'a>>,
 }
 
-impl Parser<'_> {
-    pub fn new(l: Lexer) -> Parser {
+impl<'a> Parser<'a> {
+    pub fn new(l: Lexer<'a>) -> Parser<'a> {
         let mut p = Parser {
-            l,
+            tokens: TokenStream::new(l),
             errors: Vec::new(),
-            cur_token: Token::new(),
-            peek_token: Token::new(),
+            depth: 0,
+            prefix_parse_fns: HashMap::new(),
+            infix_parse_fns: HashMap::new(),
         };
 
-        p.next_token();
-        p.next_token();
+        p.register_prefix(TokenType::IDENT, Parser::parse_identifier);
+        p.register_prefix(TokenType::INT, Parser::parse_integer_literal);
+        p.register_prefix(TokenType::STRING, Parser::parse_string_literal);
+        p.register_prefix(TokenType::BANG, Parser::parse_prefix_expression);
+        p.register_prefix(TokenType::MINUS, Parser::parse_prefix_expression);
+        p.register_prefix(TokenType::TRUE, Parser::parse_boolean);
+        p.register_prefix(TokenType::FALSE, Parser::parse_boolean);
+        p.register_prefix(TokenType::LPAREN, Parser::parse_grouped_expression);
+        p.register_prefix(TokenType::IF, Parser::parse_if_expression);
+        p.register_prefix(TokenType::FUNCTION, Parser::parse_function_literal);
+
+        p.register_infix(TokenType::PLUS, Parser::parse_infix_expression);
+        p.register_infix(TokenType::MINUS, Parser::parse_infix_expression);
+        p.register_infix(TokenType::SLASH, Parser::parse_infix_expression);
+        p.register_infix(TokenType::ASTERISK, Parser::parse_infix_expression);
+        p.register_infix(TokenType::EQ, Parser::parse_infix_expression);
+        p.register_infix(TokenType::NOTEQ, Parser::parse_infix_expression);
+        p.register_infix(TokenType::LT, Parser::parse_infix_expression);
+        p.register_infix(TokenType::GT, Parser::parse_infix_expression);
+        p.register_infix(TokenType::LPAREN, Parser::parse_call_expression);
+
+        p.tokens.peek(1);
+        p.take_lex_errors();
 
         p
     }
 
-    fn errors(&self) -> &Vec<String> {
-        &self.errors
+    /// Returns the spans of the comments skipped so far, in source order, if
+    /// the lexer was created with `Lexer::with_comments`.
+    pub fn comments(&self) -> &[Span] {
+        self.tokens.lexer().comments()
+    }
+
+    fn register_prefix(&mut self, token_type: TokenType, f: PrefixParseFn<'a>) {
+        self.prefix_parse_fns.insert(token_type, f);
     }
 
-    fn peek_error(&self, t: TokenType) -> String {
-        format!(
-            "expected next token to be {:?}, got {:?} instead",
-            t, self.peek_token.token_type
-        )
+    fn register_infix(&mut self, token_type: TokenType, f: InfixParseFn<'a>) {
+        self.infix_parse_fns.insert(token_type, f);
+    }
+
+    fn peek_error(&mut self, t: TokenType) {
+        let found = self.peek_token().clone().into_owned();
+        self.errors.push(if found.token_type == TokenType::EOF {
+            ParseError::UnexpectedEof(found)
+        } else {
+            ParseError::UnexpectedToken { expected: t, found }
+        });
     }
 
     fn next_token(&mut self) {
-        self.cur_token = std::mem::replace(&mut self.peek_token, self.l.next_token())
+        self.tokens.advance();
+        // Reading the next token right away reports any errors in it before
+        // the parse errors that mention it.
+        self.tokens.peek(1);
+        self.take_lex_errors();
+    }
+
+    fn take_lex_errors(&mut self) {
+        self.errors
+            .extend(self.tokens.take_errors().into_iter().map(ParseError::Lex));
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, Vec<ParseError>> {
+        let (program, errors) = self.parse_program_with_recovery();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors)
+        }
     }
 
-    fn parse_program(&mut self) -> Program {
+    /// Parses the whole input, recovering from errors so that a `Program` is
+    /// always produced. Statements or expressions that failed to parse are
+    /// replaced by `Statement::Error` and `Expression::Error` nodes, with one
+    /// `ParseError` recorded per failure.
+    pub fn parse_program_with_recovery(&mut self) -> (Program, Vec<ParseError>) {
         let mut program = Program::new();
 
         while !self.cur_token_is(TokenType::EOF) {
-            let stmt = self.parse_statement();
-            if let Some(stmt) = stmt {
-                program.statements.push(stmt);
-            }
-            self.next_token();
+            program.statements.push(self.parse_statement());
+        }
+
+        (program, std::mem::take(&mut self.errors))
+    }
+
+    /// Parses the next top-level statement, or returns `None` once the input
+    /// is exhausted. Errors accumulate until `take_errors` is called.
+    pub(crate) fn next_statement(&mut self) -> Option<Statement> {
+        if self.cur_token_is(TokenType::EOF) {
+            None
+        } else {
+            Some(self.parse_statement())
         }
-        program
     }
 
-    fn parse_statement(&mut self) -> Option<Statement> {
-        match self.cur_token.token_type {
+    /// Returns the start of the current token, which is the start of the
+    /// next statement between calls to `next_statement`.
+    pub(crate) fn position(&self) -> Position {
+        self.cur_token().span.start
+    }
+
+    /// Returns the byte offset up to which the lexer has read the input.
+    pub(crate) fn examined(&self) -> usize {
+        self.tokens.lexer().examined()
+    }
+
+    pub(crate) fn take_errors(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Parses the statement starting at the current token and leaves the
+    /// parser on the first token after it.
+    fn parse_statement(&mut self) -> Statement {
+        let token = self.cur_token().clone().into_owned();
+        let stmt = match self.cur_token().token_type {
             TokenType::LET => self.parse_let_statement(),
             TokenType::RETURN => self.parse_return_statement(),
             _ => self.parse_expression_statement(),
+        };
+
+        stmt.unwrap_or_else(|| {
+            // The statement may have failed on its very first token, which
+            // must be skipped for the parser to make progress.
+            if self.cur_token().span == token.span {
+                self.next_token();
+            }
+            let span = self.synchronize(token.span.start, token.span.end);
+            Statement::Error(ErrorNode { token, span })
+        })
+    }
+
+    /// Skips tokens after a parse error until the end of the current
+    /// statement: just past a `;`, or before a `}`, `let`, `return` or `fn`
+    /// that is not nested in braces. Returns the span from `start` to the
+    /// last token consumed, or up to `end` if nothing was skipped.
+    fn synchronize(&mut self, start: Position, mut end: Position) -> Span {
+        let mut depth = 0;
+        loop {
+            match self.cur_token().token_type {
+                TokenType::EOF => break,
+                TokenType::RBRACE | TokenType::LET | TokenType::RETURN | TokenType::FUNCTION
+                    if depth == 0 =>
+                {
+                    break
+                }
+                TokenType::LBRACE => depth += 1,
+                TokenType::RBRACE => depth -= 1,
+                _ => {}
+            }
+
+            let at_semicolon = depth == 0 && self.cur_token_is(TokenType::SEMICOLON);
+            end = self.cur_token().span.end;
+            self.next_token();
+            if at_semicolon {
+                break;
+            }
+        }
+        Span::new(start, end)
+    }
+
+    /// Moves past the last token of a statement and its optional semicolon.
+    fn finish_statement(&mut self) {
+        if self.peek_token_is(TokenType::SEMICOLON) {
+            self.next_token();
+        }
+        self.next_token();
+    }
+
+    /// Parses the value of a `let` or `return` statement, replacing it with an
+    /// `Expression::Error` and skipping the rest of the statement on failure.
+    fn parse_statement_value(&mut self) -> Expression {
+        let token = self.cur_token().clone().into_owned();
+        match self.parse_expression(Operator::Lowest as usize) {
+            Some(value) => {
+                self.finish_statement();
+                value
+            }
+            None => {
+                let span = self.synchronize(token.span.start, token.span.start);
+                Expression::Error(ErrorNode { token, span })
+            }
         }
     }
 
     fn parse_let_statement(&mut self) -> Option<Statement> {
         let mut stmt = LetStatement::new();
-        stmt.token = self.cur_token.clone();
+        stmt.token = self.cur_token().clone().into_owned();
 
         if !self.expect_peek(TokenType::IDENT) {
             return None;
         }
 
         stmt.name = Identifier {
-            token: self.cur_token.clone(),
-            value: self.cur_token.literal.clone(),
+            token: self.cur_token().clone().into_owned(),
+            value: self.cur_token().literal.to_string(),
         };
 
         if !self.expect_peek(TokenType::ASSIGN) {
             return None;
         }
 
-        while !self.cur_token_is(TokenType::SEMICOLON) {
-            self.next_token();
-        }
+        self.next_token();
+
+        stmt.value = Some(self.parse_statement_value());
 
         Some(Statement::LetStatement(stmt))
     }
 
     fn parse_return_statement(&mut self) -> Option<Statement> {
-        let stmt = ReturnStatement::new(self.cur_token.clone());
+        let mut stmt = ReturnStatement::new(self.cur_token().clone().into_owned());
 
         self.next_token();
 
-        while !self.cur_token_is(TokenType::SEMICOLON) {
-            self.next_token();
-        }
+        stmt.return_value = Some(self.parse_statement_value());
 
         Some(Statement::ReturnStatement(stmt))
     }
 
     fn parse_expression_statement(&mut self) -> Option<Statement> {
         let stmt = ExpressionStatement {
-            token: self.cur_token.clone(),
-            expression: self.parse_expression(Operator::Lowest as usize),
+            token: self.cur_token().clone().into_owned(),
+            expression: Some(self.parse_expression(Operator::Lowest as usize)?),
         };
 
-        if self.peek_token_is(TokenType::SEMICOLON) {
-            self.next_token();
-        }
+        self.finish_statement();
 
         Some(Statement::ExpressionStatement(stmt))
     }
 
     fn parse_expression(&mut self, precedence: usize) -> Option<Expression> {
-        match self.cur_token.token_type {
-            TokenType::IDENT => self.parse_identifier(),
-            TokenType::INT => self.parese_integer_literal(),
-            _ => None,
+        if self.depth == MAX_NESTING_DEPTH {
+            self.errors.push(ParseError::TooDeeplyNested(
+                self.cur_token().clone().into_owned(),
+            ));
+            return None;
+        }
+
+        self.depth += 1;
+        let expression = self.parse_nested_expression(precedence);
+        self.depth -= 1;
+        expression
+    }
+
+    fn parse_nested_expression(&mut self, precedence: usize) -> Option<Expression> {
+        let prefix = match self.prefix_parse_fns.get(&self.cur_token().token_type) {
+            Some(prefix) => *prefix,
+            None => {
+                self.no_prefix_parse_fn_error();
+                return None;
+            }
+        };
+        let mut left_exp = prefix(self)?;
+
+        while !self.peek_token_is(TokenType::SEMICOLON) && precedence < self.peek_precedence() {
+            let token_type = self.peek_token().token_type;
+            let infix = match self.infix_parse_fns.get(&token_type) {
+                Some(infix) => *infix,
+                None => return Some(left_exp),
+            };
+
+            self.next_token();
+
+            left_exp = infix(self, left_exp)?;
         }
+
+        Some(left_exp)
+    }
+
+    fn parse_prefix_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token().clone().into_owned();
+        let operator = self.cur_token().literal.to_string();
+
+        self.next_token();
+
+        let right = self.parse_expression(Operator::Prefix as usize)?;
+
+        Some(Expression::Prefix(PrefixExpression {
+            token,
+            operator,
+            right: Box::new(right),
+        }))
+    }
+
+    fn parse_infix_expression(&mut self, left: Expression) -> Option<Expression> {
+        let token = self.cur_token().clone().into_owned();
+        let operator = self.cur_token().literal.to_string();
+
+        let precedence = self.cur_precedence();
+        self.next_token();
+        let right = self.parse_expression(precedence)?;
+
+        Some(Expression::Infix(InfixExpression {
+            token,
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        }))
     }
 
-    fn parse_identifier(&self) -> Option<Expression> {
+    fn parse_identifier(&mut self) -> Option<Expression> {
         Some(Expression::Identifier(Identifier {
-            token: self.cur_token.clone(),
-            value: self.cur_token.literal.clone(),
+            token: self.cur_token().clone().into_owned(),
+            value: self.cur_token().literal.to_string(),
         }))
     }
 
-    fn parese_integer_literal(&mut self) -> Option<Expression> {
-        let value = match self.cur_token.literal.parse() {
+    fn parse_integer_literal(&mut self) -> Option<Expression> {
+        let value = match self.cur_token().literal.parse() {
             Ok(value) => value,
             _ => {
-                self.errors.push(format!(
-                    "could not parse {} as integer",
-                    self.cur_token.literal
+                self.errors.push(ParseError::InvalidInteger(
+                    self.cur_token().clone().into_owned(),
                 ));
                 return None;
             }
         };
 
         let lit = IntegerLiteral {
-            token: self.cur_token.clone(),
+            token: self.cur_token().clone().into_owned(),
             value,
         };
 
         Some(Expression::IntegerLiteral(lit))
     }
 
+    fn parse_string_literal(&mut self) -> Option<Expression> {
+        Some(Expression::StringLiteral(StringLiteral {
+            token: self.cur_token().clone().into_owned(),
+            value: self.cur_token().literal.to_string(),
+        }))
+    }
+
+    fn parse_boolean(&mut self) -> Option<Expression> {
+        Some(Expression::Boolean(Boolean {
+            token: self.cur_token().clone().into_owned(),
+            value: self.cur_token_is(TokenType::TRUE),
+        }))
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<Expression> {
+        self.next_token();
+
+        let exp = self.parse_expression(Operator::Lowest as usize)?;
+
+        if !self.expect_peek(TokenType::RPAREN) {
+            return None;
+        }
+
+        Some(exp)
+    }
+
+    fn parse_if_expression(&mut self) -> Option<Expression> {
+        let token = self.cur_token().clone().into_owned();
+
+        if !self.expect_peek(TokenType::LPAREN) {
+            return None;
+        }
+
+        self.next_token();
+        let condition = self.parse_expression(Operator::Lowest as usize)?;
+
+        if !self.expect_peek(TokenType::RPAREN) {
+            return None;
+        }
+
+        if !self.expect_peek(TokenType::LBRACE) {
+            return None;
+        }
+
+        let consequence = self.parse_block_statement()?;
+
+        let mut alternative = None;
+        if self.peek_token_is(TokenType::ELSE) {
+            self.next_token();
+
+            if !self.expect_peek(TokenType::LBRACE) {
+                return None;
+            }
+
+            alternative = Some(self.parse_block_statement()?);
+        }
+
+        Some(Expression::If(IfExpression {
+            token,
+            condition: Box::new(condition),
+            consequence,
+            alternative,
+        }))
+    }
+
+    fn parse_block_statement(&mut self) -> Option<BlockStatement> {
+        let token = self.cur_token().clone().into_owned();
+        let mut statements = Vec::new();
+
+        self.next_token();
+
+        while !self.cur_token_is(TokenType::RBRACE) {
+            if self.cur_token_is(TokenType::EOF) {
+                self.errors.push(ParseError::UnexpectedEof(
+                    self.cur_token().clone().into_owned(),
+                ));
+                return None;
+            }
+
+            statements.push(self.parse_statement());
+        }
+
+        Some(BlockStatement {
+            token,
+            statements,
+            rbrace: self.cur_token().clone().into_owned(),
+        })
+    }
+
+    fn parse_function_literal(&mut self) -> Option<Expression> {
+        let token = self.cur_token().clone().into_owned();
+
+        if !self.expect_peek(TokenType::LPAREN) {
+            return None;
+        }
+
+        let parameters = self.parse_function_parameters()?;
+
+        if !self.expect_peek(TokenType::LBRACE) {
+            return None;
+        }
+
+        let body = self.parse_block_statement()?;
+
+        Some(Expression::FunctionLiteral(FunctionLiteral {
+            token,
+            parameters,
+            body,
+        }))
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<Vec<Identifier>> {
+        let mut identifiers = Vec::new();
+
+        if self.peek_token_is(TokenType::RPAREN) {
+            self.next_token();
+            return Some(identifiers);
+        }
+
+        if !self.expect_peek(TokenType::IDENT) {
+            return None;
+        }
+        identifiers.push(Identifier {
+            token: self.cur_token().clone().into_owned(),
+            value: self.cur_token().literal.to_string(),
+        });
+
+        while self.peek_token_is(TokenType::COMMA) {
+            self.next_token();
+            if !self.expect_peek(TokenType::IDENT) {
+                return None;
+            }
+            identifiers.push(Identifier {
+                token: self.cur_token().clone().into_owned(),
+                value: self.cur_token().literal.to_string(),
+            });
+        }
+
+        if !self.expect_peek(TokenType::RPAREN) {
+            return None;
+        }
+
+        Some(identifiers)
+    }
+
+    fn parse_call_expression(&mut self, function: Expression) -> Option<Expression> {
+        let token = self.cur_token().clone().into_owned();
+        let arguments = self.parse_call_arguments()?;
+
+        Some(Expression::Call(CallExpression {
+            token,
+            function: Box::new(function),
+            arguments,
+            rparen: self.cur_token().clone().into_owned(),
+        }))
+    }
+
+    fn parse_call_arguments(&mut self) -> Option<Vec<Expression>> {
+        let mut args = Vec::new();
+
+        if self.peek_token_is(TokenType::RPAREN) {
+            self.next_token();
+            return Some(args);
+        }
+
+        self.next_token();
+        args.push(self.parse_expression(Operator::Lowest as usize)?);
+
+        while self.peek_token_is(TokenType::COMMA) {
+            self.next_token();
+            self.next_token();
+            args.push(self.parse_expression(Operator::Lowest as usize)?);
+        }
+
+        if !self.expect_peek(TokenType::RPAREN) {
+            return None;
+        }
+
+        Some(args)
+    }
+
+    fn no_prefix_parse_fn_error(&mut self) {
+        let token = self.cur_token().clone().into_owned();
+        self.errors.push(if token.token_type == TokenType::EOF {
+            ParseError::UnexpectedEof(token)
+        } else {
+            ParseError::NoPrefixParseFn(token)
+        });
+    }
+
+    fn peek_precedence(&mut self) -> usize {
+        lookup_precedence(self.peek_token().token_type)
+    }
+
+    fn cur_precedence(&self) -> usize {
+        lookup_precedence(self.cur_token().token_type)
+    }
+
+    fn cur_token(&self) -> &Token<'a> {
+        self.tokens.current()
+    }
+
+    fn peek_token(&mut self) -> &Token<'a> {
+        self.tokens.peek(1)
+    }
+
     fn cur_token_is(&self, t: TokenType) -> bool {
-        self.cur_token.token_type == t
+        self.cur_token().token_type == t
     }
 
-    fn peek_token_is(&self, t: TokenType) -> bool {
-        self.peek_token.token_type == t
+    fn peek_token_is(&mut self, t: TokenType) -> bool {
+        self.peek_token().token_type == t
     }
 
     fn expect_peek(&mut self, t: TokenType) -> bool {
@@ -183,83 +688,161 @@ impl Parser<'_> {
 #[cfg(test)]
 mod tests {
     use crate::{
-        ast::{Expression, Node, Statement},
+        ast::{Expression, ExpressionStatement, Node, Program, Statement},
         lexer::Lexer,
+        token::{
+            Position, Span,
+            TokenType::{ASSIGN, IDENT, INT},
+        },
     };
 
-    use super::Parser;
+    use super::{ParseError, Parser};
 
     #[test]
     fn test_let_statements() {
-        let input = "
-        let x = 5;
-        let y = 10;
-        let foobar = 838383;
-        ";
-
-        let l = Lexer::new(input);
-        let mut p = Parser::new(l);
+        let tests = [
+            ("let x = 5;", "x", "5"),
+            ("let y = 10;", "y", "10"),
+            ("let foobar = y;", "foobar", "y"),
+            ("let z = 1 + 2 * 3", "z", "(1 + (2 * 3))"),
+        ];
 
-        let program = p.parse_program();
-        check_parser_errors(&p);
+        for (input, expected_identifier, expected_value) in tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let program = check_parser_errors(p.parse_program());
 
-        if program.statements.len() != 3 {
-            panic!(
-                "program.statements does not contain 3 statements. got={}",
-                program.statements.len()
-            );
-        }
+            if program.statements.len() != 1 {
+                panic!(
+                    "program.statements does not contain 1 statements. got={}",
+                    program.statements.len()
+                );
+            }
 
-        let tests = ["x", "y", "foobar"];
-        for (i, tt) in tests.iter().enumerate() {
-            let stmt = &program.statements[i];
-            if !test_let_statement(stmt, tt) {
+            let stmt = &program.statements[0];
+            if !test_let_statement(stmt, expected_identifier) {
                 return;
             }
+
+            let val = match stmt {
+                Statement::LetStatement(let_stmt) => &let_stmt.value,
+                _ => unreachable!(),
+            };
+            match val {
+                Some(val) if val.string() == expected_value => {}
+                _ => panic!("let_stmt.value not {}. got={:?}", expected_value, val),
+            }
         }
     }
 
     #[test]
     fn test_return_statements() {
-        let input = "
-        return 5;
-        return 10;
-        return 993322;
-        ";
-
-        let l = Lexer::new(input);
-        let mut p = Parser::new(l);
+        let tests = [
+            ("return 5;", "5"),
+            ("return 10;", "10"),
+            ("return 993322;", "993322"),
+            ("return x + y", "(x + y)"),
+        ];
 
-        let program = p.parse_program();
-        check_parser_errors(&p);
+        for (input, expected_value) in tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let program = check_parser_errors(p.parse_program());
 
-        if program.statements.len() != 3 {
-            panic!(
-                "program.statements does not contain 3 statements. got={}",
-                program.statements.len()
-            );
-        }
+            if program.statements.len() != 1 {
+                panic!(
+                    "program.statements does not contain 1 statements. got={}",
+                    program.statements.len()
+                );
+            }
 
-        for stmt in program.statements {
+            let stmt = &program.statements[0];
             match stmt {
-                Statement::ReturnStatement(_) => {
+                Statement::ReturnStatement(return_stmt) => {
                     if stmt.token_literal() != "return" {
                         panic!(
                             "stmt.token_literal not 'return', got {}",
                             stmt.token_literal()
                         );
                     }
+                    match &return_stmt.return_value {
+                        Some(val) if val.string() == expected_value => {}
+                        val => panic!(
+                            "return_stmt.return_value not {}. got={:?}",
+                            expected_value, val
+                        ),
+                    }
                 }
                 _ => panic!("stmt not ReturnStatement. got={:?}", stmt),
             }
         }
     }
 
-    fn check_parser_errors(p: &Parser) {
-        let errors = p.errors();
-        if errors.len() == 0 {
-            return;
+    #[test]
+    fn test_statements_at_end_of_input() {
+        let tests = ["let x =", "let x = 5 +", "return", "return -"];
+
+        for input in tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let errors = match p.parse_program() {
+                Ok(program) => panic!(
+                    "expected parser errors for {:?}. got={:?}",
+                    input, program.statements
+                ),
+                Err(errors) => errors,
+            };
+
+            if !matches!(errors.last(), Some(ParseError::UnexpectedEof(_))) {
+                panic!("expected UnexpectedEof for {:?}. got={:?}", input, errors);
+            }
         }
+    }
+
+    #[test]
+    fn test_let_statement_errors() {
+        let tests = [
+            ("let = 10;", IDENT, ASSIGN),
+            ("let x 5;", ASSIGN, INT),
+            ("let 838383;", IDENT, INT),
+        ];
+
+        for (input, expected_type, found_type) in tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+
+            let errors = match p.parse_program() {
+                Ok(program) => panic!(
+                    "expected parser errors for {:?}. got={:?}",
+                    input, program.statements
+                ),
+                Err(errors) => errors,
+            };
+
+            match &errors[0] {
+                ParseError::UnexpectedToken { expected, found }
+                    if *expected == expected_type && found.token_type == found_type => {}
+                err => panic!(
+                    "errors[0] wrong for {:?}. expected UnexpectedToken {{ {:?}, {:?} }}, got={:?}",
+                    input, expected_type, found_type, err
+                ),
+            }
+            if errors[0].to_string()
+                != format!(
+                    "expected next token to be {:?}, got {:?} instead",
+                    expected_type, found_type
+                )
+            {
+                panic!("errors[0] message wrong. got={}", errors[0]);
+            }
+        }
+    }
+
+    fn check_parser_errors(result: Result<Program, Vec<ParseError>>) -> Program {
+        let errors = match result {
+            Ok(program) => return program,
+            Err(errors) => errors,
+        };
 
         println!("parser has {} errors", errors.len());
         for msg in errors {
@@ -305,7 +888,7 @@ mod tests {
 
         let l = Lexer::new(input);
         let mut p = Parser::new(l);
-        let program = p.parse_program();
+        let program = check_parser_errors(p.parse_program());
 
         if program.statements.len() != 1 {
             panic!(
@@ -344,8 +927,7 @@ mod tests {
 
         let l = Lexer::new(input);
         let mut p = Parser::new(l);
-        let program = p.parse_program();
-        check_parser_errors(&p);
+        let program = check_parser_errors(p.parse_program());
 
         if program.statements.len() != 1 {
             panic!(
@@ -379,4 +961,643 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_parsing_prefix_expressions() {
+        let prefix_tests = [("!5;", "!", 5), ("-15;", "-", 15)];
+
+        for (input, operator, integer_value) in prefix_tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let program = check_parser_errors(p.parse_program());
+
+            if program.statements.len() != 1 {
+                panic!(
+                    "program.statements does not contain {} statements. got={}",
+                    1,
+                    program.statements.len()
+                );
+            }
+
+            let stmt = match &program.statements[0] {
+                Statement::ExpressionStatement(stmt) => stmt,
+                _ => panic!(
+                    "program.statements[0] is not Statement::ExpressionStatement. got={:?}",
+                    program.statements[0]
+                ),
+            };
+
+            let exp = match &stmt.expression {
+                Some(Expression::Prefix(exp)) => exp,
+                _ => panic!("stmt is not Expression::Prefix. got={:?}", stmt.expression),
+            };
+            if exp.operator != operator {
+                panic!("exp.operator is not '{}'. got={}", operator, exp.operator);
+            }
+            if !test_integer_literal(&exp.right, integer_value) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_parsing_infix_expressions() {
+        let infix_tests = [
+            ("5 + 5;", 5, "+", 5),
+            ("5 - 5;", 5, "-", 5),
+            ("5 * 5;", 5, "*", 5),
+            ("5 / 5;", 5, "/", 5),
+            ("5 > 5;", 5, ">", 5),
+            ("5 < 5;", 5, "<", 5),
+            ("5 == 5;", 5, "==", 5),
+            ("5 != 5;", 5, "!=", 5),
+        ];
+
+        for (input, left_value, operator, right_value) in infix_tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let program = check_parser_errors(p.parse_program());
+
+            if program.statements.len() != 1 {
+                panic!(
+                    "program.statements does not contain {} statements. got={}",
+                    1,
+                    program.statements.len()
+                );
+            }
+
+            let stmt = match &program.statements[0] {
+                Statement::ExpressionStatement(stmt) => stmt,
+                _ => panic!(
+                    "program.statements[0] is not Statement::ExpressionStatement. got={:?}",
+                    program.statements[0]
+                ),
+            };
+
+            let exp = match &stmt.expression {
+                Some(Expression::Infix(exp)) => exp,
+                _ => panic!("exp is not Expression::Infix. got={:?}", stmt.expression),
+            };
+            if !test_integer_literal(&exp.left, left_value) {
+                return;
+            }
+            if exp.operator != operator {
+                panic!("exp.operator is not '{}'. got={}", operator, exp.operator);
+            }
+            if !test_integer_literal(&exp.right, right_value) {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_operator_precedence_parsing() {
+        let tests = [
+            ("-a * b", "((-a) * b)"),
+            ("!-a", "(!(-a))"),
+            ("a + b + c", "((a + b) + c)"),
+            ("a + b - c", "((a + b) - c)"),
+            ("a * b * c", "((a * b) * c)"),
+            ("a * b / c", "((a * b) / c)"),
+            ("a + b / c", "(a + (b / c))"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
+            ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))"),
+            (
+                "3 + 4 * 5 == 3 * 1 + 4 * 5",
+                "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))",
+            ),
+            ("true", "true"),
+            ("false", "false"),
+            ("3 > 5 == false", "((3 > 5) == false)"),
+            ("3 < 5 == true", "((3 < 5) == true)"),
+            ("1 + (2 + 3) + 4", "((1 + (2 + 3)) + 4)"),
+            ("(5 + 5) * 2", "((5 + 5) * 2)"),
+            ("2 / (5 + 5)", "(2 / (5 + 5))"),
+            ("-(5 + 5)", "(-(5 + 5))"),
+            ("!(true == true)", "(!(true == true))"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))",
+            ),
+            (
+                "add(a + b + c * d / f + g)",
+                "add((((a + b) + ((c * d) / f)) + g))",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let program = check_parser_errors(p.parse_program());
+
+            let actual = program.string();
+            if actual != expected {
+                panic!("expected={}, got={}", expected, actual);
+            }
+        }
+    }
+
+    fn test_integer_literal(il: &Expression, value: i64) -> bool {
+        let integ = match il {
+            Expression::IntegerLiteral(integ) => integ,
+            _ => {
+                println!("il not Expression::IntegerLiteral. got={:?}", il);
+                return false;
+            }
+        };
+
+        if integ.value != value {
+            println!("integ.value not {}. got={}", value, integ.value);
+            return false;
+        }
+
+        if integ.token_literal() != value.to_string() {
+            println!(
+                "integ.token_literal not {}. got={}",
+                value,
+                integ.token_literal()
+            );
+            return false;
+        }
+
+        true
+    }
+
+    #[test]
+    fn test_statement_spans() {
+        let input = "let x = -a * 10;\nreturn x;";
+
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = check_parser_errors(p.parse_program());
+
+        let tests = [
+            ((1, 1, 0), (1, 16, 15), "let x = -a * 10"),
+            ((2, 1, 17), (2, 9, 25), "return x"),
+        ];
+
+        for (stmt, (start, end, text)) in program.statements.iter().zip(tests) {
+            let expected = Span::new(
+                Position::new(start.0, start.1, start.2),
+                Position::new(end.0, end.1, end.2),
+            );
+            if stmt.span() != expected {
+                panic!(
+                    "stmt.span() wrong. expected={:?}, got={:?}",
+                    expected,
+                    stmt.span()
+                );
+            }
+            if &input[stmt.span().start.offset..stmt.span().end.offset] != text {
+                panic!("stmt.span() does not cover {:?}", text);
+            }
+        }
+
+        if let Statement::LetStatement(let_stmt) = &program.statements[0] {
+            let value = let_stmt.value.as_ref().unwrap();
+            if &input[value.span().start.offset..value.span().end.offset] != "-a * 10" {
+                panic!("value.span() wrong. got={:?}", value.span());
+            }
+        }
+
+        if program.span().start.offset != 0 || program.span().end.offset != 25 {
+            panic!("program.span() wrong. got={:?}", program.span());
+        }
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let input = "let x = 5;\nlet = 10;";
+
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let errors = p.parse_program().unwrap_err();
+
+        let expected = Span::new(Position::new(2, 5, 15), Position::new(2, 6, 16));
+        if errors[0].span() != expected {
+            panic!(
+                "errors[0].span() wrong. expected={:?}, got={:?}",
+                expected,
+                errors[0].span()
+            );
+        }
+    }
+
+    #[test]
+    fn test_lexer_errors() {
+        let input = "let pаypal = 1;";
+
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let errors = p.parse_program().unwrap_err();
+
+        if errors.len() != 1 || !matches!(errors[0], ParseError::Lex(_)) {
+            panic!("expected a single ParseError::Lex. got={:?}", errors);
+        }
+    }
+
+    #[test]
+    fn test_string_literal_expression() {
+        let input = r#""hello\t\"world\"";"#;
+
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = check_parser_errors(p.parse_program());
+
+        let stmt = match &program.statements[0] {
+            Statement::ExpressionStatement(stmt) => stmt,
+            _ => panic!(
+                "program.statements[0] is not Statement::ExpressionStatement. got={:?}",
+                program.statements[0]
+            ),
+        };
+
+        let literal = match &stmt.expression {
+            Some(Expression::StringLiteral(literal)) => literal,
+            _ => panic!(
+                "exp not Expression::StringLiteral. got={:?}",
+                stmt.expression
+            ),
+        };
+        if literal.value != "hello\t\"world\"" {
+            panic!(
+                "literal.value not {:?}. got={:?}",
+                "hello\t\"world\"", literal.value
+            );
+        }
+        if program.string() != r#""hello\t\"world\"""# {
+            panic!("program.string() wrong. got={}", program.string());
+        }
+    }
+
+    fn parse_single_expression(input: &str) -> Expression {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = check_parser_errors(p.parse_program());
+
+        if program.statements.len() != 1 {
+            panic!(
+                "program.statements does not contain {} statements. got={}",
+                1,
+                program.statements.len()
+            );
+        }
+
+        match &program.statements[0] {
+            Statement::ExpressionStatement(ExpressionStatement {
+                expression: Some(exp),
+                ..
+            }) => exp.clone(),
+            stmt => panic!(
+                "program.statements[0] is not Statement::ExpressionStatement. got={:?}",
+                stmt
+            ),
+        }
+    }
+
+    #[test]
+    fn test_boolean_expression() {
+        let tests = [("true;", true), ("false;", false)];
+
+        for (input, expected) in tests {
+            match parse_single_expression(input) {
+                Expression::Boolean(boolean) => {
+                    if boolean.value != expected {
+                        panic!("boolean.value not {}. got={}", expected, boolean.value);
+                    }
+                }
+                exp => panic!("exp not Expression::Boolean. got={:?}", exp),
+            }
+        }
+    }
+
+    #[test]
+    fn test_if_expression() {
+        let input = "if (x < y) { x }";
+
+        let exp = match parse_single_expression(input) {
+            Expression::If(exp) => exp,
+            exp => panic!("exp not Expression::If. got={:?}", exp),
+        };
+
+        if exp.condition.string() != "(x < y)" {
+            panic!("exp.condition wrong. got={}", exp.condition.string());
+        }
+        if exp.consequence.statements.len() != 1 {
+            panic!(
+                "consequence is not 1 statements. got={}",
+                exp.consequence.statements.len()
+            );
+        }
+        if exp.consequence.string() != "x" {
+            panic!("exp.consequence wrong. got={}", exp.consequence.string());
+        }
+        if exp.alternative.is_some() {
+            panic!("exp.alternative was not None. got={:?}", exp.alternative);
+        }
+    }
+
+    #[test]
+    fn test_if_else_expression() {
+        let input = "if (x < y) { x } else { y }";
+
+        let exp = match parse_single_expression(input) {
+            Expression::If(exp) => exp,
+            exp => panic!("exp not Expression::If. got={:?}", exp),
+        };
+
+        if exp.consequence.string() != "x" {
+            panic!("exp.consequence wrong. got={}", exp.consequence.string());
+        }
+        match &exp.alternative {
+            Some(alternative) if alternative.string() == "y" => {}
+            alternative => panic!("exp.alternative wrong. got={:?}", alternative),
+        }
+        if exp.span().end.offset != input.len() {
+            panic!(
+                "exp.span() does not reach the closing brace. got={:?}",
+                exp.span()
+            );
+        }
+    }
+
+    #[test]
+    fn test_function_literal_parsing() {
+        let input = "fn(x, y) { x + y; }";
+
+        let function = match parse_single_expression(input) {
+            Expression::FunctionLiteral(function) => function,
+            exp => panic!("exp not Expression::FunctionLiteral. got={:?}", exp),
+        };
+
+        let params: Vec<&str> = function
+            .parameters
+            .iter()
+            .map(|p| p.value.as_str())
+            .collect();
+        if params != ["x", "y"] {
+            panic!("function literal parameters wrong. got={:?}", params);
+        }
+        if function.body.string() != "(x + y)" {
+            panic!("function.body wrong. got={}", function.body.string());
+        }
+    }
+
+    #[test]
+    fn test_function_parameter_parsing() {
+        let tests: [(&str, &[&str]); 3] = [
+            ("fn() {};", &[]),
+            ("fn(x) {};", &["x"]),
+            ("fn(x, y, z) {};", &["x", "y", "z"]),
+        ];
+
+        for (input, expected) in tests {
+            let function = match parse_single_expression(input) {
+                Expression::FunctionLiteral(function) => function,
+                exp => panic!("exp not Expression::FunctionLiteral. got={:?}", exp),
+            };
+
+            let params: Vec<&str> = function
+                .parameters
+                .iter()
+                .map(|p| p.value.as_str())
+                .collect();
+            if params != expected {
+                panic!(
+                    "length parameters wrong. want={:?}, got={:?}",
+                    expected, params
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_call_expression_parsing() {
+        let input = "add(1, 2 * 3, 4 + 5);";
+
+        let exp = match parse_single_expression(input) {
+            Expression::Call(exp) => exp,
+            exp => panic!("exp not Expression::Call. got={:?}", exp),
+        };
+
+        if exp.function.string() != "add" {
+            panic!("exp.function wrong. got={}", exp.function.string());
+        }
+        let args: Vec<String> = exp.arguments.iter().map(|a| a.string()).collect();
+        if args != ["1", "(2 * 3)", "(4 + 5)"] {
+            panic!("exp.arguments wrong. got={:?}", args);
+        }
+        if exp.span().end.offset != input.len() - 1 {
+            panic!(
+                "exp.span() does not reach the closing paren. got={:?}",
+                exp.span()
+            );
+        }
+    }
+
+    #[test]
+    fn test_unclosed_block() {
+        let tests = ["if (x) { x", "fn(x) { x", "fn(x, y"];
+
+        for input in tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+
+            match p.parse_program() {
+                Err(errors) if matches!(errors.last(), Some(ParseError::UnexpectedEof(_))) => {}
+                result => panic!("expected UnexpectedEof for {:?}. got={:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let tests = [
+            (
+                "let = 10; let y = 2;",
+                vec!["expected next token to be IDENT, got ASSIGN instead"],
+                "<error>let y = 2;",
+            ),
+            (
+                "let x = ; x + 1;",
+                vec!["no prefix parse function for SEMICOLON found"],
+                "let x = <error>;(x + 1)",
+            ),
+            (
+                "return * 2\nlet y = 3;",
+                vec!["no prefix parse function for ASTERISK found"],
+                "return <error>;let y = 3;",
+            ),
+            (
+                "let f = fn(x) { x +; 1 }; f(2);",
+                vec!["no prefix parse function for SEMICOLON found"],
+                "let f = fn(x) <error>1;f(2)",
+            ),
+            (
+                "let g = fn(x { x }; let h = 1;",
+                vec!["expected next token to be RPAREN, got LBRACE instead"],
+                "let g = <error>;let h = 1;",
+            ),
+            (
+                "if (x) { let = 1 }\n) let z = 2;",
+                vec![
+                    "expected next token to be IDENT, got ASSIGN instead",
+                    "no prefix parse function for RPAREN found",
+                ],
+                "ifx <error><error>let z = 2;",
+            ),
+            ("add(1, 2", vec!["unexpected end of input"], "<error>"),
+        ];
+
+        for (input, expected_errors, expected_string) in tests {
+            let l = Lexer::new(input);
+            let mut p = Parser::new(l);
+            let (program, errors) = p.parse_program_with_recovery();
+
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            if messages != expected_errors {
+                panic!(
+                    "errors wrong for {:?}. expected={:?}, got={:?}",
+                    input, expected_errors, messages
+                );
+            }
+            // Blocks around the error stay intact.
+            if !expected_string.is_empty() && program.string() != expected_string {
+                panic!(
+                    "program.string() wrong for {:?}. expected={:?}, got={:?}",
+                    input,
+                    expected_string,
+                    program.string()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = format!("{}1{};", "(".repeat(60), ")".repeat(60));
+        let tests = [
+            (
+                format!("{}1; let y = 2;", "(".repeat(100_000)),
+                Some("<error>let y = 2;"),
+            ),
+            (format!("{}1", "-".repeat(200_000)), Some("<error>")),
+            (
+                format!("{}1{}", "if (x) { ".repeat(100_000), " }".repeat(100_000)),
+                // Blocks around the error stay intact.
+                None,
+            ),
+        ];
+
+        for (input, expected_string) in tests {
+            let l = Lexer::new(&input);
+            let mut p = Parser::new(l);
+            let (program, errors) = p.parse_program_with_recovery();
+
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            if messages != ["expression nested too deeply"] {
+                panic!("errors wrong for {:?}... got={:?}", &input[..20], messages);
+            }
+            if expected_string.is_some_and(|expected| program.string() != expected) {
+                panic!(
+                    "program.string() wrong for {:?}... expected={:?}, got={:?}",
+                    &input[..20],
+                    expected_string,
+                    program.string()
+                );
+            }
+        }
+
+        let l = Lexer::new(&nested);
+        let mut p = Parser::new(l);
+        if let Err(errors) = p.parse_program() {
+            panic!("parsing 60 nested parentheses failed: {:?}", errors);
+        }
+    }
+
+    #[test]
+    fn test_error_node_spans() {
+        let input = "let x = 5;\nlet = 10;\nlet y = );";
+
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let (program, errors) = p.parse_program_with_recovery();
+
+        if errors.len() != 2 {
+            panic!("expected 2 errors. got={:?}", errors);
+        }
+
+        let error_stmt = match &program.statements[1] {
+            Statement::Error(error) => error,
+            stmt => panic!("statements[1] is not Statement::Error. got={:?}", stmt),
+        };
+        let expected = Span::new(Position::new(2, 1, 11), Position::new(2, 10, 20));
+        if error_stmt.span != expected {
+            panic!(
+                "error statement span wrong. expected={:?}, got={:?}",
+                expected, error_stmt.span
+            );
+        }
+
+        let value = match &program.statements[2] {
+            Statement::LetStatement(let_stmt) if let_stmt.name.value == "y" => &let_stmt.value,
+            stmt => panic!("statements[2] is not let y. got={:?}", stmt),
+        };
+        let expected = Span::new(Position::new(3, 9, 29), Position::new(3, 11, 31));
+        match value {
+            Some(Expression::Error(error)) if error.span == expected => {}
+            value => panic!(
+                "let value is not an error spanning {:?}. got={:?}",
+                expected, value
+            ),
+        }
+    }
+
+    #[test]
+    fn test_lexer_example_program() {
+        let input = "let five = 5;
+        let ten = 10;
+
+        let add = fn(x, y) {
+          x + y;
+        };
+
+        let result = add(five, ten);
+        !-5;
+        5 < 10 > 5;
+
+        if (5 < 10) {
+          return true;
+        } else {
+          return false;
+        }
+
+        10 == 10;
+        10 != 9;
+        ";
+
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = check_parser_errors(p.parse_program());
+
+        let expected = "let five = 5;\
+            let ten = 10;\
+            let add = fn(x, y) (x + y);\
+            let result = add(five, ten);\
+            (!(-5))\
+            ((5 < 10) > 5)\
+            if(5 < 10) return true;else return false;\
+            (10 == 10)\
+            (10 != 9)";
+        if program.string() != expected {
+            panic!(
+                "program.string() wrong. expected={}, got={}",
+                expected,
+                program.string()
+            );
+        }
+    }
 }
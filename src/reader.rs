@@ -0,0 +1,330 @@
+//! Lexing input read incrementally from a `BufRead`.
+//!
+//! [`ReaderLexer`] keeps only a window of the input in memory: the text from
+//! the start of the next token to the end of the last chunk read. Each token
+//! is lexed by an ordinary [`Lexer`] over that window. If the lexer reached
+//! the end of the window before the input ended, the token may continue in
+//! the next chunk, so another chunk is read and the token is lexed again.
+//! Memory use is therefore bounded by the chunk size plus the longest token
+//! (or comment before a token), whatever the size of the input.
+//!
+//! Byte sequences that are not valid UTF-8 are replaced with U+FFFD and
+//! reported as [`LexError::InvalidUtf8`]. Positions refer to the text after
+//! that replacement, as produced by `String::from_utf8_lossy`.
+
+use std::{
+    io::{self, BufRead, ErrorKind},
+    str,
+};
+
+use crate::{
+    lexer::{LexError, Lexer},
+    token::{Position, Span, Token, TokenType::EOF},
+};
+
+const REPLACEMENT: &str = "\u{FFFD}";
+
+/// A lexer that pulls its input from a reader in chunks. Tokens own their
+/// literals, as the text they were read from is discarded.
+#[derive(Debug)]
+pub struct ReaderLexer<R> {
+    reader: R,
+    /// Decoded input from the offset `base` to the end of the last chunk.
+    window: String,
+    base: usize,
+    /// Position of the first character not yet lexed.
+    cursor: Position,
+    /// Position just after the end of `window`.
+    end: Position,
+    /// Bytes of a character split by the end of the last chunk.
+    partial: Vec<u8>,
+    /// Whether the reader has reached the end of the input.
+    done: bool,
+    errors: Vec<LexError>,
+}
+
+impl<R: BufRead> ReaderLexer<R> {
+    pub fn new(reader: R) -> Self {
+        ReaderLexer {
+            reader,
+            window: String::new(),
+            base: 0,
+            cursor: Position::default(),
+            end: Position::default(),
+            partial: Vec::new(),
+            done: false,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Returns the errors reported so far. Invalid UTF-8 is reported when it
+    /// is read, which may be before the errors in the tokens preceding it.
+    pub fn errors(&self) -> &[LexError] {
+        &self.errors
+    }
+
+    /// Removes and returns the errors reported since the last call.
+    pub fn take_errors(&mut self) -> Vec<LexError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Returns the next token, reading from the reader as needed. Once the
+    /// input is exhausted every call returns `EOF`.
+    pub fn next_token(&mut self) -> io::Result<Token<'static>> {
+        loop {
+            let start = Position {
+                offset: self.cursor.offset - self.base,
+                ..self.cursor
+            };
+            let mut l = Lexer::starting_at(&self.window, start);
+            let mut tok = l.next_token().into_owned();
+
+            // Reading up to the end of the window means the token could run
+            // on into input that has not been read yet.
+            if l.examined() < self.window.len() || self.done {
+                let errors = l.take_errors();
+                self.shift(&mut tok.span);
+                for mut err in errors {
+                    self.shift(err.span_mut());
+                    self.errors.push(err);
+                }
+                self.cursor = tok.span.end;
+                return Ok(tok);
+            }
+
+            self.fill()?;
+        }
+    }
+
+    /// Converts a span relative to the window into one in the whole input.
+    fn shift(&self, span: &mut Span) {
+        span.start.offset += self.base;
+        span.end.offset += self.base;
+    }
+
+    /// Drops the text before the cursor and reads chunks until the window
+    /// has at least doubled, so a long token is lexed again only a
+    /// logarithmic number of times.
+    fn fill(&mut self) -> io::Result<()> {
+        self.window.drain(..self.cursor.offset - self.base);
+        self.base = self.cursor.offset;
+
+        let wanted = self.window.len() * 2;
+        while !self.done {
+            self.read_chunk()?;
+            if self.window.len() >= wanted {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the next chunk of input to the window.
+    fn read_chunk(&mut self) -> io::Result<()> {
+        let chunk = loop {
+            match self.reader.fill_buf() {
+                Ok(chunk) => break chunk,
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        };
+        if chunk.is_empty() {
+            self.done = true;
+            if !self.partial.is_empty() {
+                self.partial.clear();
+                self.push_invalid();
+            }
+            return Ok(());
+        }
+
+        let len = chunk.len();
+        self.partial.extend_from_slice(chunk);
+        self.reader.consume(len);
+
+        let buffered = std::mem::take(&mut self.partial);
+        let mut bytes = &buffered[..];
+        loop {
+            match str::from_utf8(bytes) {
+                Ok(text) => {
+                    self.push(text);
+                    break;
+                }
+                Err(err) => {
+                    let (valid, rest) = bytes.split_at(err.valid_up_to());
+                    // Checked by `from_utf8` above.
+                    self.push(str::from_utf8(valid).unwrap());
+                    match err.error_len() {
+                        Some(len) => {
+                            self.push_invalid();
+                            bytes = &rest[len..];
+                        }
+                        None => {
+                            self.partial = rest.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, text: &str) {
+        self.window.push_str(text);
+        self.end = self.end.advance(text);
+    }
+
+    fn push_invalid(&mut self) {
+        let start = self.end;
+        self.push(REPLACEMENT);
+        self.errors.push(LexError::InvalidUtf8 {
+            span: Span::new(start, self.end),
+        });
+    }
+}
+
+/// Yields the tokens of the input and any error from the reader, ending
+/// before `EOF`.
+impl<R: BufRead> Iterator for ReaderLexer<R> {
+    type Item = io::Result<Token<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_token() {
+            Ok(tok) if tok.token_type == EOF => None,
+            result => Some(result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Read};
+
+    use super::ReaderLexer;
+    use crate::{
+        lexer::{LexError, Lexer},
+        token::{Position, Span},
+    };
+
+    #[test]
+    fn test_chunk_boundaries() {
+        let inputs = [
+            "",
+            "let five = 5;\nlet add = fn(x, y) { x + y; };\n",
+            "  // comment\n/* block /* nested */ */ \"a\\tb\\u{e9}\" != 10 == identifiér",
+            "if (x < 12345) { return \"café\"; } else { !true }",
+            "\"unterminated /* comment",
+            "x /* unterminated \"string",
+            "\"bad \\q escape\" Сity @",
+        ];
+
+        for input in inputs {
+            let mut expected = Vec::new();
+            let mut l = Lexer::new(input);
+            expected.extend(l.by_ref().map(|tok| tok.into_owned()));
+            let expected_errors = l.take_errors();
+
+            for capacity in [1, 2, 3, 5, 16, 4096] {
+                let mut lexer =
+                    ReaderLexer::new(BufReader::with_capacity(capacity, input.as_bytes()));
+                let tokens: Vec<_> = lexer.by_ref().map(Result::unwrap).collect();
+                if tokens != expected || lexer.errors() != expected_errors {
+                    panic!(
+                        "lexing {:?} in chunks of {} wrong. expected={:?} {:?}, got={:?} {:?}",
+                        input,
+                        capacity,
+                        expected,
+                        expected_errors,
+                        tokens,
+                        lexer.errors()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let input: &[u8] = b"let x\xff = \"a\xe2\x82\";\n\xf0\x9f\x98\x80 \xe2\x82";
+        let text = String::from_utf8_lossy(input);
+        let expected: Vec<_> = Lexer::new(&text).map(|tok| tok.into_owned()).collect();
+        let expected_errors = [
+            LexError::InvalidUtf8 {
+                span: Span::new(Position::new(1, 6, 5), Position::new(1, 7, 8)),
+            },
+            LexError::InvalidUtf8 {
+                span: Span::new(Position::new(1, 12, 13), Position::new(1, 13, 16)),
+            },
+            LexError::InvalidUtf8 {
+                span: Span::new(Position::new(2, 3, 24), Position::new(2, 4, 27)),
+            },
+        ];
+
+        for capacity in [1, 2, 3, 4096] {
+            let mut lexer = Lexer::from_reader(BufReader::with_capacity(capacity, input));
+            let tokens: Vec<_> = lexer.by_ref().map(Result::unwrap).collect();
+            if tokens != expected || lexer.errors() != expected_errors {
+                panic!(
+                    "lexing in chunks of {} wrong. expected={:?} {:?}, got={:?} {:?}",
+                    capacity,
+                    expected,
+                    expected_errors,
+                    tokens,
+                    lexer.errors()
+                );
+            }
+        }
+    }
+
+    /// Produces `count` copies of `text` without holding them in memory.
+    struct Repeat {
+        text: &'static [u8],
+        count: usize,
+        offset: usize,
+    }
+
+    impl Read for Repeat {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.count == 0 {
+                return Ok(0);
+            }
+            let len = buf.len().min(self.text.len() - self.offset);
+            buf[..len].copy_from_slice(&self.text[self.offset..self.offset + len]);
+            self.offset += len;
+            if self.offset == self.text.len() {
+                self.offset = 0;
+                self.count -= 1;
+            }
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_bounded_memory() {
+        let text = b"let answer = fn(x) { x * 42 }; // comment\n";
+        let reader = Repeat {
+            text,
+            count: 100_000,
+            offset: 0,
+        };
+        let mut lexer = Lexer::from_reader(BufReader::with_capacity(1024, reader));
+
+        let mut tokens = 0;
+        let mut largest = 0;
+        while let Some(tok) = lexer.next() {
+            tok.unwrap();
+            tokens += 1;
+            largest = largest.max(lexer.window.capacity());
+        }
+
+        if tokens != 100_000 * 13 {
+            panic!("token count wrong. got={}", tokens);
+        }
+        if largest > 4096 {
+            panic!("window grew to {} bytes", largest);
+        }
+        if lexer.cursor.line != 100_001 {
+            panic!("final position wrong. got={:?}", lexer.cursor);
+        }
+    }
+}
@@ -1,23 +1,275 @@
-use std::io::Write;
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
 
-use crate::{lexer::Lexer, token::TokenType::EOF};
+use monkey_rust::{
+    diagnostic::{ColorMode, Diagnostic},
+    evaluator::eval,
+    lexer::Lexer,
+    object::{Env, Environment},
+    parse,
+    token::TokenType::{LBRACE, LPAREN, RBRACE, RPAREN},
+    LexError,
+};
 
 const PROMPT: &str = ">> ";
+const FILE_NAME: &str = "<repl>";
+const CONTINUATION_PROMPT: &str = ".. ";
+
+const HELP: &str = ":eval       evaluate each input (default)
+:tokens     print the tokens of each input
+:ast        print the parsed program of each input
+:reset      forget all bindings
+:load FILE  evaluate FILE in the current environment
+:help       print this message
+:quit       exit the REPL (as does Ctrl-D)";
+
+/// What the REPL shows for each input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Eval,
+    Tokens,
+    Ast,
+}
 
 pub fn start() {
+    let stdout = io::stdout();
+    let color = ColorMode::detect(&stdout);
+    if let Err(err) = run(io::stdin().lock(), stdout, color) {
+        eprintln!("monkey: {}", err);
+    }
+}
+
+/// Runs the REPL until `input` reaches end of file or `:quit` is entered.
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W, color: ColorMode) -> io::Result<()> {
     let mut buf = String::new();
+    let mut env = Environment::new();
+    let mut mode = Mode::Eval;
     loop {
-        print!("{}", PROMPT);
-        std::io::stdout().flush().unwrap();
-        std::io::stdin().read_line(&mut buf).unwrap();
-        let mut l = Lexer::new(&buf);
-        loop {
-            let tok = l.next_token();
-            if tok.token_type == EOF {
-                break;
+        let prompt = if buf.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+
+        if input.read_line(&mut buf)? == 0 {
+            writeln!(output)?;
+            return Ok(());
+        }
+
+        if let Some(command) = buf.trim().strip_prefix(':') {
+            let (name, arg) = match command.split_once(char::is_whitespace) {
+                Some((name, arg)) => (name, arg.trim()),
+                None => (command, ""),
+            };
+            match name {
+                "eval" => mode = Mode::Eval,
+                "tokens" => mode = Mode::Tokens,
+                "ast" => mode = Mode::Ast,
+                "reset" => env = Environment::new(),
+                "load" if arg.is_empty() => writeln!(output, ":load expects a file name")?,
+                "load" => match fs::read_to_string(arg) {
+                    Ok(source) => evaluate(arg, &source, &env, &mut output, color)?,
+                    Err(err) => writeln!(output, "cannot read {}: {}", arg, err)?,
+                },
+                "help" => writeln!(output, "{}", HELP)?,
+                "quit" => return Ok(()),
+                _ => writeln!(output, "unknown command :{}, try :help", name)?,
+            }
+            buf.clear();
+            continue;
+        }
+
+        if is_incomplete(&buf) {
+            continue;
+        }
+
+        match mode {
+            Mode::Eval => evaluate(FILE_NAME, &buf, &env, &mut output, color)?,
+            Mode::Tokens => {
+                let mut l = Lexer::new(&buf);
+                for tok in l.by_ref() {
+                    writeln!(
+                        output,
+                        "token_type:{:?} literal:{}",
+                        tok.token_type, tok.literal
+                    )?;
+                }
+                print_errors(FILE_NAME, &buf, &l.take_errors(), &mut output, color)?;
             }
-            println!("token_type:{:?} literal:{}", tok.token_type, tok.literal);
+            Mode::Ast => match parse(&buf) {
+                Ok(program) => writeln!(output, "{}", program.string())?,
+                Err(errors) => print_errors(FILE_NAME, &buf, &errors, &mut output, color)?,
+            },
         }
         buf.clear()
     }
 }
+
+fn evaluate<W: Write>(
+    file_name: &str,
+    source: &str,
+    env: &Env,
+    output: &mut W,
+    color: ColorMode,
+) -> io::Result<()> {
+    match parse(source) {
+        Ok(program) => {
+            if let Some(evaluated) = eval(&program, env) {
+                writeln!(output, "{}", evaluated.inspect())?;
+            }
+            Ok(())
+        }
+        Err(errors) => print_errors(file_name, source, &errors, output, color),
+    }
+}
+
+fn print_errors<'e, E, W: Write>(
+    file_name: &str,
+    source: &str,
+    errors: &'e [E],
+    output: &mut W,
+    color: ColorMode,
+) -> io::Result<()>
+where
+    Diagnostic: From<&'e E>,
+{
+    for err in errors {
+        write!(
+            output,
+            "{}",
+            Diagnostic::from(err).render(file_name, source, color)
+        )?;
+    }
+    Ok(())
+}
+
+/// Reports whether `input` has unclosed braces, parentheses, strings or
+/// block comments and so should be continued on the next line.
+fn is_incomplete(input: &str) -> bool {
+    let mut l = Lexer::new(input);
+    let mut depth = 0;
+    for tok in l.by_ref() {
+        match tok.token_type {
+            LBRACE | LPAREN => depth += 1,
+            RBRACE | RPAREN => depth -= 1,
+            _ => {}
+        }
+    }
+
+    depth > 0
+        || l.errors().iter().any(|err| {
+            matches!(
+                err,
+                LexError::UnterminatedString { .. } | LexError::UnterminatedBlockComment { .. }
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use monkey_rust::diagnostic::ColorMode;
+
+    use super::{is_incomplete, run};
+
+    fn repl(input: &str) -> String {
+        let mut output = Vec::new();
+        run(input.as_bytes(), &mut output, ColorMode::Plain).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_is_incomplete() {
+        let tests = [
+            ("let x = 5;", false),
+            ("let f = fn(x) {", true),
+            ("let f = fn(x) {\n x\n}", false),
+            ("add(1,", true),
+            ("\"unterminated", true),
+            ("/* open comment", true),
+            ("\"{\"", false),
+            ("}", false),
+        ];
+
+        for (input, expected) in tests {
+            if is_incomplete(input) != expected {
+                panic!("is_incomplete({:?}) wrong. want={}", input, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_eof_exits() {
+        let output = repl("1 + 2\n");
+        if output != ">> 3\n>> \n" {
+            panic!("output wrong. got={:?}", output);
+        }
+    }
+
+    #[test]
+    fn test_continuation_prompt() {
+        let output = repl("let add = fn(x, y) {\nx + y\n};\nadd(1, 2)\n");
+        if output != ">> .. .. >> 3\n>> \n" {
+            panic!("output wrong. got={:?}", output);
+        }
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let output =
+            repl(":tokens\nlet\n:ast\n-1 + 2\n:eval\nlet x = 1;\n:reset\nx\n:frob\n:quit\n1\n");
+        let expected = ">> >> token_type:LET literal:let
+>> >> ((-1) + 2)
+>> >> >> >> ERROR: identifier not found: x
+>> unknown command :frob, try :help
+>> ";
+        if output != expected {
+            panic!("output wrong. want={:?}, got={:?}", expected, output);
+        }
+    }
+
+    #[test]
+    fn test_parser_errors() {
+        let output = repl("let = 1;\n");
+        let expected = ">> error: expected next token to be IDENT, got ASSIGN instead
+ --> <repl>:1:5
+  |
+1 | let = 1;
+  |     ^ expected IDENT
+>> \n";
+        if output != expected {
+            panic!("output wrong. want={:?}, got={:?}", expected, output);
+        }
+    }
+
+    #[test]
+    fn test_lexer_errors_in_tokens_mode() {
+        let output = repl(":tokens\n\"a\\q\"\n");
+        let expected = ">> >> token_type:STRING literal:a
+error: invalid escape sequence \\q
+ --> <repl>:1:3
+  |
+1 | \"a\\q\"
+  |   ^^
+  |
+  = help: the valid escapes are \\n, \\t, \\\\, \\\" and \\u{...} with 1 to 6 hex digits
+>> \n";
+        if output != expected {
+            panic!("output wrong. want={:?}, got={:?}", expected, output);
+        }
+    }
+
+    #[test]
+    fn test_load() {
+        let path = std::env::temp_dir().join(format!("monkey-repl-{}.mk", std::process::id()));
+        std::fs::write(&path, "let double = fn(x) { x * 2 };").unwrap();
+
+        let output = repl(&format!(":load {}\ndouble(21)\n:load\n", path.display()));
+        if output != ">> >> 42\n>> :load expects a file name\n>> \n" {
+            panic!("output wrong. got={:?}", output);
+        }
+    }
+}
@@ -0,0 +1,600 @@
+//! Lossless conversion of tokens and programs to and from JSON and
+//! S-expressions, for tools written in other languages.
+//!
+//! A token is `{"type": "LET", "literal": "let", "span": span}` where a span
+//! is `{"start": [line, column, offset], "end": [line, column, offset]}`. A
+//! node is an object whose `kind` is the name of its AST type followed by the
+//! node's fields under their Rust names, for example
+//!
+//! ```text
+//! {"kind": "Identifier", "token": token, "value": "x"}
+//! ```
+//!
+//! Missing optional children are `null`. Error placeholders have the kind
+//! `Error` whether they stand for a statement or an expression. The
+//! S-expression form is the same document written with [`crate::sexp`].
+
+use std::fmt;
+
+use crate::{
+    ast::{
+        BlockStatement, Boolean, CallExpression, ErrorNode, Expression, ExpressionStatement,
+        FunctionLiteral, Identifier, IfExpression, InfixExpression, IntegerLiteral, LetStatement,
+        PrefixExpression, Program, ReturnStatement, Statement, StringLiteral,
+    },
+    json::{self, JsonError, Value},
+    sexp::{self, SexpError},
+    token::{Position, Span, Token, TokenType},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeserializeError {
+    Json(JsonError),
+    Sexp(SexpError),
+    /// The document is well formed but does not describe a program.
+    Invalid(String),
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "invalid JSON: {}", err),
+            Self::Sexp(err) => write!(f, "invalid S-expression: {}", err),
+            Self::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+pub fn to_json(program: &Program) -> String {
+    program_to_value(program).to_string()
+}
+
+pub fn to_sexp(program: &Program) -> String {
+    sexp::to_string(&program_to_value(program))
+}
+
+pub fn from_json(input: &str) -> Result<Program, DeserializeError> {
+    program_from_value(&json::parse(input).map_err(DeserializeError::Json)?)
+}
+
+pub fn from_sexp(input: &str) -> Result<Program, DeserializeError> {
+    program_from_value(&sexp::parse(input).map_err(DeserializeError::Sexp)?)
+}
+
+pub fn tokens_to_value(tokens: &[Token]) -> Value {
+    Value::Array(tokens.iter().map(token_to_value).collect())
+}
+
+pub fn tokens_from_value(value: &Value) -> Result<Vec<Token<'static>>, DeserializeError> {
+    array(value, "tokens")?
+        .iter()
+        .map(token_from_value)
+        .collect()
+}
+
+pub fn token_to_value(token: &Token) -> Value {
+    Value::object([
+        ("type", Value::from(format!("{:?}", token.token_type))),
+        ("literal", Value::from(token.literal.as_ref())),
+        ("span", span_to_value(token.span)),
+    ])
+}
+
+pub fn token_from_value(value: &Value) -> Result<Token<'static>, DeserializeError> {
+    let name = string(field(value, "token", "type")?, "token type")?;
+    let token_type = TokenType::ALL
+        .into_iter()
+        .find(|token_type| format!("{:?}", token_type) == name)
+        .ok_or_else(|| invalid(format!("unknown token type {}", name)))?;
+
+    Ok(Token {
+        token_type,
+        literal: string(field(value, "token", "literal")?, "token literal")?
+            .to_string()
+            .into(),
+        span: span_from_value(field(value, "token", "span")?)?,
+    })
+}
+
+fn span_to_value(span: Span) -> Value {
+    let position = |p: Position| Value::from(vec![p.line.into(), p.column.into(), p.offset.into()]);
+    Value::object([("start", position(span.start)), ("end", position(span.end))])
+}
+
+fn span_from_value(value: &Value) -> Result<Span, DeserializeError> {
+    let position = |name| -> Result<Position, DeserializeError> {
+        match array(field(value, "span", name)?, "position")? {
+            [line, column, offset] => Ok(Position::new(
+                index(line, "line")?,
+                index(column, "column")?,
+                index(offset, "offset")?,
+            )),
+            _ => Err(invalid("expected [line, column, offset] for position")),
+        }
+    };
+    Ok(Span::new(position("start")?, position("end")?))
+}
+
+pub fn program_to_value(program: &Program) -> Value {
+    Value::object([
+        ("kind", Value::from("Program")),
+        ("statements", statements_to_value(&program.statements)),
+    ])
+}
+
+pub fn program_from_value(value: &Value) -> Result<Program, DeserializeError> {
+    expect_kind(value, "Program")?;
+    Ok(Program {
+        statements: statements_from_value(field(value, "Program", "statements")?)?,
+    })
+}
+
+fn statements_to_value(statements: &[Statement]) -> Value {
+    Value::Array(statements.iter().map(statement_to_value).collect())
+}
+
+fn statements_from_value(value: &Value) -> Result<Vec<Statement>, DeserializeError> {
+    array(value, "statements")?
+        .iter()
+        .map(statement_from_value)
+        .collect()
+}
+
+fn statement_to_value(statement: &Statement) -> Value {
+    match statement {
+        Statement::LetStatement(let_stmt) => Value::object([
+            ("kind", Value::from("LetStatement")),
+            ("token", token_to_value(&let_stmt.token)),
+            ("name", identifier_to_value(&let_stmt.name)),
+            ("value", optional_expression_to_value(&let_stmt.value)),
+        ]),
+        Statement::ReturnStatement(return_stmt) => Value::object([
+            ("kind", Value::from("ReturnStatement")),
+            ("token", token_to_value(&return_stmt.token)),
+            (
+                "return_value",
+                optional_expression_to_value(&return_stmt.return_value),
+            ),
+        ]),
+        Statement::ExpressionStatement(expression_stmt) => Value::object([
+            ("kind", Value::from("ExpressionStatement")),
+            ("token", token_to_value(&expression_stmt.token)),
+            (
+                "expression",
+                optional_expression_to_value(&expression_stmt.expression),
+            ),
+        ]),
+        Statement::Error(error) => error_to_value(error),
+    }
+}
+
+fn statement_from_value(value: &Value) -> Result<Statement, DeserializeError> {
+    let kind = kind(value)?;
+    let token = || token_from_value(field(value, kind, "token")?);
+    let statement = match kind {
+        "LetStatement" => Statement::LetStatement(LetStatement {
+            token: token()?,
+            name: identifier_from_value(field(value, kind, "name")?)?,
+            value: optional_expression_from_value(field(value, kind, "value")?)?,
+        }),
+        "ReturnStatement" => Statement::ReturnStatement(ReturnStatement {
+            token: token()?,
+            return_value: optional_expression_from_value(field(value, kind, "return_value")?)?,
+        }),
+        "ExpressionStatement" => Statement::ExpressionStatement(ExpressionStatement {
+            token: token()?,
+            expression: optional_expression_from_value(field(value, kind, "expression")?)?,
+        }),
+        "Error" => Statement::Error(error_from_value(value)?),
+        _ => return Err(invalid(format!("unknown statement kind {}", kind))),
+    };
+    Ok(statement)
+}
+
+fn block_to_value(block: &BlockStatement) -> Value {
+    Value::object([
+        ("kind", Value::from("BlockStatement")),
+        ("token", token_to_value(&block.token)),
+        ("statements", statements_to_value(&block.statements)),
+        ("rbrace", token_to_value(&block.rbrace)),
+    ])
+}
+
+fn block_from_value(value: &Value) -> Result<BlockStatement, DeserializeError> {
+    let kind = "BlockStatement";
+    expect_kind(value, kind)?;
+    Ok(BlockStatement {
+        token: token_from_value(field(value, kind, "token")?)?,
+        statements: statements_from_value(field(value, kind, "statements")?)?,
+        rbrace: token_from_value(field(value, kind, "rbrace")?)?,
+    })
+}
+
+fn identifier_to_value(identifier: &Identifier) -> Value {
+    Value::object([
+        ("kind", Value::from("Identifier")),
+        ("token", token_to_value(&identifier.token)),
+        ("value", Value::from(identifier.value.as_str())),
+    ])
+}
+
+fn identifier_from_value(value: &Value) -> Result<Identifier, DeserializeError> {
+    let kind = "Identifier";
+    expect_kind(value, kind)?;
+    Ok(Identifier {
+        token: token_from_value(field(value, kind, "token")?)?,
+        value: string(field(value, kind, "value")?, "Identifier value")?.to_string(),
+    })
+}
+
+fn error_to_value(error: &ErrorNode) -> Value {
+    Value::object([
+        ("kind", Value::from("Error")),
+        ("token", token_to_value(&error.token)),
+        ("span", span_to_value(error.span)),
+    ])
+}
+
+fn error_from_value(value: &Value) -> Result<ErrorNode, DeserializeError> {
+    Ok(ErrorNode {
+        token: token_from_value(field(value, "Error", "token")?)?,
+        span: span_from_value(field(value, "Error", "span")?)?,
+    })
+}
+
+fn optional_expression_to_value(expression: &Option<Expression>) -> Value {
+    expression.as_ref().map_or(Value::Null, expression_to_value)
+}
+
+fn optional_expression_from_value(value: &Value) -> Result<Option<Expression>, DeserializeError> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    expression_from_value(value).map(Some)
+}
+
+fn expression_to_value(expression: &Expression) -> Value {
+    match expression {
+        Expression::Identifier(identifier) => identifier_to_value(identifier),
+        Expression::IntegerLiteral(integer_literal) => Value::object([
+            ("kind", Value::from("IntegerLiteral")),
+            ("token", token_to_value(&integer_literal.token)),
+            ("value", Value::from(integer_literal.value)),
+        ]),
+        Expression::StringLiteral(string_literal) => Value::object([
+            ("kind", Value::from("StringLiteral")),
+            ("token", token_to_value(&string_literal.token)),
+            ("value", Value::from(string_literal.value.as_str())),
+        ]),
+        Expression::Boolean(boolean) => Value::object([
+            ("kind", Value::from("Boolean")),
+            ("token", token_to_value(&boolean.token)),
+            ("value", Value::from(boolean.value)),
+        ]),
+        Expression::Prefix(prefix) => Value::object([
+            ("kind", Value::from("PrefixExpression")),
+            ("token", token_to_value(&prefix.token)),
+            ("operator", Value::from(prefix.operator.as_str())),
+            ("right", expression_to_value(&prefix.right)),
+        ]),
+        Expression::Infix(infix) => Value::object([
+            ("kind", Value::from("InfixExpression")),
+            ("token", token_to_value(&infix.token)),
+            ("left", expression_to_value(&infix.left)),
+            ("operator", Value::from(infix.operator.as_str())),
+            ("right", expression_to_value(&infix.right)),
+        ]),
+        Expression::If(if_expression) => Value::object([
+            ("kind", Value::from("IfExpression")),
+            ("token", token_to_value(&if_expression.token)),
+            ("condition", expression_to_value(&if_expression.condition)),
+            ("consequence", block_to_value(&if_expression.consequence)),
+            (
+                "alternative",
+                if_expression
+                    .alternative
+                    .as_ref()
+                    .map_or(Value::Null, block_to_value),
+            ),
+        ]),
+        Expression::FunctionLiteral(function_literal) => Value::object([
+            ("kind", Value::from("FunctionLiteral")),
+            ("token", token_to_value(&function_literal.token)),
+            (
+                "parameters",
+                Value::Array(
+                    function_literal
+                        .parameters
+                        .iter()
+                        .map(identifier_to_value)
+                        .collect(),
+                ),
+            ),
+            ("body", block_to_value(&function_literal.body)),
+        ]),
+        Expression::Call(call) => Value::object([
+            ("kind", Value::from("CallExpression")),
+            ("token", token_to_value(&call.token)),
+            ("function", expression_to_value(&call.function)),
+            (
+                "arguments",
+                Value::Array(call.arguments.iter().map(expression_to_value).collect()),
+            ),
+            ("rparen", token_to_value(&call.rparen)),
+        ]),
+        Expression::Error(error) => error_to_value(error),
+    }
+}
+
+fn expression_from_value(value: &Value) -> Result<Expression, DeserializeError> {
+    let kind = kind(value)?;
+    let token = || token_from_value(field(value, kind, "token")?);
+    let child = |name| -> Result<Box<Expression>, DeserializeError> {
+        expression_from_value(field(value, kind, name)?).map(Box::new)
+    };
+    let operator = || -> Result<String, DeserializeError> {
+        Ok(string(field(value, kind, "operator")?, "operator")?.to_string())
+    };
+
+    let expression = match kind {
+        "Identifier" => Expression::Identifier(identifier_from_value(value)?),
+        "IntegerLiteral" => Expression::IntegerLiteral(IntegerLiteral {
+            token: token()?,
+            value: field(value, kind, "value")?
+                .as_i64()
+                .ok_or_else(|| invalid("expected an integer for IntegerLiteral value"))?,
+        }),
+        "StringLiteral" => Expression::StringLiteral(StringLiteral {
+            token: token()?,
+            value: string(field(value, kind, "value")?, "StringLiteral value")?.to_string(),
+        }),
+        "Boolean" => Expression::Boolean(Boolean {
+            token: token()?,
+            value: field(value, kind, "value")?
+                .as_bool()
+                .ok_or_else(|| invalid("expected a boolean for Boolean value"))?,
+        }),
+        "PrefixExpression" => Expression::Prefix(PrefixExpression {
+            token: token()?,
+            operator: operator()?,
+            right: child("right")?,
+        }),
+        "InfixExpression" => Expression::Infix(InfixExpression {
+            token: token()?,
+            left: child("left")?,
+            operator: operator()?,
+            right: child("right")?,
+        }),
+        "IfExpression" => {
+            let alternative = field(value, kind, "alternative")?;
+            Expression::If(IfExpression {
+                token: token()?,
+                condition: child("condition")?,
+                consequence: block_from_value(field(value, kind, "consequence")?)?,
+                alternative: if alternative.is_null() {
+                    None
+                } else {
+                    Some(block_from_value(alternative)?)
+                },
+            })
+        }
+        "FunctionLiteral" => Expression::FunctionLiteral(FunctionLiteral {
+            token: token()?,
+            parameters: array(field(value, kind, "parameters")?, "parameters")?
+                .iter()
+                .map(identifier_from_value)
+                .collect::<Result<_, _>>()?,
+            body: block_from_value(field(value, kind, "body")?)?,
+        }),
+        "CallExpression" => Expression::Call(CallExpression {
+            token: token()?,
+            function: child("function")?,
+            arguments: array(field(value, kind, "arguments")?, "arguments")?
+                .iter()
+                .map(expression_from_value)
+                .collect::<Result<_, _>>()?,
+            rparen: token_from_value(field(value, kind, "rparen")?)?,
+        }),
+        "Error" => Expression::Error(error_from_value(value)?),
+        _ => return Err(invalid(format!("unknown expression kind {}", kind))),
+    };
+    Ok(expression)
+}
+
+fn invalid(message: impl Into<String>) -> DeserializeError {
+    DeserializeError::Invalid(message.into())
+}
+
+fn kind(value: &Value) -> Result<&str, DeserializeError> {
+    value
+        .get("kind")
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid("expected a node with a kind"))
+}
+
+fn expect_kind(value: &Value, expected: &str) -> Result<(), DeserializeError> {
+    match kind(value)? {
+        kind if kind == expected => Ok(()),
+        kind => Err(invalid(format!("expected {}, got {}", expected, kind))),
+    }
+}
+
+/// Returns the member `name` of `value`, which is described as `owner` in
+/// the error.
+fn field<'a>(value: &'a Value, owner: &str, name: &str) -> Result<&'a Value, DeserializeError> {
+    value
+        .get(name)
+        .ok_or_else(|| invalid(format!("missing {} in {}", name, owner)))
+}
+
+fn string<'a>(value: &'a Value, what: &str) -> Result<&'a str, DeserializeError> {
+    value
+        .as_str()
+        .ok_or_else(|| invalid(format!("expected a string for {}", what)))
+}
+
+fn array<'a>(value: &'a Value, what: &str) -> Result<&'a [Value], DeserializeError> {
+    value
+        .as_array()
+        .ok_or_else(|| invalid(format!("expected an array for {}", what)))
+}
+
+fn index(value: &Value, what: &str) -> Result<usize, DeserializeError> {
+    value
+        .as_i64()
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or_else(|| invalid(format!("expected a non-negative integer for {}", what)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::Lexer,
+        parse, parse_with_recovery,
+        token::{Token, TokenType},
+    };
+
+    use super::{
+        from_json, from_sexp, to_json, to_sexp, tokens_from_value, tokens_to_value,
+        DeserializeError,
+    };
+
+    const PROGRAMS: [&str; 6] = [
+        "let x = 5; return x;",
+        "let add = fn(a, b) { a + b * -c }; add(1, add(2, 3));",
+        "if (x < 10) { \"small\\n\" } else { !true == false }",
+        "let f = fn() { if (n) { return 1; } }; f()",
+        "let s = \"é \\u{1F600} \\\"q\\\"\";\n\t99",
+        "let = 1; let y = ; f(1, 2",
+    ];
+
+    #[test]
+    fn test_round_trip() {
+        for input in PROGRAMS {
+            let (program, _) = parse_with_recovery(input);
+            let expected = format!("{:?}", program);
+
+            let json = to_json(&program);
+            match from_json(&json) {
+                Ok(decoded) if format!("{:?}", decoded) == expected => {}
+                result => panic!("JSON round trip of {:?} wrong. got={:?}", input, result),
+            }
+
+            let sexp = to_sexp(&program);
+            match from_sexp(&sexp) {
+                Ok(decoded) if format!("{:?}", decoded) == expected => {}
+                result => panic!("sexp round trip of {:?} wrong. got={:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_json() {
+        let program = parse("-x").unwrap();
+
+        let expected = concat!(
+            r#"{"kind":"Program","statements":[{"kind":"ExpressionStatement","#,
+            r#""token":{"type":"MINUS","literal":"-","span":{"start":[1,1,0],"end":[1,2,1]}},"#,
+            r#""expression":{"kind":"PrefixExpression","#,
+            r#""token":{"type":"MINUS","literal":"-","span":{"start":[1,1,0],"end":[1,2,1]}},"#,
+            r#""operator":"-","right":{"kind":"Identifier","#,
+            r#""token":{"type":"IDENT","literal":"x","span":{"start":[1,2,1],"end":[1,3,2]}},"#,
+            r#""value":"x"}}}]}"#,
+        );
+        if to_json(&program) != expected {
+            panic!(
+                "to_json wrong.\nwant={}\ngot= {}",
+                expected,
+                to_json(&program)
+            );
+        }
+    }
+
+    #[test]
+    fn test_to_sexp() {
+        let program = parse("return 1;").unwrap();
+
+        let expected = r#"(Program
+  :statements ((ReturnStatement
+                 :token (:type "RETURN"
+                         :literal "return"
+                         :span (:start (1 1 0) :end (1 7 6)))
+                 :return_value (IntegerLiteral
+                                 :token (:type "INT"
+                                         :literal "1"
+                                         :span (:start (1 8 7) :end (1 9 8)))
+                                 :value 1))))"#;
+        if to_sexp(&program) != expected {
+            panic!(
+                "to_sexp wrong.\nwant=\n{}\ngot=\n{}",
+                expected,
+                to_sexp(&program)
+            );
+        }
+    }
+
+    #[test]
+    fn test_tokens() {
+        let mut l = Lexer::new("let s = \"a\\tb\";");
+        let mut tokens = Vec::new();
+        loop {
+            let tok = l.next_token();
+            let eof = tok.token_type == TokenType::EOF;
+            tokens.push(tok);
+            if eof {
+                break;
+            }
+        }
+
+        let value = tokens_to_value(&tokens);
+        let decoded: Vec<Token> =
+            match tokens_from_value(&crate::json::parse(&value.to_string()).unwrap()) {
+                Ok(decoded) => decoded,
+                Err(err) => panic!("tokens_from_value failed: {}", err),
+            };
+        if decoded != tokens {
+            panic!("token round trip wrong. got={:?}", decoded);
+        }
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let tests = [
+            ("[1,", "invalid JSON: unexpected end of input at byte 3"),
+            ("{\"kind\":\"Module\"}", "expected Program, got Module"),
+            ("{\"kind\":\"Program\"}", "missing statements in Program"),
+            (
+                "{\"kind\":\"Program\",\"statements\":[{\"kind\":\"Loop\"}]}",
+                "unknown statement kind Loop",
+            ),
+            (
+                "{\"kind\":\"Program\",\"statements\":[{\"kind\":\"ExpressionStatement\",\
+                 \"token\":{\"type\":\"WHILE\",\"literal\":\"while\",\"span\":null}}]}",
+                "unknown token type WHILE",
+            ),
+            (
+                "{\"kind\":\"Program\",\"statements\":[{\"kind\":\"ExpressionStatement\",\
+                 \"token\":{\"type\":\"INT\",\"literal\":\"1\",\
+                 \"span\":{\"start\":[1,1],\"end\":[1,2,1]}}}]}",
+                "expected [line, column, offset] for position",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            match from_json(input) {
+                Err(err) if err.to_string() == expected => {}
+                result => panic!(
+                    "from_json({:?}) wrong. want={:?}, got={:?}",
+                    input, expected, result
+                ),
+            }
+        }
+
+        match from_sexp("(Program :statements (1)") {
+            Err(DeserializeError::Sexp(_)) => {}
+            result => panic!("from_sexp wrong. got={:?}", result),
+        }
+    }
+}
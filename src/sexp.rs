@@ -0,0 +1,457 @@
+//! An S-expression syntax for [`json::Value`](crate::json::Value).
+//!
+//! Arrays are lists of their elements, `(1 2 3)`. Objects are lists of
+//! `:key value` pairs, headed by the value of their `kind` member when it is a
+//! symbol, so `{"kind": "Identifier", "value": "x"}` is
+//! `(Identifier :value "x")` and `{"line": 1}` is `(:line 1)`. Null and the
+//! booleans are `nil`, `true` and `false`, and strings use the escapes of
+//! Monkey string literals. An empty object reads back as an empty list.
+
+use std::fmt;
+
+use crate::json::Value;
+
+/// Lists longer than this are broken across lines.
+const WIDTH: usize = 80;
+const INDENT: usize = 2;
+
+/// Renders `value` on a single line if it fits and indented otherwise.
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_pretty(&mut out, value, 0);
+    out
+}
+
+fn write_pretty(out: &mut String, value: &Value, column: usize) {
+    let flat = to_flat_string(value);
+    if column + flat.chars().count() <= WIDTH {
+        out.push_str(&flat);
+        return;
+    }
+
+    match value {
+        Value::Array(values) => {
+            out.push('(');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    newline(out, column + 1);
+                }
+                write_pretty(out, value, column + 1);
+            }
+            out.push(')');
+        }
+        Value::Object(members) => {
+            out.push('(');
+            let head = kind(members);
+            let members = match head {
+                Some(kind) => {
+                    out.push_str(kind);
+                    &members[1..]
+                }
+                None => members.as_slice(),
+            };
+            // Members go under the head symbol, or line up with the first
+            // member when there is none.
+            let indent = if head.is_some() {
+                column + INDENT
+            } else {
+                column + 1
+            };
+            for (i, (key, value)) in members.iter().enumerate() {
+                if i > 0 || head.is_some() {
+                    newline(out, indent);
+                }
+                out.push_str(&format!(":{} ", key));
+                write_pretty(out, value, indent + key.chars().count() + 2);
+            }
+            out.push(')');
+        }
+        _ => out.push_str(&flat),
+    }
+}
+
+fn newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+fn to_flat_string(value: &Value) -> String {
+    match value {
+        Value::Null => "nil".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(n) => n.to_string(),
+        Value::Float(n) if n.is_finite() => format!("{:?}", n),
+        Value::Float(_) => "nil".to_string(),
+        Value::String(s) => crate::ast::quote(s),
+        Value::Array(values) => {
+            let values: Vec<String> = values.iter().map(to_flat_string).collect();
+            format!("({})", values.join(" "))
+        }
+        Value::Object(members) => {
+            let mut parts = Vec::new();
+            let members = match kind(members) {
+                Some(kind) => {
+                    parts.push(kind.to_string());
+                    &members[1..]
+                }
+                None => members.as_slice(),
+            };
+            for (key, value) in members {
+                parts.push(format!(":{} {}", key, to_flat_string(value)));
+            }
+            format!("({})", parts.join(" "))
+        }
+    }
+}
+
+/// Returns the `kind` of an object if it comes first and can be written as
+/// the head symbol.
+fn kind(members: &[(String, Value)]) -> Option<&str> {
+    match members.first() {
+        Some((key, Value::String(kind))) if key == "kind" && is_symbol(kind) => Some(kind),
+        _ => None,
+    }
+}
+
+fn is_symbol(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(is_symbol_char)
+        && !matches!(s, "nil" | "true" | "false")
+}
+
+fn is_symbol_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_' || ch == '-'
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SexpError {
+    pub message: String,
+    /// Byte offset into the input where the error was detected.
+    pub offset: usize,
+}
+
+impl fmt::Display for SexpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for SexpError {}
+
+/// Parses a single S-expression.
+pub fn parse(input: &str) -> Result<Value, SexpError> {
+    let mut parser = SexpParser { input, position: 0 };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.position != input.len() {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+struct SexpParser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl SexpParser<'_> {
+    fn error(&self, message: &str) -> SexpError {
+        SexpError {
+            message: message.to_string(),
+            offset: self.position,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.peek().filter(|ch| ch.is_whitespace()) {
+            self.position += ch.len_utf8();
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, SexpError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => self.parse_list(),
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            Some('-' | '0'..='9') => self.parse_number(),
+            Some(':') => Err(self.error("unexpected keyword")),
+            Some(ch) if is_symbol_char(ch) => {
+                let start = self.position;
+                match self.parse_symbol() {
+                    "nil" => Ok(Value::Null),
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => Err(SexpError {
+                        message: "unexpected symbol".to_string(),
+                        offset: start,
+                    }),
+                }
+            }
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_symbol(&mut self) -> &str {
+        let start = self.position;
+        while let Some(ch) = self.peek().filter(|&ch| is_symbol_char(ch)) {
+            self.position += ch.len_utf8();
+        }
+        &self.input[start..self.position]
+    }
+
+    fn parse_list(&mut self) -> Result<Value, SexpError> {
+        self.position += 1;
+        self.skip_whitespace();
+
+        let mut members = Vec::new();
+        match self.peek() {
+            Some(':') => {}
+            Some(ch) if ch.is_ascii_alphabetic() || ch == '_' => {
+                let start = self.position;
+                let symbol = self.parse_symbol().to_string();
+                if matches!(symbol.as_str(), "nil" | "true" | "false") {
+                    // Not a head symbol but the first element of a list.
+                    self.position = start;
+                    return self.parse_elements();
+                }
+                members.push(("kind".to_string(), Value::String(symbol)));
+            }
+            _ => return self.parse_elements(),
+        }
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(')') => {
+                    self.position += 1;
+                    return Ok(Value::Object(members));
+                }
+                Some(':') => {
+                    self.position += 1;
+                    let key = self.parse_symbol().to_string();
+                    if key.is_empty() {
+                        return Err(self.error("expected a member name"));
+                    }
+                    members.push((key, self.parse_value()?));
+                }
+                None => return Err(self.error("unterminated list")),
+                Some(_) => return Err(self.error("expected a keyword or ')'")),
+            }
+        }
+    }
+
+    fn parse_elements(&mut self) -> Result<Value, SexpError> {
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(')') => {
+                    self.position += 1;
+                    return Ok(Value::Array(values));
+                }
+                None => return Err(self.error("unterminated list")),
+                Some(_) => values.push(self.parse_value()?),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, SexpError> {
+        let mut value = String::new();
+        self.position += 1;
+        loop {
+            let ch = match self.peek() {
+                Some(ch) => ch,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.position += ch.len_utf8();
+            match ch {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.position += 1;
+                            value.push(self.parse_unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    self.position += 1;
+                    value.push(escaped);
+                }
+                ch => value.push(ch),
+            }
+        }
+    }
+
+    /// Parses the `{hex}` part of a `\u{hex}` escape.
+    fn parse_unicode_escape(&mut self) -> Result<char, SexpError> {
+        let rest = &self.input[self.position..];
+        let hex = rest
+            .strip_prefix('{')
+            .and_then(|rest| rest.split_once('}'))
+            .map(|(hex, _)| hex)
+            .filter(|hex| (1..=6).contains(&hex.len()))
+            .ok_or_else(|| self.error("invalid escape"))?;
+        let ch = u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("invalid escape"))?;
+        self.position += hex.len() + 2;
+        Ok(ch)
+    }
+
+    fn parse_number(&mut self) -> Result<Value, SexpError> {
+        let start = self.position;
+        let mut is_float = false;
+        if self.peek() == Some('-') {
+            self.position += 1;
+        }
+        while let Some(ch) = self.peek() {
+            match ch {
+                '0'..='9' => {}
+                '.' | 'e' | 'E' | '+' | '-' => is_float = true,
+                _ => break,
+            }
+            self.position += 1;
+        }
+
+        let text = &self.input[start..self.position];
+        if !is_float {
+            if let Ok(n) = text.parse() {
+                return Ok(Value::Int(n));
+            }
+        }
+        text.parse().map(Value::Float).map_err(|_| SexpError {
+            message: format!("invalid number {}", text),
+            offset: start,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::json::Value;
+
+    use super::{parse, to_string};
+
+    #[test]
+    fn test_round_trip() {
+        let tests = [
+            (Value::Null, "nil"),
+            (Value::Array(vec![]), "()"),
+            (
+                Value::Array(vec![
+                    Value::Bool(true),
+                    Value::Int(-3),
+                    Value::Float(0.5),
+                    Value::from("a\"\\\n\t\u{1}é"),
+                ]),
+                r#"(true -3 0.5 "a\"\\\n\t\u{1}é")"#,
+            ),
+            (
+                Value::object([
+                    ("kind", Value::from("Identifier")),
+                    ("value", Value::from("x")),
+                    ("at", Value::Array(vec![Value::Int(1), Value::Int(2)])),
+                ]),
+                r#"(Identifier :value "x" :at (1 2))"#,
+            ),
+            (
+                Value::object([("line", Value::Int(1)), ("kind", Value::from("x"))]),
+                r#"(:line 1 :kind "x")"#,
+            ),
+            (
+                Value::object([("kind", Value::from("not a symbol"))]),
+                r#"(:kind "not a symbol")"#,
+            ),
+            (
+                Value::object([("kind", Value::from("nil")), ("x", Value::Null)]),
+                r#"(:kind "nil" :x nil)"#,
+            ),
+        ];
+
+        for (value, expected) in tests {
+            let serialized = to_string(&value);
+            if serialized != expected {
+                panic!("to_string wrong. want={}, got={}", expected, serialized);
+            }
+            if parse(&serialized).as_ref() != Ok(&value) {
+                panic!(
+                    "round trip of {} wrong. got={:?}",
+                    serialized,
+                    parse(&serialized)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pretty() {
+        let item = |name: &str| {
+            Value::object([
+                ("kind", Value::from("Item")),
+                ("name", Value::from(name)),
+                (
+                    "tags",
+                    Value::Array(vec![Value::from("first"), Value::from("second")]),
+                ),
+            ])
+        };
+        let value = Value::object([
+            ("kind", Value::from("List")),
+            ("items", Value::Array(vec![item("one"), item("two")])),
+        ]);
+
+        let expected = r#"(List
+  :items ((Item :name "one" :tags ("first" "second"))
+          (Item :name "two" :tags ("first" "second"))))"#;
+        let serialized = to_string(&value);
+        if serialized != expected {
+            panic!(
+                "to_string wrong.\nwant=\n{}\ngot=\n{}",
+                expected, serialized
+            );
+        }
+        if parse(&serialized).as_ref() != Ok(&value) {
+            panic!("round trip wrong. got={:?}", parse(&serialized));
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let tests = [
+            ("", "unexpected end of input at byte 0"),
+            ("(1 2", "unterminated list at byte 4"),
+            ("(Item :a 1 2)", "expected a keyword or ')' at byte 11"),
+            ("(: 1)", "expected a member name at byte 2"),
+            ("(1 :a)", "unexpected keyword at byte 3"),
+            ("foo", "unexpected symbol at byte 0"),
+            ("\"abc", "unterminated string at byte 4"),
+            ("\"\\u{110000}\"", "invalid escape at byte 3"),
+            ("1 2", "trailing characters at byte 2"),
+            ("-", "invalid number - at byte 0"),
+        ];
+
+        for (input, expected) in tests {
+            match parse(input) {
+                Err(err) if err.to_string() == expected => {}
+                result => panic!(
+                    "parse({:?}) wrong. want={:?}, got={:?}",
+                    input, expected, result
+                ),
+            }
+        }
+    }
+}
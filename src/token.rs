@@ -1,3 +1,6 @@
+use std::borrow::Cow;
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(PartialEq, Eq, Debug, Default, Clone, Copy, Hash)]
 pub enum TokenType {
     #[default]
@@ -5,6 +8,7 @@ pub enum TokenType {
     EOF,
     IDENT,
     INT,
+    STRING,
     ASSIGN,
     PLUS,
     MINUS,
@@ -28,9 +32,92 @@ pub enum TokenType {
     RETURN,
     EQ,
     NOTEQ,
+    /// Only produced by a lexer created with `Lexer::with_trivia`.
+    WHITESPACE,
+    /// Only produced by a lexer created with `Lexer::with_trivia`.
+    COMMENT,
+}
+
+impl TokenType {
+    /// Every token type, in declaration order.
+    pub const ALL: [TokenType; 30] = [
+        TokenType::ILLEGAL,
+        TokenType::EOF,
+        TokenType::IDENT,
+        TokenType::INT,
+        TokenType::STRING,
+        TokenType::ASSIGN,
+        TokenType::PLUS,
+        TokenType::MINUS,
+        TokenType::BANG,
+        TokenType::ASTERISK,
+        TokenType::SLASH,
+        TokenType::LT,
+        TokenType::GT,
+        TokenType::COMMA,
+        TokenType::SEMICOLON,
+        TokenType::LPAREN,
+        TokenType::RPAREN,
+        TokenType::LBRACE,
+        TokenType::RBRACE,
+        TokenType::FUNCTION,
+        TokenType::LET,
+        TokenType::TRUE,
+        TokenType::FALSE,
+        TokenType::IF,
+        TokenType::ELSE,
+        TokenType::RETURN,
+        TokenType::EQ,
+        TokenType::NOTEQ,
+        TokenType::WHITESPACE,
+        TokenType::COMMENT,
+    ];
+
+    /// Reports whether tokens of this type carry no meaning for the parser.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, TokenType::WHITESPACE | TokenType::COMMENT)
+    }
+
+    /// Returns the text every token of this type has, if it is always the
+    /// same.
+    pub fn fixed_literal(self) -> Option<&'static str> {
+        let literal = match self {
+            TokenType::EOF => "",
+            TokenType::ASSIGN => "=",
+            TokenType::PLUS => "+",
+            TokenType::MINUS => "-",
+            TokenType::BANG => "!",
+            TokenType::ASTERISK => "*",
+            TokenType::SLASH => "/",
+            TokenType::LT => "<",
+            TokenType::GT => ">",
+            TokenType::COMMA => ",",
+            TokenType::SEMICOLON => ";",
+            TokenType::LPAREN => "(",
+            TokenType::RPAREN => ")",
+            TokenType::LBRACE => "{",
+            TokenType::RBRACE => "}",
+            TokenType::FUNCTION => "fn",
+            TokenType::LET => "let",
+            TokenType::TRUE => "true",
+            TokenType::FALSE => "false",
+            TokenType::IF => "if",
+            TokenType::ELSE => "else",
+            TokenType::RETURN => "return",
+            TokenType::EQ => "==",
+            TokenType::NOTEQ => "!=",
+            TokenType::ILLEGAL
+            | TokenType::IDENT
+            | TokenType::INT
+            | TokenType::STRING
+            | TokenType::WHITESPACE
+            | TokenType::COMMENT => return None,
+        };
+        Some(literal)
+    }
 }
 
-const KEYWORDS: [(&str, TokenType); 7] = [
+pub const KEYWORDS: [(&str, TokenType); 7] = [
     ("fn", TokenType::FUNCTION),
     ("let", TokenType::LET),
     ("true", TokenType::TRUE),
@@ -50,17 +137,106 @@ pub fn lookup_ident(ident: &str) -> TokenType {
     TokenType::IDENT
 }
 
-#[derive(Clone, Debug)]
-pub struct Token {
+/// A location in the source text. `line` and `column` are 1-based and count
+/// characters, `offset` is the 0-based byte offset into the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize, offset: usize) -> Self {
+        Position {
+            line,
+            column,
+            offset,
+        }
+    }
+
+    /// Returns the position just after `text`, which starts at this one.
+    pub fn advance(self, text: &str) -> Position {
+        text.chars().fold(self, |position, ch| {
+            if ch == '\n' {
+                Position::new(position.line + 1, 1, position.offset + 1)
+            } else {
+                Position::new(
+                    position.line,
+                    position.column + 1,
+                    position.offset + ch.len_utf8(),
+                )
+            }
+        })
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new(1, 1, 0)
+    }
+}
+
+/// A half-open range `[start, end)` of the source text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span { start, end }
+    }
+
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: if other.start.offset < self.start.offset {
+                other.start
+            } else {
+                self.start
+            },
+            end: if other.end.offset > self.end.offset {
+                other.end
+            } else {
+                self.end
+            },
+        }
+    }
+}
+
+/// A token of the input. `literal` borrows the token's text from the input
+/// it was read from, except for string literals containing escape sequences
+/// and identifiers changed by normalization, which own their decoded text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Token<'a> {
     pub token_type: TokenType,
-    pub literal: String,
+    pub literal: Cow<'a, str>,
+    pub span: Span,
 }
 
-impl Token {
+impl Token<'_> {
     pub fn new() -> Self {
         Token {
             token_type: TokenType::default(),
-            literal: String::default(),
+            literal: Cow::Borrowed(""),
+            span: Span::default(),
+        }
+    }
+
+    /// Copies the literal if needed so the token no longer borrows the input.
+    /// Keywords and punctuation refer to a static string instead, so only
+    /// identifiers and literals allocate.
+    pub fn into_owned(self) -> Token<'static> {
+        let literal = match self.token_type.fixed_literal() {
+            Some(fixed) if fixed == self.literal => Cow::Borrowed(fixed),
+            _ => Cow::Owned(self.literal.into_owned()),
+        };
+        Token {
+            token_type: self.token_type,
+            literal,
+            span: self.span,
         }
     }
 }
@@ -0,0 +1,345 @@
+//! Unicode support for identifiers: the UAX #31 `XID_Start`/`XID_Continue`
+//! properties, NFC normalization and the UTS #39 mixed-script check. Also
+//! the display width of characters, which diagnostics use to line up carets.
+
+mod tables;
+
+use std::{borrow::Cow, cmp::Ordering};
+
+use tables::{
+    CANONICAL_COMBINING_CLASS, CANONICAL_COMPOSITION, CANONICAL_DECOMPOSITION, EAST_ASIAN_WIDE,
+    SCRIPTS, XID_CONTINUE, XID_START,
+};
+
+// Constants of the Hangul syllable algorithm (Unicode §3.12).
+const S_BASE: u32 = 0xAC00;
+const L_BASE: u32 = 0x1100;
+const V_BASE: u32 = 0x1161;
+const T_BASE: u32 = 0x11A7;
+const L_COUNT: u32 = 19;
+const V_COUNT: u32 = 21;
+const T_COUNT: u32 = 28;
+const N_COUNT: u32 = V_COUNT * T_COUNT;
+const S_COUNT: u32 = L_COUNT * N_COUNT;
+
+/// Script combinations that UTS #39 accepts at the "Highly Restrictive"
+/// level, so that e.g. Japanese identifiers may mix kanji and kana.
+const ALLOWED_SCRIPT_SETS: [&[&str]; 3] = [
+    &["Latin", "Han", "Hiragana", "Katakana"],
+    &["Latin", "Han", "Bopomofo"],
+    &["Latin", "Han", "Hangul"],
+];
+
+fn in_ranges(ranges: &[(char, char)], ch: char) -> bool {
+    ranges
+        .binary_search_by(|&(lo, hi)| {
+            if hi < ch {
+                Ordering::Less
+            } else if lo > ch {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+pub fn is_xid_start(ch: char) -> bool {
+    if ch.is_ascii() {
+        return ch.is_ascii_alphabetic();
+    }
+    in_ranges(XID_START, ch)
+}
+
+pub fn is_xid_continue(ch: char) -> bool {
+    if ch.is_ascii() {
+        return ch.is_ascii_alphanumeric() || ch == '_';
+    }
+    in_ranges(XID_CONTINUE, ch)
+}
+
+/// Returns the script of `ch`, or `None` for characters that are shared
+/// between scripts (`Common` and `Inherited`) or are not identifier
+/// characters at all.
+pub fn script(ch: char) -> Option<&'static str> {
+    SCRIPTS
+        .binary_search_by(|&(lo, hi, _)| {
+            if hi < ch {
+                Ordering::Less
+            } else if lo > ch {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .ok()
+        .map(|i| SCRIPTS[i].2)
+}
+
+/// Returns the scripts used by `ident` if they form a combination that could
+/// be used to spoof another identifier, e.g. Latin mixed with Cyrillic.
+pub fn confusable_scripts(ident: &str) -> Option<Vec<&'static str>> {
+    if ident.is_ascii() {
+        return None;
+    }
+
+    let mut scripts = Vec::new();
+    for script in ident.chars().filter_map(script) {
+        if !scripts.contains(&script) {
+            scripts.push(script);
+        }
+    }
+
+    if scripts.len() <= 1
+        || ALLOWED_SCRIPT_SETS
+            .iter()
+            .any(|set| scripts.iter().all(|script| set.contains(script)))
+    {
+        return None;
+    }
+    Some(scripts)
+}
+
+/// Returns the number of terminal columns `ch` takes up: 2 for wide and
+/// fullwidth East Asian characters, 0 for combining marks, which are drawn
+/// over the character before them, and 1 for everything else.
+pub fn display_width(ch: char) -> usize {
+    if ch.is_ascii() {
+        1
+    } else if combining_class(ch) != 0 {
+        0
+    } else if in_ranges(EAST_ASIAN_WIDE, ch) {
+        2
+    } else {
+        1
+    }
+}
+
+fn combining_class(ch: char) -> u8 {
+    CANONICAL_COMBINING_CLASS
+        .binary_search_by(|&(lo, hi, _)| {
+            if hi < ch {
+                Ordering::Less
+            } else if lo > ch {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .map_or(0, |i| CANONICAL_COMBINING_CLASS[i].2)
+}
+
+fn decompose(ch: char, out: &mut Vec<char>) {
+    let s = ch as u32;
+    if (S_BASE..S_BASE + S_COUNT).contains(&s) {
+        let s_index = s - S_BASE;
+        let l = L_BASE + s_index / N_COUNT;
+        let v = V_BASE + (s_index % N_COUNT) / T_COUNT;
+        let t = T_BASE + s_index % T_COUNT;
+        out.extend([l, v].iter().filter_map(|&c| char::from_u32(c)));
+        if t != T_BASE {
+            out.extend(char::from_u32(t));
+        }
+        return;
+    }
+
+    match CANONICAL_DECOMPOSITION.binary_search_by(|&(c, _)| c.cmp(&ch)) {
+        Ok(i) => out.extend_from_slice(CANONICAL_DECOMPOSITION[i].1),
+        Err(_) => out.push(ch),
+    }
+}
+
+fn compose(a: char, b: char) -> Option<char> {
+    let (a, b) = (a as u32, b as u32);
+    if (L_BASE..L_BASE + L_COUNT).contains(&a) && (V_BASE..V_BASE + V_COUNT).contains(&b) {
+        let lv = S_BASE + ((a - L_BASE) * V_COUNT + (b - V_BASE)) * T_COUNT;
+        return char::from_u32(lv);
+    }
+    if (S_BASE..S_BASE + S_COUNT).contains(&a)
+        && (a - S_BASE).is_multiple_of(T_COUNT)
+        && (T_BASE + 1..T_BASE + T_COUNT).contains(&b)
+    {
+        return char::from_u32(a + b - T_BASE);
+    }
+
+    let (a, b) = (char::from_u32(a)?, char::from_u32(b)?);
+    CANONICAL_COMPOSITION
+        .binary_search_by(|&(pair, _)| pair.cmp(&(a, b)))
+        .ok()
+        .map(|i| CANONICAL_COMPOSITION[i].1)
+}
+
+/// Converts `s` to Normalization Form C, borrowing `s` when it is already
+/// normalized.
+pub fn to_nfc(s: &str) -> Cow<'_, str> {
+    if s.is_ascii() {
+        return Cow::Borrowed(s);
+    }
+
+    let mut chars = Vec::with_capacity(s.len());
+    for ch in s.chars() {
+        decompose(ch, &mut chars);
+    }
+
+    // Canonical ordering: sort each run of non-starters by combining class.
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        while i < chars.len() && combining_class(chars[i]) != 0 {
+            i += 1;
+        }
+        chars[start..i].sort_by_key(|&ch| combining_class(ch));
+        i += 1;
+    }
+
+    let mut result: Vec<char> = Vec::with_capacity(chars.len());
+    let mut starter = None;
+    let mut last_class = None;
+    for ch in chars {
+        let class = combining_class(ch);
+        if let Some(starter) = starter {
+            let blocked = matches!(last_class, Some(last) if last == 0 || last >= class);
+            if !blocked {
+                if let Some(composed) = compose(result[starter], ch) {
+                    result[starter] = composed;
+                    continue;
+                }
+            }
+        }
+
+        if class == 0 {
+            starter = Some(result.len());
+            last_class = None;
+        } else {
+            last_class = Some(class);
+        }
+        result.push(ch);
+    }
+
+    let result: String = result.into_iter().collect();
+    if result == s {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{confusable_scripts, display_width, is_xid_continue, is_xid_start, script, to_nfc};
+
+    #[test]
+    fn test_xid() {
+        let tests = [
+            ('a', true, true),
+            ('_', false, true),
+            ('7', false, true),
+            ('é', true, true),
+            ('変', true, true),
+            ('ー', true, true),
+            ('\u{301}', false, true),
+            ('+', false, false),
+            ('🐒', false, false),
+        ];
+
+        for (ch, start, cont) in tests {
+            if is_xid_start(ch) != start {
+                panic!("is_xid_start({:?}) wrong. expected={}", ch, start);
+            }
+            if is_xid_continue(ch) != cont {
+                panic!("is_xid_continue({:?}) wrong. expected={}", ch, cont);
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_nfc() {
+        let tests = [
+            ("abc", "abc"),
+            ("cafe\u{301}", "café"),
+            ("\u{212B}", "\u{C5}"),
+            ("a\u{323}\u{302}", "\u{1EAD}"),
+            ("a\u{302}\u{323}", "\u{1EAD}"),
+            ("\u{1100}\u{1161}\u{11A8}", "각"),
+            ("か\u{3099}", "が"),
+            ("e\u{301}\u{301}", "é\u{301}"),
+        ];
+
+        for (input, expected) in tests {
+            let actual = to_nfc(input);
+            if actual != expected {
+                panic!(
+                    "to_nfc({:?}) wrong. expected={:?}, got={:?}",
+                    input, expected, actual
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_script() {
+        let tests = [
+            ('a', Some("Latin")),
+            ('а', Some("Cyrillic")),
+            ('α', Some("Greek")),
+            ('漢', Some("Han")),
+            ('ひ', Some("Hiragana")),
+            ('1', None),
+            ('_', None),
+        ];
+
+        for (ch, expected) in tests {
+            if script(ch) != expected {
+                panic!(
+                    "script({:?}) wrong. expected={:?}, got={:?}",
+                    ch,
+                    expected,
+                    script(ch)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_confusable_scripts() {
+        let tests = [
+            ("counter", None),
+            ("счётчик", None),
+            ("変数の値", None),
+            ("userの名前", None),
+            ("x_1", None),
+            ("pаypal", Some(vec!["Latin", "Cyrillic"])),
+            ("αlpha", Some(vec!["Greek", "Latin"])),
+        ];
+
+        for (input, expected) in tests {
+            let actual = confusable_scripts(input);
+            if actual != expected {
+                panic!(
+                    "confusable_scripts({:?}) wrong. expected={:?}, got={:?}",
+                    input, expected, actual
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_width() {
+        let tests = [
+            ('a', 1),
+            ('é', 1),
+            ('\u{301}', 0),
+            ('変', 2),
+            ('ｱ', 1),
+            ('Ａ', 2),
+            ('한', 2),
+            ('🐒', 2),
+        ];
+
+        for (ch, expected) in tests {
+            if display_width(ch) != expected {
+                panic!("display_width({:?}) wrong. expected={}", ch, expected);
+            }
+        }
+    }
+}
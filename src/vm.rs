@@ -0,0 +1,687 @@
+use std::{fmt, rc::Rc};
+
+use crate::{
+    code::{read_u16, read_u8, Instructions, Opcode},
+    compiler::Bytecode,
+    object::{Closure, CompiledFunction, Object},
+};
+
+const STACK_SIZE: usize = 2048;
+const GLOBALS_SIZE: usize = 65536;
+const MAX_FRAMES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError {
+    StackOverflow,
+    TypeMismatch {
+        left: &'static str,
+        operator: &'static str,
+        right: &'static str,
+    },
+    UnknownInfixOperator {
+        left: &'static str,
+        operator: &'static str,
+        right: &'static str,
+    },
+    UnknownPrefixOperator {
+        operator: &'static str,
+        right: &'static str,
+    },
+    DivisionByZero,
+    IntegerOverflow,
+    NotAFunction(&'static str),
+    WrongNumberOfArguments {
+        want: usize,
+        got: usize,
+    },
+    InvalidOpcode(u8),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StackOverflow => write!(f, "stack overflow"),
+            Self::TypeMismatch {
+                left,
+                operator,
+                right,
+            } => write!(f, "type mismatch: {} {} {}", left, operator, right),
+            Self::UnknownInfixOperator {
+                left,
+                operator,
+                right,
+            } => write!(f, "unknown operator: {} {} {}", left, operator, right),
+            Self::UnknownPrefixOperator { operator, right } => {
+                write!(f, "unknown operator: {}{}", operator, right)
+            }
+            Self::DivisionByZero => write!(f, "division by zero"),
+            Self::IntegerOverflow => write!(f, "integer overflow"),
+            Self::NotAFunction(object_type) => write!(f, "not a function: {}", object_type),
+            Self::WrongNumberOfArguments { want, got } => {
+                write!(f, "wrong number of arguments: want={}, got={}", want, got)
+            }
+            Self::InvalidOpcode(byte) => write!(f, "opcode {} undefined", byte),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+/// The call frame of a function invocation. `base_pointer` is the stack
+/// slot of the first local binding.
+#[derive(Debug, Clone)]
+struct Frame {
+    cl: Rc<Closure>,
+    ip: usize,
+    base_pointer: usize,
+}
+
+impl Frame {
+    fn new(cl: Rc<Closure>, base_pointer: usize) -> Self {
+        Frame {
+            cl,
+            ip: 0,
+            base_pointer,
+        }
+    }
+
+    fn instructions(&self) -> &Instructions {
+        &self.cl.func.instructions
+    }
+}
+
+#[derive(Debug)]
+pub struct VM {
+    constants: Vec<Object>,
+
+    stack: Vec<Object>,
+    // Always points to the next free slot. Top of stack is stack[sp-1].
+    sp: usize,
+
+    globals: Vec<Object>,
+
+    frames: Vec<Frame>,
+}
+
+impl VM {
+    pub fn new(bytecode: Bytecode) -> Self {
+        let main_fn = Rc::new(CompiledFunction {
+            instructions: bytecode.instructions,
+            num_locals: 0,
+            num_parameters: 0,
+        });
+        let main_closure = Rc::new(Closure {
+            func: main_fn,
+            free: Vec::new(),
+        });
+
+        let mut frames = Vec::with_capacity(MAX_FRAMES);
+        frames.push(Frame::new(main_closure, 0));
+
+        VM {
+            constants: bytecode.constants,
+            stack: vec![Object::Null; STACK_SIZE],
+            sp: 0,
+            globals: vec![Object::Null; GLOBALS_SIZE],
+            frames,
+        }
+    }
+
+    /// The value most recently popped off the stack, i.e. the value of the
+    /// last expression statement executed.
+    pub fn last_popped_stack_elem(&self) -> &Object {
+        &self.stack[self.sp]
+    }
+
+    pub fn run(&mut self) -> Result<(), VmError> {
+        loop {
+            let frame = self.current_frame();
+            let ip = frame.ip;
+            if ip >= frame.instructions().len() {
+                return Ok(());
+            }
+
+            let byte = frame.instructions()[ip];
+            let op = Opcode::from_byte(byte).ok_or(VmError::InvalidOpcode(byte))?;
+            self.current_frame_mut().ip += 1;
+
+            match op {
+                Opcode::Constant => {
+                    let const_index = self.read_u16_operand() as usize;
+                    self.push(self.constants[const_index].clone())?;
+                }
+                Opcode::Pop => {
+                    self.pop();
+                }
+                Opcode::Add | Opcode::Sub | Opcode::Mul | Opcode::Div => {
+                    self.execute_binary_operation(op)?;
+                }
+                Opcode::True => self.push(Object::Boolean(true))?,
+                Opcode::False => self.push(Object::Boolean(false))?,
+                Opcode::Equal | Opcode::NotEqual | Opcode::GreaterThan | Opcode::LessThan => {
+                    self.execute_comparison(op)?;
+                }
+                Opcode::Bang => {
+                    let operand = self.pop();
+                    self.push(Object::Boolean(!is_truthy(&operand)))?;
+                }
+                Opcode::Minus => {
+                    let operand = self.pop();
+                    match operand {
+                        Object::Integer(value) => {
+                            let value = value.checked_neg().ok_or(VmError::IntegerOverflow)?;
+                            self.push(Object::Integer(value))?;
+                        }
+                        operand => {
+                            return Err(VmError::UnknownPrefixOperator {
+                                operator: "-",
+                                right: operand.object_type(),
+                            })
+                        }
+                    }
+                }
+                Opcode::Jump => {
+                    let pos = self.read_u16_operand() as usize;
+                    self.current_frame_mut().ip = pos;
+                }
+                Opcode::JumpNotTruthy => {
+                    let pos = self.read_u16_operand() as usize;
+
+                    let condition = self.pop();
+                    if !is_truthy(&condition) {
+                        self.current_frame_mut().ip = pos;
+                    }
+                }
+                Opcode::Null => self.push(Object::Null)?,
+                Opcode::SetGlobal => {
+                    let global_index = self.read_u16_operand() as usize;
+                    self.globals[global_index] = self.pop();
+                }
+                Opcode::GetGlobal => {
+                    let global_index = self.read_u16_operand() as usize;
+                    self.push(self.globals[global_index].clone())?;
+                }
+                Opcode::SetLocal => {
+                    let local_index = self.read_u8_operand() as usize;
+                    let base_pointer = self.current_frame().base_pointer;
+                    self.stack[base_pointer + local_index] = self.pop();
+                }
+                Opcode::GetLocal => {
+                    let local_index = self.read_u8_operand() as usize;
+                    let base_pointer = self.current_frame().base_pointer;
+                    self.push(self.stack[base_pointer + local_index].clone())?;
+                }
+                Opcode::GetFree => {
+                    let free_index = self.read_u8_operand() as usize;
+                    let obj = self.current_frame().cl.free[free_index].clone();
+                    self.push(obj)?;
+                }
+                Opcode::CurrentClosure => {
+                    let cl = self.current_frame().cl.clone();
+                    self.push(Object::Closure(cl))?;
+                }
+                Opcode::Closure => {
+                    let const_index = self.read_u16_operand() as usize;
+                    let num_free = self.read_u8_operand() as usize;
+                    self.push_closure(const_index, num_free)?;
+                }
+                Opcode::Call => {
+                    let num_args = self.read_u8_operand() as usize;
+                    self.call_function(num_args)?;
+                }
+                Opcode::ReturnValue => {
+                    let return_value = self.pop();
+                    // A return outside any function ends the program, leaving
+                    // the value where `last_popped_stack_elem` finds it.
+                    if self.frames.len() == 1 {
+                        return Ok(());
+                    }
+
+                    let frame = self.pop_frame();
+                    self.sp = frame.base_pointer - 1;
+
+                    self.push(return_value)?;
+                }
+                Opcode::Return => {
+                    if self.frames.len() == 1 {
+                        self.stack[self.sp] = Object::Null;
+                        return Ok(());
+                    }
+
+                    let frame = self.pop_frame();
+                    self.sp = frame.base_pointer - 1;
+
+                    self.push(Object::Null)?;
+                }
+            }
+        }
+    }
+
+    fn current_frame(&self) -> &Frame {
+        self.frames.last().expect("vm has no frame")
+    }
+
+    fn current_frame_mut(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("vm has no frame")
+    }
+
+    fn push_frame(&mut self, frame: Frame) -> Result<(), VmError> {
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(VmError::StackOverflow);
+        }
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    fn pop_frame(&mut self) -> Frame {
+        self.frames.pop().expect("vm has no frame")
+    }
+
+    fn read_u16_operand(&mut self) -> u16 {
+        let frame = self.current_frame_mut();
+        let operand = read_u16(&frame.cl.func.instructions[frame.ip..]);
+        frame.ip += 2;
+        operand
+    }
+
+    fn read_u8_operand(&mut self) -> u8 {
+        let frame = self.current_frame_mut();
+        let operand = read_u8(&frame.cl.func.instructions[frame.ip..]);
+        frame.ip += 1;
+        operand
+    }
+
+    fn push(&mut self, obj: Object) -> Result<(), VmError> {
+        if self.sp >= STACK_SIZE {
+            return Err(VmError::StackOverflow);
+        }
+
+        self.stack[self.sp] = obj;
+        self.sp += 1;
+
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Object {
+        let obj = self.stack[self.sp - 1].clone();
+        self.sp -= 1;
+        obj
+    }
+
+    fn push_closure(&mut self, const_index: usize, num_free: usize) -> Result<(), VmError> {
+        let func = match &self.constants[const_index] {
+            Object::CompiledFunction(func) => func.clone(),
+            obj => return Err(VmError::NotAFunction(obj.object_type())),
+        };
+
+        let free = self.stack[self.sp - num_free..self.sp].to_vec();
+        self.sp -= num_free;
+
+        self.push(Object::Closure(Rc::new(Closure { func, free })))
+    }
+
+    fn call_function(&mut self, num_args: usize) -> Result<(), VmError> {
+        let cl = match &self.stack[self.sp - 1 - num_args] {
+            Object::Closure(cl) => cl.clone(),
+            obj => return Err(VmError::NotAFunction(obj.object_type())),
+        };
+
+        if num_args != cl.func.num_parameters {
+            return Err(VmError::WrongNumberOfArguments {
+                want: cl.func.num_parameters,
+                got: num_args,
+            });
+        }
+
+        let base_pointer = self.sp - num_args;
+        let num_locals = cl.func.num_locals;
+        if base_pointer + num_locals > STACK_SIZE {
+            return Err(VmError::StackOverflow);
+        }
+
+        self.push_frame(Frame::new(cl, base_pointer))?;
+        self.sp = base_pointer + num_locals;
+
+        Ok(())
+    }
+
+    fn execute_binary_operation(&mut self, op: Opcode) -> Result<(), VmError> {
+        let right = self.pop();
+        let left = self.pop();
+
+        let result = match (left, right) {
+            (Object::Integer(left), Object::Integer(right)) => {
+                let value = match op {
+                    Opcode::Add => left.checked_add(right),
+                    Opcode::Sub => left.checked_sub(right),
+                    Opcode::Mul => left.checked_mul(right),
+                    Opcode::Div if right == 0 => return Err(VmError::DivisionByZero),
+                    Opcode::Div => left.checked_div(right),
+                    _ => unreachable!("{:?} is not a binary operation", op),
+                };
+                Object::Integer(value.ok_or(VmError::IntegerOverflow)?)
+            }
+            (Object::String(left), Object::String(right)) if op == Opcode::Add => {
+                Object::String(left + &right)
+            }
+            (left, right) => return Err(operator_error(op, &left, &right)),
+        };
+
+        self.push(result)
+    }
+
+    fn execute_comparison(&mut self, op: Opcode) -> Result<(), VmError> {
+        let right = self.pop();
+        let left = self.pop();
+
+        let result = match (&left, &right, op) {
+            (Object::Integer(left), Object::Integer(right), _) => match op {
+                Opcode::Equal => left == right,
+                Opcode::NotEqual => left != right,
+                Opcode::LessThan => left < right,
+                _ => left > right,
+            },
+            (Object::Boolean(left), Object::Boolean(right), Opcode::Equal) => left == right,
+            (Object::Boolean(left), Object::Boolean(right), Opcode::NotEqual) => left != right,
+            (Object::String(left), Object::String(right), Opcode::Equal) => left == right,
+            (Object::String(left), Object::String(right), Opcode::NotEqual) => left != right,
+            _ => return Err(operator_error(op, &left, &right)),
+        };
+
+        self.push(Object::Boolean(result))
+    }
+}
+
+fn operator_error(op: Opcode, left: &Object, right: &Object) -> VmError {
+    let operator = match op {
+        Opcode::Add => "+",
+        Opcode::Sub => "-",
+        Opcode::Mul => "*",
+        Opcode::Div => "/",
+        Opcode::Equal => "==",
+        Opcode::NotEqual => "!=",
+        Opcode::LessThan => "<",
+        _ => ">",
+    };
+
+    if left.object_type() != right.object_type() {
+        VmError::TypeMismatch {
+            left: left.object_type(),
+            operator,
+            right: right.object_type(),
+        }
+    } else {
+        VmError::UnknownInfixOperator {
+            left: left.object_type(),
+            operator,
+            right: right.object_type(),
+        }
+    }
+}
+
+fn is_truthy(obj: &Object) -> bool {
+    match obj {
+        Object::Boolean(value) => *value,
+        Object::Null => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{compiler::Compiler, lexer::Lexer, object::Object, parser::Parser};
+
+    use super::{VmError, VM};
+
+    enum Expected {
+        Int(i64),
+        Bool(bool),
+        Str(&'static str),
+        Null,
+    }
+
+    fn run(input: &str) -> Result<VM, VmError> {
+        let l = Lexer::new(input);
+        let mut p = Parser::new(l);
+        let program = match p.parse_program() {
+            Ok(program) => program,
+            Err(errors) => panic!("parser has errors for {:?}: {:?}", input, errors),
+        };
+
+        let mut compiler = Compiler::new();
+        if let Err(err) = compiler.compile(&program) {
+            panic!("compiler error for {:?}: {}", input, err);
+        }
+
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run()?;
+        Ok(vm)
+    }
+
+    fn run_vm_tests(tests: &[(&str, Expected)]) {
+        for (input, expected) in tests {
+            let vm = match run(input) {
+                Ok(vm) => vm,
+                Err(err) => panic!("vm error for {:?}: {}", input, err),
+            };
+            let stack_elem = vm.last_popped_stack_elem();
+
+            let ok = match (expected, stack_elem) {
+                (Expected::Int(want), Object::Integer(got)) => want == got,
+                (Expected::Bool(want), Object::Boolean(got)) => want == got,
+                (Expected::Str(want), Object::String(got)) => want == got,
+                (Expected::Null, Object::Null) => true,
+                _ => false,
+            };
+            if !ok {
+                panic!("wrong result for {:?}. got={:?}", input, stack_elem);
+            }
+        }
+    }
+
+    fn run_vm_error_tests(tests: &[(&str, &str)]) {
+        for (input, expected) in tests {
+            match run(input) {
+                Ok(vm) => panic!(
+                    "expected VM error for {:?}. got={:?}",
+                    input,
+                    vm.last_popped_stack_elem()
+                ),
+                Err(err) if err.to_string() == *expected => {}
+                Err(err) => panic!(
+                    "wrong VM error for {:?}. want={:?}, got={:?}",
+                    input,
+                    expected,
+                    err.to_string()
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let tests = [
+            ("1", Expected::Int(1)),
+            ("1 + 2", Expected::Int(3)),
+            ("1 - 2", Expected::Int(-1)),
+            ("4 / 2", Expected::Int(2)),
+            ("50 / 2 * 2 + 10 - 5", Expected::Int(55)),
+            ("5 * (2 + 10)", Expected::Int(60)),
+            ("-5", Expected::Int(-5)),
+            ("-50 + 100 + -50", Expected::Int(0)),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", Expected::Int(50)),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_boolean_expressions() {
+        let tests = [
+            ("true", Expected::Bool(true)),
+            ("1 < 2", Expected::Bool(true)),
+            ("1 > 2", Expected::Bool(false)),
+            ("1 == 1", Expected::Bool(true)),
+            ("1 != 1", Expected::Bool(false)),
+            ("true == false", Expected::Bool(false)),
+            ("(1 < 2) == true", Expected::Bool(true)),
+            ("!true", Expected::Bool(false)),
+            ("!!5", Expected::Bool(true)),
+            ("!(if (false) { 5; })", Expected::Bool(true)),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let tests = [
+            ("if (true) { 10 }", Expected::Int(10)),
+            ("if (true) { 10 } else { 20 }", Expected::Int(10)),
+            ("if (false) { 10 } else { 20 } ", Expected::Int(20)),
+            ("if (1 < 2) { 10 }", Expected::Int(10)),
+            ("if (1 > 2) { 10 }", Expected::Null),
+            (
+                "if ((if (false) { 10 })) { 10 } else { 20 }",
+                Expected::Int(20),
+            ),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let tests = [
+            ("let one = 1; one", Expected::Int(1)),
+            ("let one = 1; let two = 2; one + two", Expected::Int(3)),
+            (
+                "let one = 1; let two = one + one; one + two",
+                Expected::Int(3),
+            ),
+            ("let a = 1; let a = a + 1; a", Expected::Int(2)),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_string_expressions() {
+        let tests = [
+            ("\"monkey\"", Expected::Str("monkey")),
+            (
+                "\"mon\" + \"key\" + \"banana\"",
+                Expected::Str("monkeybanana"),
+            ),
+            ("\"a\" == \"a\"", Expected::Bool(true)),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_calling_functions() {
+        let tests = [
+            (
+                "let fivePlusTen = fn() { 5 + 10; }; fivePlusTen();",
+                Expected::Int(15),
+            ),
+            (
+                "let earlyExit = fn() { return 99; 100; }; earlyExit();",
+                Expected::Int(99),
+            ),
+            ("let noReturn = fn() { }; noReturn();", Expected::Null),
+            (
+                "let returnsOne = fn() { 1; }; let returnsOneReturner = fn() { returnsOne; }; returnsOneReturner()();",
+                Expected::Int(1),
+            ),
+            (
+                "let sum = fn(a, b) { let c = a + b; c; }; sum(1, 2) + sum(3, 4);",
+                Expected::Int(10),
+            ),
+            (
+                "let globalNum = 10; let sum = fn(a, b) { let c = a + b; c + globalNum; }; let outer = fn() { sum(1, 2) + sum(3, 4) + globalNum; }; outer() + globalNum;",
+                Expected::Int(50),
+            ),
+            (
+                "let shadow = fn(a) { let a = a + 1; let b = 10; let b = b * a; b }; shadow(1);",
+                Expected::Int(20),
+            ),
+            (
+                "let a = 1; let inner = fn() { let a = a + 1; a }; inner() + a;",
+                Expected::Int(3),
+            ),
+            ("return 10;", Expected::Int(10)),
+            ("if (true) { return 5; }; 3", Expected::Int(5)),
+            ("1; return 2; 3", Expected::Int(2)),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_closures() {
+        let tests = [
+            (
+                "let newClosure = fn(a) { fn() { a; }; }; let closure = newClosure(99); closure();",
+                Expected::Int(99),
+            ),
+            (
+                "let newAdder = fn(a, b) { fn(c) { a + b + c }; }; let adder = newAdder(1, 2); adder(8);",
+                Expected::Int(11),
+            ),
+            (
+                "let newAdderOuter = fn(a, b) { let c = a + b; fn(d) { let e = d + c; fn(f) { e + f; }; }; }; let newAdderInner = newAdderOuter(1, 2); let adder = newAdderInner(3); adder(8);",
+                Expected::Int(14),
+            ),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_recursive_functions() {
+        let tests = [
+            (
+                "let countDown = fn(x) { if (x == 0) { return 0; } else { countDown(x - 1); } }; countDown(1);",
+                Expected::Int(0),
+            ),
+            (
+                "let wrapper = fn() { let countDown = fn(x) { if (x == 0) { return 0; } else { countDown(x - 1); } }; countDown(1); }; wrapper();",
+                Expected::Int(0),
+            ),
+            (
+                "let fibonacci = fn(x) { if (x == 0) { return 0; } else { if (x == 1) { return 1; } else { fibonacci(x - 1) + fibonacci(x - 2); } } }; fibonacci(15);",
+                Expected::Int(610),
+            ),
+        ];
+
+        run_vm_tests(&tests);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let tests = [
+            (
+                "fn() { 1; }(1);",
+                "wrong number of arguments: want=0, got=1",
+            ),
+            (
+                "fn(a) { a; }();",
+                "wrong number of arguments: want=1, got=0",
+            ),
+            ("1 + true", "type mismatch: INTEGER + BOOLEAN"),
+            ("1 < true", "type mismatch: INTEGER < BOOLEAN"),
+            ("\"a\" < \"b\"", "unknown operator: STRING < STRING"),
+            ("true + false", "unknown operator: BOOLEAN + BOOLEAN"),
+            ("\"a\" - \"b\"", "unknown operator: STRING - STRING"),
+            ("-true", "unknown operator: -BOOLEAN"),
+            ("10 / 0", "division by zero"),
+            ("9223372036854775807 + 1", "integer overflow"),
+            ("let x = 1; x();", "not a function: INTEGER"),
+            ("let f = fn() { f(); }; f();", "stack overflow"),
+        ];
+
+        run_vm_error_tests(&tests);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use monkey_rust::Lexer;
+
+/// Counts the allocations made by the current thread, so tests running in
+/// parallel do not disturb each other.
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Lexes `input` to the end and returns the number of tokens and the number
+/// of allocations made on the way.
+fn lex(input: &str) -> (usize, usize) {
+    let before = ALLOCATIONS.with(Cell::get);
+    let tokens = Lexer::new(input).count();
+    (tokens, ALLOCATIONS.with(Cell::get) - before)
+}
+
+#[test]
+fn test_lexing_does_not_allocate() {
+    let statement = "let add = fn(x, y) { if (x != y) { return \"sum\" + x * 10; } }; // add\n";
+    let input = statement.repeat(1000);
+
+    let (tokens, allocations) = lex(&input);
+    if tokens < 20_000 {
+        panic!("too few tokens lexed. got={}", tokens);
+    }
+    if allocations != 0 {
+        panic!(
+            "lexing {} tokens allocated {} times. expected none",
+            tokens, allocations
+        );
+    }
+}
+
+#[test]
+fn test_owned_tokens_allocate() {
+    // Escaped strings own their decoded value.
+    let tests = [("\"a\\n\" ".repeat(100), 100, 300)];
+
+    for (input, min, max) in tests {
+        let (_, allocations) = lex(&input);
+        if allocations < min || allocations > max {
+            panic!(
+                "allocations for {:?}... wrong. expected between {} and {}, got={}",
+                &input[..10],
+                min,
+                max,
+                allocations
+            );
+        }
+    }
+}
+
+#[test]
+fn test_owning_fixed_tokens_does_not_allocate() {
+    // The parser owns every token it keeps in the AST; keywords and
+    // punctuation need no copy of their text.
+    let statement = "if (true) { return !false; } else { return -(true == false); };\n";
+    let input = statement.repeat(1000);
+
+    let before = ALLOCATIONS.with(Cell::get);
+    let mut tokens = 0;
+    for tok in Lexer::new(&input) {
+        drop(tok.into_owned());
+        tokens += 1;
+    }
+    let allocations = ALLOCATIONS.with(Cell::get) - before;
+    if allocations != 0 {
+        panic!(
+            "owning {} tokens allocated {} times. expected none",
+            tokens, allocations
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use monkey_rust::{
+    compiler::Compiler,
+    evaluator::{eval, with_eval_stack},
+    object::Environment,
+    parse,
+    vm::VM,
+};
+
+/// Runs `input` with the tree-walking evaluator and returns what it prints.
+fn evaluate(input: &str) -> String {
+    let program = parse(input).unwrap();
+    match eval(&program, &Environment::new()) {
+        Some(evaluated) => evaluated.inspect(),
+        None => "null".to_string(),
+    }
+}
+
+/// Compiles and runs `input` on the VM and returns what it prints.
+fn execute(input: &str) -> String {
+    let program = parse(input).unwrap();
+    let mut compiler = Compiler::new();
+    if let Err(err) = compiler.compile(&program) {
+        return format!("ERROR: {}", err);
+    }
+    let mut vm = VM::new(compiler.bytecode());
+    match vm.run() {
+        Ok(()) => vm.last_popped_stack_elem().inspect(),
+        Err(err) => format!("ERROR: {}", err),
+    }
+}
+
+#[test]
+fn test_backends_agree() {
+    let tests = [
+        "1 + 2 * 3 - 4 / 2",
+        "-(5 + 5) * 2 == -20",
+        "!(1 < 2) != (3 > 4)",
+        "\"mon\" + \"key\"",
+        "if (1 > 2) { 10 }",
+        "if (false) { 10 } else { 20 }",
+        "let a = 1; let a = a + 1; a",
+        "let a = 1; let g = fn() { a }; let a = 2; g()",
+        "let a = 1; let set = fn() { let a = 10; a }; set() + a",
+        "let add = fn(x) { fn(y) { x + y } }; add(2)(3)",
+        "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+        "let f = fn() { return 1; 2 }; f()",
+        "return 10; 20",
+        "1 + true",
+        "1 < true",
+        "\"a\" - \"b\"",
+        "-true",
+        "let x = 1; x()",
+        "fn(a) { a }()",
+    ];
+
+    with_eval_stack(|| {
+        for input in tests {
+            let evaluated = evaluate(input);
+            let executed = execute(input);
+            if evaluated != executed {
+                panic!(
+                    "backends disagree on {:?}. evaluator={:?}, vm={:?}",
+                    input, evaluated, executed
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,190 @@
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+fn monkey(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_monkey"))
+        .args(args)
+        .output()
+        .expect("failed to run monkey")
+}
+
+fn write_script(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("monkey-cli-{}-{}", std::process::id(), name));
+    fs::write(&path, source).expect("failed to write script");
+    path
+}
+
+#[test]
+fn test_eval_one_liner() {
+    let output = monkey(&["-e", "let add = fn(x, y) { x + y }; add(2, 3) * 4"]);
+
+    if !output.status.success() || String::from_utf8_lossy(&output.stdout) != "20\n" {
+        panic!("monkey -e wrong. got={:?}", output);
+    }
+
+    let output = monkey(&[
+        "-e",
+        "let s = fn(n) { if (n == 0) { 0 } else { n + s(n - 1) } }; s(1000)",
+    ]);
+    if !output.status.success() || String::from_utf8_lossy(&output.stdout) != "500500\n" {
+        panic!("monkey -e with deep recursion wrong. got={:?}", output);
+    }
+}
+
+#[test]
+fn test_run_file() {
+    let path = write_script(
+        "run.mk",
+        "let x = 5;\nif (x > 3) { \"big\" } else { \"small\" }\n",
+    );
+    let output = monkey(&["run", path.to_str().unwrap()]);
+
+    if !output.status.success() || String::from_utf8_lossy(&output.stdout) != "big\n" {
+        panic!("monkey run wrong. got={:?}", output);
+    }
+
+    let path = write_script("run-error.mk", "1 + true;\n");
+    let output = monkey(&["run", path.to_str().unwrap()]);
+
+    if output.status.code() != Some(1)
+        || String::from_utf8_lossy(&output.stderr) != "ERROR: type mismatch: INTEGER + BOOLEAN\n"
+    {
+        panic!("monkey run with runtime error wrong. got={:?}", output);
+    }
+}
+
+#[test]
+fn test_tokens_and_ast() {
+    let path = write_script("ast.mk", "let x = 1 + 2 * 3;");
+    let path = path.to_str().unwrap();
+
+    let output = monkey(&["tokens", path]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success()
+        || stdout.lines().next() != Some("token_type:LET literal:let")
+        || stdout.lines().count() != 9
+    {
+        panic!("monkey tokens wrong. got={:?}", output);
+    }
+
+    let output = monkey(&["ast", path]);
+    if !output.status.success()
+        || String::from_utf8_lossy(&output.stdout) != "let x = (1 + (2 * 3));\n"
+    {
+        panic!("monkey ast wrong. got={:?}", output);
+    }
+
+    let output = monkey(&["ast", "--format", "debug", path]);
+    if !output.status.success() || !String::from_utf8_lossy(&output.stdout).contains("LetStatement")
+    {
+        panic!("monkey ast --format debug wrong. got={:?}", output);
+    }
+
+    let output = monkey(&["ast", "--format", "json", path]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match monkey_rust::serialize::from_json(&stdout) {
+        Ok(program) if output.status.success() && program.string() == "let x = (1 + (2 * 3));" => {}
+        result => panic!("monkey ast --format json wrong. got={:?}", result),
+    }
+
+    let output = monkey(&["ast", "--format", "sexp", path]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match monkey_rust::serialize::from_sexp(&stdout) {
+        Ok(program) if output.status.success() && program.string() == "let x = (1 + (2 * 3));" => {}
+        result => panic!("monkey ast --format sexp wrong. got={:?}", result),
+    }
+}
+
+#[test]
+fn test_check() {
+    let path = write_script("check-ok.mk", "let x = 1;");
+    let output = monkey(&["check", path.to_str().unwrap()]);
+    if !output.status.success() || !output.stdout.is_empty() {
+        panic!("monkey check on valid file wrong. got={:?}", output);
+    }
+
+    let path = write_script("check-bad.mk", "let x = 1;\nlet = 2;");
+    let path = path.to_str().unwrap();
+    let output = monkey(&["check", path]);
+    let expected = format!(
+        "error: expected next token to be IDENT, got ASSIGN instead
+ --> {}:2:5
+  |
+2 | let = 2;
+  |     ^ expected IDENT
+",
+        path
+    );
+    if output.status.code() != Some(1) || String::from_utf8_lossy(&output.stderr) != expected {
+        panic!("monkey check on invalid file wrong. got={:?}", output);
+    }
+}
+
+#[test]
+fn test_fmt() {
+    let path = write_script("fmt.mk", "let add=fn(x,y){x+y} // sum\nadd(1,2)");
+    let path = path.to_str().unwrap();
+
+    let output = monkey(&["fmt", "--check", path]);
+    if output.status.code() != Some(1)
+        || String::from_utf8_lossy(&output.stderr) != format!("monkey: {} is not formatted\n", path)
+    {
+        panic!(
+            "monkey fmt --check on unformatted file wrong. got={:?}",
+            output
+        );
+    }
+
+    let output = monkey(&["fmt", path]);
+    let formatted = fs::read_to_string(path).unwrap();
+    if !output.status.success() || formatted != "let add = fn(x, y) { x + y }; // sum\nadd(1, 2);\n"
+    {
+        panic!("monkey fmt wrong. got={:?}, file={:?}", output, formatted);
+    }
+
+    let output = monkey(&["fmt", "--check", path]);
+    if !output.status.success() || !output.stderr.is_empty() {
+        panic!(
+            "monkey fmt --check on formatted file wrong. got={:?}",
+            output
+        );
+    }
+
+    let path = write_script("fmt-bad.mk", "let = 1;");
+    let output = monkey(&["fmt", path.to_str().unwrap()]);
+    if output.status.code() != Some(1) || fs::read_to_string(&path).unwrap() != "let = 1;" {
+        panic!("monkey fmt on invalid file wrong. got={:?}", output);
+    }
+
+    // A file that cannot be read does not stop the files after it.
+    let missing = env::temp_dir().join(format!("monkey-cli-{}-missing.mk", std::process::id()));
+    let missing = missing.to_str().unwrap();
+    let path = write_script("fmt-after-missing.mk", "add(1,2)");
+    let output = monkey(&["fmt", missing, path.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.code() != Some(2)
+        || !stderr.starts_with(&format!("monkey: cannot read {}", missing))
+        || fs::read_to_string(&path).unwrap() != "add(1, 2);\n"
+    {
+        panic!("monkey fmt past a missing file wrong. got={:?}", output);
+    }
+}
+
+#[test]
+fn test_usage_errors() {
+    let output = monkey(&["frobnicate"]);
+    if output.status.code() != Some(2)
+        || !String::from_utf8_lossy(&output.stderr)
+            .starts_with("monkey: unknown command: frobnicate")
+    {
+        panic!("monkey with unknown command wrong. got={:?}", output);
+    }
+
+    let output = monkey(&["run", "/nonexistent/script.mk"]);
+    if output.status.code() != Some(2) {
+        panic!("monkey run on missing file wrong. got={:?}", output);
+    }
+}
@@ -0,0 +1,126 @@
+use monkey_rust::{
+    ast::{Expression, Node, Statement},
+    parse, parse_with_recovery,
+    token::TokenType::{ASSIGN, EOF, IDENT, INT, LET, SEMICOLON},
+    LexError, Lexer, ParseError,
+};
+
+#[test]
+fn test_parse_program() {
+    let input = "let x = 5;\nlet y = x * -2;\nreturn x + y;";
+
+    let program = match parse(input) {
+        Ok(program) => program,
+        Err(errors) => panic!("parse returned errors: {:?}", errors),
+    };
+
+    if program.statements.len() != 3 {
+        panic!(
+            "program.statements does not contain 3 statements. got={}",
+            program.statements.len()
+        );
+    }
+
+    let expected = "let x = 5;let y = (x * (-2));return (x + y);";
+    if program.string() != expected {
+        panic!(
+            "program.string() wrong. expected={}, got={}",
+            expected,
+            program.string()
+        );
+    }
+
+    match &program.statements[1] {
+        Statement::LetStatement(stmt) => {
+            if stmt.name.value != "y" {
+                panic!("stmt.name.value not 'y'. got={}", stmt.name.value);
+            }
+            if !matches!(stmt.value, Some(Expression::Infix(_))) {
+                panic!("stmt.value not Expression::Infix. got={:?}", stmt.value);
+            }
+            if stmt.span().start.line != 2 {
+                panic!("stmt.span() not on line 2. got={:?}", stmt.span());
+            }
+        }
+        stmt => panic!("program.statements[1] not LetStatement. got={:?}", stmt),
+    }
+}
+
+#[test]
+fn test_parse_errors() {
+    let errors = match parse("let = 5;\nlet s = \"unterminated") {
+        Ok(program) => panic!("expected parse errors. got={:?}", program),
+        Err(errors) => errors,
+    };
+
+    match &errors[0] {
+        ParseError::UnexpectedToken { expected, found } => {
+            if *expected != IDENT || found.token_type != ASSIGN {
+                panic!("errors[0] wrong. got={:?}", errors[0]);
+            }
+        }
+        err => panic!("errors[0] not UnexpectedToken. got={:?}", err),
+    }
+
+    if !errors
+        .iter()
+        .any(|err| matches!(err, ParseError::Lex(LexError::UnterminatedString { .. })))
+    {
+        panic!("expected an UnterminatedString error. got={:?}", errors);
+    }
+
+    for err in &errors {
+        let _: &dyn std::error::Error = err;
+        if err.to_string().is_empty() {
+            panic!("error has an empty message: {:?}", err);
+        }
+    }
+}
+
+#[test]
+fn test_parse_with_recovery() {
+    let input = "let a = 1;\nlet = 2;\n) ) )\nlet b = a + ;\nlet c = fn(x) { x * 2 };";
+
+    let (program, errors) = parse_with_recovery(input);
+
+    if errors.len() != 3 {
+        panic!("expected 3 errors. got={:?}", errors);
+    }
+
+    let names: Vec<&str> = program
+        .statements
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Statement::LetStatement(let_stmt) => Some(let_stmt.name.value.as_str()),
+            _ => None,
+        })
+        .collect();
+    if names != ["a", "b", "c"] {
+        panic!("wrong let bindings recovered. got={:?}", names);
+    }
+}
+
+#[test]
+fn test_lexer() {
+    let mut l = Lexer::new("let five = 5;");
+
+    let tests = [
+        (LET, "let"),
+        (IDENT, "five"),
+        (ASSIGN, "="),
+        (INT, "5"),
+        (SEMICOLON, ";"),
+        (EOF, ""),
+    ];
+
+    for (i, tt) in tests.into_iter().enumerate() {
+        let tok = l.next_token();
+
+        if tok.token_type != tt.0 || tok.literal != tt.1 {
+            panic!(
+                "tests[{}] - token wrong. expected={:?}, got={:?}",
+                i, tt, tok
+            );
+        }
+    }
+}
@@ -0,0 +1,216 @@
+#!/usr/bin/env python3
+"""Generates src/unicode/tables.rs from the Unicode Character Database.
+
+Usage:
+
+    python3 tools/gen_unicode_tables.py [UCD_DIR]
+
+UCD_DIR must contain UnicodeData.txt, DerivedCoreProperties.txt,
+DerivedNormalizationProps.txt, Scripts.txt and EastAsianWidth.txt from
+https://www.unicode.org/Public/14.0.0/ucd/. Without UCD_DIR the files are
+downloaded from there. The tables are written to src/unicode/tables.rs
+relative to the repository root.
+
+To move to a newer Unicode version, change UNICODE_VERSION, rerun the script
+and run `cargo test`.
+"""
+
+import os
+import sys
+import urllib.request
+
+UNICODE_VERSION = "14.0.0"
+UCD_URL = "https://www.unicode.org/Public/%s/ucd/" % UNICODE_VERSION
+
+OUTPUT = os.path.join(os.path.dirname(__file__), "..", "src", "unicode", "tables.rs")
+
+# Precomposed Hangul syllables are decomposed and composed algorithmically
+# by src/unicode.rs, so they are left out of the tables.
+S_BASE = 0xAC00
+S_COUNT = 11172
+
+# Scripts that are shared between scripts and so never make an identifier
+# mixed-script.
+SHARED_SCRIPTS = {"Common", "Inherited"}
+
+
+def read_ucd(ucd_dir, name):
+    if ucd_dir is None:
+        with urllib.request.urlopen(UCD_URL + name) as response:
+            return response.read().decode("utf-8")
+    with open(os.path.join(ucd_dir, name), encoding="utf-8") as f:
+        return f.read()
+
+
+def records(text):
+    """Yields the fields of each line of a UCD file, without comments."""
+    for line in text.splitlines():
+        line = line.split("#", 1)[0].strip()
+        if line:
+            yield [field.strip() for field in line.split(";")]
+
+
+def code_points(field):
+    """Returns the code points of a `XXXX` or `XXXX..YYYY` field."""
+    lo, _, hi = field.partition("..")
+    return range(int(lo, 16), int(hi or lo, 16) + 1)
+
+
+def ranges(chars):
+    """Collapses a set of code points into sorted (lo, hi) ranges."""
+    out = []
+    for c in sorted(chars):
+        if out and out[-1][1] == c - 1:
+            out[-1][1] = c
+        else:
+            out.append([c, c])
+    return [tuple(r) for r in out]
+
+
+def valued_ranges(values):
+    """Collapses a {code point: value} map into sorted (lo, hi, value)
+    ranges of consecutive code points with the same value."""
+    out = []
+    for c in sorted(values):
+        if out and out[-1][1] == c - 1 and out[-1][2] == values[c]:
+            out[-1][1] = c
+        else:
+            out.append([c, c, values[c]])
+    return [tuple(r) for r in out]
+
+
+def unicode_data(text):
+    """Returns the assigned code points, their non-zero canonical combining
+    classes and their canonical decomposition mappings."""
+    assigned = set()
+    combining_class = {}
+    mapping = {}
+    first = None
+    for fields in records(text):
+        c, name, ccc, decomposition = int(fields[0], 16), fields[1], int(fields[3]), fields[5]
+        if name.endswith(", First>"):
+            first = c
+            continue
+        block = range(first, c + 1) if name.endswith(", Last>") else [c]
+        first = None
+        assigned.update(block)
+        if ccc:
+            combining_class.update(dict.fromkeys(block, ccc))
+        if decomposition and not decomposition.startswith("<"):
+            mapping[c] = [int(part, 16) for part in decomposition.split()]
+    return assigned, combining_class, mapping
+
+
+def full_decomposition(c, mapping):
+    if c not in mapping:
+        return [c]
+    return [d for part in mapping[c] for d in full_decomposition(part, mapping)]
+
+
+def binary_property(text, name):
+    return {c for fields in records(text) if fields[1] == name for c in code_points(fields[0])}
+
+
+def u(c):
+    return "'\\u{%X}'" % c
+
+
+def table(name, ty, items, per_line):
+    lines = ["    " + " ".join(items[i : i + per_line]) for i in range(0, len(items), per_line)]
+    return "#[rustfmt::skip]\npub const %s: %s = &[\n%s\n];\n" % (name, ty, "\n".join(lines))
+
+
+def main():
+    ucd_dir = sys.argv[1] if len(sys.argv) > 1 else None
+
+    assigned, combining_class, mapping = unicode_data(read_ucd(ucd_dir, "UnicodeData.txt"))
+
+    core = read_ucd(ucd_dir, "DerivedCoreProperties.txt")
+    xid_start = binary_property(core, "XID_Start")
+    xid_continue = binary_property(core, "XID_Continue")
+
+    # Only the scripts of identifier characters matter to the mixed-script
+    # check, which keeps the table small.
+    scripts = {
+        c: fields[1]
+        for fields in records(read_ucd(ucd_dir, "Scripts.txt"))
+        if fields[1] not in SHARED_SCRIPTS
+        for c in code_points(fields[0])
+        if c in xid_continue
+    }
+
+    exclusions = binary_property(
+        read_ucd(ucd_dir, "DerivedNormalizationProps.txt"), "Full_Composition_Exclusion"
+    )
+
+    wide = {
+        c
+        for fields in records(read_ucd(ucd_dir, "EastAsianWidth.txt"))
+        if fields[1] in ("W", "F")
+        for c in code_points(fields[0])
+        if c in assigned
+    }
+
+    decomposable = [c for c in sorted(mapping) if not S_BASE <= c < S_BASE + S_COUNT]
+    decompositions = [(c, full_decomposition(c, mapping)) for c in decomposable]
+    compositions = sorted(
+        (tuple(mapping[c]), c)
+        for c in decomposable
+        if len(mapping[c]) == 2 and c not in exclusions
+    )
+
+    out = [
+        "//! Unicode property tables used by the lexer and diagnostics.\n"
+        "//!\n"
+        "//! Generated by tools/gen_unicode_tables.py from the Unicode %s character\n"
+        "//! database. Do not edit by hand.\n" % UNICODE_VERSION,
+        table(
+            "XID_START",
+            "&[(char, char)]",
+            ["(%s, %s)," % (u(lo), u(hi)) for lo, hi in ranges(xid_start)],
+            4,
+        ),
+        table(
+            "XID_CONTINUE",
+            "&[(char, char)]",
+            ["(%s, %s)," % (u(lo), u(hi)) for lo, hi in ranges(xid_continue)],
+            4,
+        ),
+        table(
+            "CANONICAL_COMBINING_CLASS",
+            "&[(char, char, u8)]",
+            ["(%s, %s, %d)," % (u(lo), u(hi), ccc) for lo, hi, ccc in valued_ranges(combining_class)],
+            3,
+        ),
+        table(
+            "CANONICAL_DECOMPOSITION",
+            "&[(char, &[char])]",
+            ["(%s, &[%s])," % (u(c), ", ".join(map(u, d))) for c, d in decompositions],
+            2,
+        ),
+        table(
+            "CANONICAL_COMPOSITION",
+            "&[((char, char), char)]",
+            ["((%s, %s), %s)," % (u(a), u(b), u(c)) for (a, b), c in compositions],
+            2,
+        ),
+        table(
+            "SCRIPTS",
+            "&[(char, char, &str)]",
+            ['(%s, %s, "%s"),' % (u(lo), u(hi), script) for lo, hi, script in valued_ranges(scripts)],
+            3,
+        ),
+        table(
+            "EAST_ASIAN_WIDE",
+            "&[(char, char)]",
+            ["(%s, %s)," % (u(lo), u(hi)) for lo, hi in ranges(wide)],
+            4,
+        ),
+    ]
+
+    with open(OUTPUT, "w", encoding="utf-8") as f:
+        f.write("\n".join(out))
+
+
+if __name__ == "__main__":
+    main()